@@ -1,4 +1,8 @@
 fn main() {
+    // migrations are embedded into the server binary
+    println!("cargo:rerun-if-changed=migrations");
+    println!("cargo:rerun-if-changed=orwell.proto");
+
     prost_build::Config::new()
         .out_dir("src/pb")
         .compile_protos(&["orwell.proto"], &["."])
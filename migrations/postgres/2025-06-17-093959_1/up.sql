@@ -0,0 +1,3 @@
+-- Your SQL goes here
+-- postgres rejects an empty migration, the sqlite one is a no-op too
+SELECT 1;
@@ -0,0 +1,4 @@
+-- This file should undo anything in `up.sql`
+DROP TABLE IF EXISTS messages_;
+DROP TABLE IF EXISTS keys_;
+DROP TABLE IF EXISTS clients_;
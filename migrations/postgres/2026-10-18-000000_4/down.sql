@@ -0,0 +1,4 @@
+-- This file should undo anything in `up.sql`
+DROP INDEX IF EXISTS message_keys_receiver_id_idx;
+DROP INDEX IF EXISTS message_keys_msg_id_idx;
+DROP INDEX IF EXISTS messages_timestamp_idx;
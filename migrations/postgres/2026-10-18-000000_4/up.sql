@@ -0,0 +1,4 @@
+-- Your SQL goes here
+CREATE INDEX IF NOT EXISTS message_keys_receiver_id_idx ON message_keys_(receiver_id_);
+CREATE INDEX IF NOT EXISTS message_keys_msg_id_idx ON message_keys_(msg_id_);
+CREATE INDEX IF NOT EXISTS messages_timestamp_idx ON messages_(timestamp_);
@@ -0,0 +1,5 @@
+-- Your SQL goes here
+ALTER TABLE messages_ ADD COLUMN channel_ TEXT NOT NULL DEFAULT 'main';
+ALTER TABLE messages_ ADD COLUMN expires_at_ BIGINT;
+CREATE INDEX IF NOT EXISTS messages_channel_idx ON messages_(channel_);
+CREATE INDEX IF NOT EXISTS messages_expires_at_idx ON messages_(expires_at_);
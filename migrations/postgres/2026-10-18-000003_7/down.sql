@@ -0,0 +1,3 @@
+-- This file should undo anything in `up.sql`
+DROP INDEX IF EXISTS message_keys_pending_idx;
+ALTER TABLE message_keys_ DROP COLUMN pending_;
@@ -0,0 +1,4 @@
+-- This file should undo anything in `up.sql`
+DROP INDEX IF EXISTS messages_target_id_idx;
+ALTER TABLE messages_ DROP COLUMN target_id_;
+ALTER TABLE messages_ DROP COLUMN action_;
@@ -0,0 +1,3 @@
+-- Your SQL goes here
+DROP INDEX IF EXISTS messages_channel_idx;
+ALTER TABLE messages_ DROP COLUMN channel_;
@@ -0,0 +1,5 @@
+-- This file should undo anything in `up.sql`
+DROP INDEX IF EXISTS `messages_expires_at_idx`;
+DROP INDEX IF EXISTS `messages_channel_idx`;
+ALTER TABLE `messages_` DROP COLUMN `expires_at_`;
+ALTER TABLE `messages_` DROP COLUMN `channel_`;
@@ -0,0 +1,9 @@
+-- Your SQL goes here
+
+CREATE TABLE `message_receipts_`(
+	`msg_id_` TEXT NOT NULL,
+	`receiver_id_` TEXT NOT NULL,
+	`delivered_at_` BIGINT NOT NULL,
+	`read_at_` BIGINT,
+	PRIMARY KEY (`msg_id_`, `receiver_id_`)
+);
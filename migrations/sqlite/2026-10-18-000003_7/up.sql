@@ -0,0 +1,3 @@
+-- Your SQL goes here
+ALTER TABLE `message_keys_` ADD COLUMN `pending_` BOOLEAN NOT NULL DEFAULT FALSE;
+CREATE INDEX IF NOT EXISTS `message_keys_pending_idx` ON `message_keys_`(`receiver_id_`, `pending_`);
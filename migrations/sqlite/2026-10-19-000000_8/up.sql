@@ -0,0 +1,4 @@
+-- Your SQL goes here
+ALTER TABLE `messages_` ADD COLUMN `action_` INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE `messages_` ADD COLUMN `target_id_` TEXT;
+CREATE INDEX IF NOT EXISTS `messages_target_id_idx` ON `messages_`(`target_id_`);
@@ -0,0 +1,3 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE `messages_` ADD COLUMN `channel_` TEXT NOT NULL DEFAULT 'main';
+CREATE INDEX IF NOT EXISTS `messages_channel_idx` ON `messages_`(`channel_`);
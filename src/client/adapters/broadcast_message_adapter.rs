@@ -19,7 +19,7 @@ impl ClientPacketAdapter for BroadcastMessageAdapter {
 
     fn process(&self, packet: OrwellPacket, _context: ClientPacketContext<'_>) -> Result<()> {
         let packet: ServerBroadcastMessage = decode_packet!(packet, ServerBroadcastMessage);
-        Service::handle_message(&packet, false)?;
+        Service::handle_message(&packet, false, false)?;
         Ok(())
     }
 }
@@ -27,6 +27,7 @@ impl ClientPacketAdapter for ClientInfoAdapter {
                 name: client.name.clone(),
                 color: client.color as i32,
                 kyber_pk: client.kyber_pk,
+                dilithium_pk: client.dilithium_pk,
                 status: ClientStatus::try_from(client.status).unwrap(),
             });
         }
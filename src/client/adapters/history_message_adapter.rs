@@ -6,8 +6,10 @@ use orwell::{
 use prost::Message;
 
 use crate::{
+    message::{add_chat_message_rich, LineBuilder},
     packet_adapter::{ClientPacketAdapter, ClientPacketContext},
     service::Service,
+    theme::THEME,
 };
 
 pub struct HistoryMessageAdapter;
@@ -19,8 +21,20 @@ impl ClientPacketAdapter for HistoryMessageAdapter {
 
     fn process(&self, packet: OrwellPacket, _context: ClientPacketContext<'_>) -> Result<()> {
         let packet = decode_packet!(packet, ServerHistoryMessage);
+        // the first batch of the offline backlog opens it with a divider
+        if packet.offline && packet.missed > 0 {
+            add_chat_message_rich(
+                LineBuilder::new()
+                    .styled(
+                        format!("──── 离线期间错过 {} 条消息 ────", packet.missed),
+                        THEME.read().unwrap().divider_style(),
+                    )
+                    .build(),
+                None,
+            );
+        }
         for message in packet.data {
-            Service::handle_message(&message, true)?;
+            Service::handle_message(&message, true, packet.offline)?;
         }
         Ok(())
     }
@@ -9,6 +9,7 @@ use prost::Message;
 use crate::{
     message::add_chat_message,
     packet_adapter::{ClientPacketAdapter, ClientPacketContext},
+    service::Service,
     STATE,
 };
 
@@ -27,7 +28,10 @@ impl ClientPacketAdapter for LoginResponseAdapter {
             let mut state: std::sync::RwLockWriteGuard<'_, crate::State> = STATE.write().unwrap();
             state.connected = true;
             state.start_time = get_now_timestamp();
+            let server_url = state.server_url.clone();
             drop(state);
+            // the server sends history right after this response
+            Service::load_cached_messages(&server_url);
         } else {
             add_chat_message(format!("登录失败, 原因: {}", packet.message));
         }
@@ -6,14 +6,18 @@ pub mod history_message_adapter;
 pub mod login_response_adapter;
 pub mod pre_login_adapter;
 pub mod ratchet_step_adapter;
+pub mod receipt_adapter;
 pub mod register_response_adapter;
+pub mod server_error_adapter;
+pub mod typing_adapter;
 
 use crate::adapters::{
     broadcast_message_adapter::BroadcastMessageAdapter, client_info_adapter::ClientInfoAdapter,
     color_response_adapter::ColorResponseAdapter, heartbeat_adapter::HeartbeatAdapter,
     history_message_adapter::HistoryMessageAdapter, login_response_adapter::LoginResponseAdapter,
     pre_login_adapter::PreLoginAdapter, ratchet_step_adapter::RatchetStepAdapter,
-    register_response_adapter::RegisterResponseAdapter,
+    receipt_adapter::ReceiptAdapter, register_response_adapter::RegisterResponseAdapter,
+    server_error_adapter::ServerErrorAdapter, typing_adapter::TypingAdapter,
 };
 use crate::packet_adapter::ClientPacketAdapterRegistry;
 
@@ -30,6 +34,9 @@ pub fn create_client_registry() -> ClientPacketAdapterRegistry {
     registry.register(Box::new(HistoryMessageAdapter));
     registry.register(Box::new(ColorResponseAdapter));
     registry.register(Box::new(RatchetStepAdapter));
+    registry.register(Box::new(ServerErrorAdapter));
+    registry.register(Box::new(TypingAdapter));
+    registry.register(Box::new(ReceiptAdapter));
 
     registry
 }
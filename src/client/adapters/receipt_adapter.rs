@@ -0,0 +1,25 @@
+use anyhow::Result;
+use orwell::{
+    decode_packet,
+    pb::orwell::{OrwellPacket, PacketType, ServerReceipt},
+};
+use prost::Message;
+
+use crate::{
+    message::{update_chat_message_receipt, Receipt},
+    packet_adapter::{ClientPacketAdapter, ClientPacketContext},
+};
+
+pub struct ReceiptAdapter;
+
+impl ClientPacketAdapter for ReceiptAdapter {
+    fn packet_type(&self) -> PacketType {
+        PacketType::ServerReceipt
+    }
+
+    fn process(&self, packet: OrwellPacket, _context: ClientPacketContext<'_>) -> Result<()> {
+        let packet = decode_packet!(packet, ServerReceipt);
+        update_chat_message_receipt(&packet.message_id, Receipt::from(&packet));
+        Ok(())
+    }
+}
@@ -0,0 +1,26 @@
+use anyhow::Result;
+use orwell::{
+    decode_packet,
+    pb::orwell::{OrwellPacket, PacketType, ServerError},
+};
+use prost::Message;
+
+use crate::{
+    message::{add_chat_message, add_debug_message, MessageLevel},
+    packet_adapter::{ClientPacketAdapter, ClientPacketContext},
+};
+
+pub struct ServerErrorAdapter;
+
+impl ClientPacketAdapter for ServerErrorAdapter {
+    fn packet_type(&self) -> PacketType {
+        PacketType::ServerError
+    }
+
+    fn process(&self, packet: OrwellPacket, _context: ClientPacketContext<'_>) -> Result<()> {
+        let packet = decode_packet!(packet, ServerError);
+        add_debug_message(MessageLevel::Error, format!("服务器错误: {}", packet.error));
+        add_chat_message(format!("服务器错误: {}", packet.error));
+        Ok(())
+    }
+}
@@ -0,0 +1,30 @@
+use anyhow::Result;
+use orwell::{
+    decode_packet,
+    pb::orwell::{OrwellPacket, PacketType, ServerBroadcastTyping},
+};
+use prost::Message;
+
+use crate::{
+    packet_adapter::{ClientPacketAdapter, ClientPacketContext},
+    service::TypingManager,
+};
+
+pub struct TypingAdapter;
+
+impl ClientPacketAdapter for TypingAdapter {
+    fn packet_type(&self) -> PacketType {
+        PacketType::ServerBroadcastTyping
+    }
+
+    fn process(&self, packet: OrwellPacket, _context: ClientPacketContext<'_>) -> Result<()> {
+        let packet = decode_packet!(packet, ServerBroadcastTyping);
+        TypingManager::update(
+            packet.sender_id,
+            packet.sender_name,
+            packet.color,
+            packet.typing,
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,491 @@
+use std::{
+    collections::HashSet,
+    fs,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use anyhow::{anyhow, Result};
+use lazy_static::lazy_static;
+use orwell::{
+    pb::orwell::{
+        ExpiringMessage, MessageCache, MessageDelete, MessageEdit, MessageType, ReactionMessage,
+        ServerBroadcastMessage,
+    },
+    shared::{encryption::Encryption, helper::get_now_timestamp},
+};
+use prost::Message;
+use sha2::{Digest, Sha256};
+
+use crate::{
+    config::{get_cache_enabled, get_cache_limits},
+    key::KEY_MANAGER,
+    message::{add_debug_message, MessageLevel},
+};
+
+const CACHE_FOLDER: &str = "./cache";
+/// Writes are batched, a burst of messages saves the file once
+const FLUSH_INTERVAL: Duration = Duration::from_secs(3);
+
+lazy_static! {
+    static ref CACHE: Mutex<Option<CacheFile>> = Mutex::new(None);
+}
+
+/// The open cache of the server we are logged in to
+struct CacheFile {
+    path: String,
+    key: Vec<u8>,
+    server_url: String,
+    /// Oldest first
+    messages: Vec<ServerBroadcastMessage>,
+    ids: HashSet<String>,
+    /// Deleted this session, never cached even if they show up again
+    deleted: HashSet<String>,
+    dirty: bool,
+    last_flush: Instant,
+}
+
+impl CacheFile {
+    fn new(path: String, key: Vec<u8>, server_url: String) -> Self {
+        Self {
+            path,
+            key,
+            server_url,
+            messages: Vec::new(),
+            ids: HashSet::new(),
+            deleted: HashSet::new(),
+            dirty: false,
+            last_flush: Instant::now(),
+        }
+    }
+
+    fn load(&mut self) -> Result<()> {
+        if !fs::exists(&self.path)? {
+            return Ok(());
+        }
+        let data = Encryption::aes_decrypt(&fs::read(&self.path)?, &self.key)?;
+        let cache = MessageCache::decode(data.as_slice())?;
+        if cache.server_url != self.server_url {
+            return Err(anyhow!("缓存属于另一个服务器"));
+        }
+        for message in cache.messages {
+            self.insert(message, get_now_timestamp());
+        }
+        self.dirty = false;
+        Ok(())
+    }
+
+    fn save(&mut self) -> Result<()> {
+        let (max_messages, max_bytes) = get_cache_limits();
+        self.prune(get_now_timestamp(), max_messages, max_bytes);
+        let cache = MessageCache {
+            server_url: self.server_url.clone(),
+            messages: self.messages.clone(),
+        };
+        let data = Encryption::aes_encrypt(&cache.encode_to_vec(), &self.key);
+        if let Some(parent) = std::path::Path::new(&self.path).parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(&self.path, data)?;
+        self.dirty = false;
+        self.last_flush = Instant::now();
+        Ok(())
+    }
+
+    /// Add a message keeping the timestamp order, deletes take their target
+    /// out along with its edits and reactions
+    fn insert(&mut self, message: ServerBroadcastMessage, now: u64) -> bool {
+        if message.id.is_empty()
+            || self.ids.contains(&message.id)
+            || self.deleted.contains(&message.id)
+        {
+            return false;
+        }
+        let payload = Payload::parse(&message);
+        if payload
+            .expires_at
+            .is_some_and(|expires_at| expires_at <= now)
+        {
+            return false;
+        }
+        if let Some(target) = payload.delete_target {
+            // only the sender may delete, the same check the delete adapter makes
+            let owned = self
+                .messages
+                .iter()
+                .position(|cached| cached.id == target && cached.sender_id == message.sender_id);
+            if let Some(index) = owned {
+                self.messages.remove(index);
+                self.messages
+                    .retain(|cached| Payload::parse(cached).target.as_ref() != Some(&target));
+                self.ids = self
+                    .messages
+                    .iter()
+                    .map(|message| message.id.clone())
+                    .collect();
+                self.dirty = true;
+            }
+            self.deleted.insert(target);
+            return false;
+        }
+        if payload
+            .target
+            .is_some_and(|target| self.deleted.contains(&target))
+        {
+            return false;
+        }
+
+        let index = self
+            .messages
+            .partition_point(|cached| cached.timestamp <= message.timestamp);
+        self.ids.insert(message.id.clone());
+        self.messages.insert(index, message);
+        self.dirty = true;
+        true
+    }
+
+    /// Drop expired messages, then the oldest until both limits hold
+    fn prune(&mut self, now: u64, max_messages: usize, max_bytes: usize) {
+        let before = self.messages.len();
+        self.messages.retain(|message| {
+            Payload::parse(message)
+                .expires_at
+                .is_none_or(|expires_at| expires_at > now)
+        });
+
+        // count back from the newest until a limit is reached
+        let mut bytes = 0;
+        let mut keep = 0;
+        for message in self.messages.iter().rev() {
+            bytes += message.encoded_len();
+            if keep == max_messages || bytes > max_bytes {
+                break;
+            }
+            keep += 1;
+        }
+        let excess = self.messages.len() - keep;
+        self.messages.drain(..excess);
+
+        if self.messages.len() != before {
+            self.ids = self
+                .messages
+                .iter()
+                .map(|message| message.id.clone())
+                .collect();
+            self.dirty = true;
+        }
+    }
+}
+
+/// What the cache needs to know about a decrypted payload
+#[derive(Default)]
+struct Payload {
+    expires_at: Option<u64>,
+    delete_target: Option<String>,
+    /// Message an edit or reaction applies to
+    target: Option<String>,
+}
+
+impl Payload {
+    fn parse(message: &ServerBroadcastMessage) -> Self {
+        Self::parse_data(&message.data, message.timestamp)
+    }
+
+    fn parse_data(data: &[u8], timestamp: u64) -> Self {
+        let Some((&message_type, rest)) = data.split_first() else {
+            return Self::default();
+        };
+        match MessageType::try_from(message_type as i32) {
+            Ok(MessageType::Expiring) => {
+                let Ok(expiring) = ExpiringMessage::decode(rest) else {
+                    return Self::default();
+                };
+                Self {
+                    expires_at: Some(timestamp.saturating_add(expiring.timer.saturating_mul(1000))),
+                    // expiring messages do not nest
+                    ..Self::parse_data(&expiring.data, timestamp)
+                }
+            }
+            Ok(MessageType::Delete) => Self {
+                delete_target: MessageDelete::decode(rest)
+                    .ok()
+                    .map(|delete| delete.target_id),
+                ..Self::default()
+            },
+            Ok(MessageType::Edit) => Self {
+                target: MessageEdit::decode(rest).ok().map(|edit| edit.target_id),
+                ..Self::default()
+            },
+            Ok(MessageType::Reaction) => Self {
+                target: ReactionMessage::decode(rest)
+                    .ok()
+                    .map(|reaction| reaction.target_id),
+                ..Self::default()
+            },
+            _ => Self::default(),
+        }
+    }
+}
+
+/// Encrypted per server store of the messages we received, so a restart
+/// does not depend on the server's short history
+pub struct CacheManager;
+
+impl CacheManager {
+    fn cache_path(profile: &str, server_url: &str) -> String {
+        let hash = Sha256::digest(server_url.as_bytes());
+        let name = hash[..16]
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect::<String>();
+        format!("{}/{}/{}.cache", CACHE_FOLDER, profile, name)
+    }
+
+    /// Open the cache of `server_url` for the loaded profile and return its
+    /// messages newest first, the order history is shown in
+    pub fn open(server_url: &str) -> Result<Vec<ServerBroadcastMessage>> {
+        let mut cache = CACHE.lock().unwrap();
+        if let Some(previous) = cache.as_mut().filter(|previous| previous.dirty) {
+            previous.save()?;
+        }
+        *cache = None;
+        if !get_cache_enabled() {
+            return Ok(Vec::new());
+        }
+
+        let key_manager = KEY_MANAGER.read().unwrap();
+        let manager = key_manager
+            .as_ref()
+            .ok_or_else(|| anyhow!("尚未加载身份"))?;
+        let profile = manager
+            .profile
+            .as_ref()
+            .ok_or_else(|| anyhow!("尚未加载身份"))?;
+        let key = manager
+            .cache_key
+            .clone()
+            .ok_or_else(|| anyhow!("尚未加载身份"))?;
+        let path = Self::cache_path(&profile.name, server_url);
+        drop(key_manager);
+
+        let mut file = CacheFile::new(path, key, server_url.to_string());
+        file.load()?;
+        let messages = file.messages.iter().rev().cloned().collect();
+        cache.replace(file);
+        Ok(messages)
+    }
+
+    pub fn contains(id: &str) -> bool {
+        CACHE
+            .lock()
+            .unwrap()
+            .as_ref()
+            .is_some_and(|cache| cache.ids.contains(id))
+    }
+
+    /// Remember a received message together with its decrypted payload
+    pub fn store(message: &ServerBroadcastMessage, data: &[u8]) {
+        let mut cache = CACHE.lock().unwrap();
+        let Some(cache) = cache.as_mut() else {
+            return;
+        };
+        let message = ServerBroadcastMessage {
+            key: None,
+            data: data.to_vec(),
+            ..message.clone()
+        };
+        cache.insert(message, get_now_timestamp());
+    }
+
+    /// Write pending changes, at most once per `FLUSH_INTERVAL`
+    pub fn flush() {
+        let mut cache = CACHE.lock().unwrap();
+        let Some(cache) = cache.as_mut() else {
+            return;
+        };
+        if cache.dirty && cache.last_flush.elapsed() >= FLUSH_INTERVAL {
+            if let Err(e) = cache.save() {
+                add_debug_message(MessageLevel::Error, format!("保存本地缓存失败: {}", e));
+            }
+        }
+    }
+
+    /// Write pending changes now, used when quitting
+    pub fn save() {
+        let mut cache = CACHE.lock().unwrap();
+        if let Some(cache) = cache.as_mut().filter(|cache| cache.dirty) {
+            let _ = cache.save();
+        }
+    }
+
+    /// Forget every cached message of the loaded profile, on disk and in
+    /// memory, and return how many cache files were removed
+    pub fn wipe() -> Result<usize> {
+        let mut cache = CACHE.lock().unwrap();
+        if let Some(cache) = cache.as_mut() {
+            cache.messages.clear();
+            cache.ids.clear();
+            cache.dirty = false;
+        }
+
+        let key_manager = KEY_MANAGER.read().unwrap();
+        let profile = key_manager
+            .as_ref()
+            .and_then(|manager| manager.profile.as_ref())
+            .ok_or_else(|| anyhow!("尚未加载身份"))?;
+        let folder = format!("{}/{}", CACHE_FOLDER, profile.name);
+        drop(key_manager);
+
+        let Ok(entries) = fs::read_dir(&folder) else {
+            return Ok(0);
+        };
+        let mut removed = 0;
+        for entry in entries {
+            fs::remove_file(entry?.path())?;
+            removed += 1;
+        }
+        fs::remove_dir(&folder)?;
+        Ok(removed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn message(id: &str, sender: &str, timestamp: u64, data: Vec<u8>) -> ServerBroadcastMessage {
+        ServerBroadcastMessage {
+            id: id.to_string(),
+            sender_id: sender.to_string(),
+            timestamp,
+            data,
+            ..Default::default()
+        }
+    }
+
+    fn text(text: &str) -> Vec<u8> {
+        let mut data = text.as_bytes().to_vec();
+        data.insert(0, MessageType::Text as u8);
+        data
+    }
+
+    fn delete(target: &str) -> Vec<u8> {
+        let mut data = MessageDelete {
+            target_id: target.to_string(),
+            sign: vec![],
+        }
+        .encode_to_vec();
+        data.insert(0, MessageType::Delete as u8);
+        data
+    }
+
+    fn edit(target: &str, text: &str) -> Vec<u8> {
+        let mut data = MessageEdit {
+            target_id: target.to_string(),
+            text: text.to_string(),
+            ..Default::default()
+        }
+        .encode_to_vec();
+        data.insert(0, MessageType::Edit as u8);
+        data
+    }
+
+    fn reaction(target: &str) -> Vec<u8> {
+        let mut data = ReactionMessage {
+            target_id: target.to_string(),
+            emoji: "+1".to_string(),
+            remove: false,
+        }
+        .encode_to_vec();
+        data.insert(0, MessageType::Reaction as u8);
+        data
+    }
+
+    fn expiring(timer: u64, inner: Vec<u8>) -> Vec<u8> {
+        let mut data = ExpiringMessage { timer, data: inner }.encode_to_vec();
+        data.insert(0, MessageType::Expiring as u8);
+        data
+    }
+
+    fn ids(cache: &CacheFile) -> Vec<&str> {
+        cache
+            .messages
+            .iter()
+            .map(|message| message.id.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn insert_orders_dedups_and_deletes() {
+        let mut cache = CacheFile::new(String::new(), vec![0; 32], "ws://x".to_string());
+        assert!(cache.insert(message("b", "alice", 2_000, text("two")), 0));
+        assert!(cache.insert(message("a", "alice", 1_000, text("one")), 0));
+        assert!(!cache.insert(message("a", "alice", 1_000, text("one")), 0));
+        assert!(cache.insert(message("c", "bob", 3_000, text("three")), 0));
+        assert_eq!(ids(&cache), ["a", "b", "c"]);
+
+        // someone else cannot delete alice's message
+        assert!(!cache.insert(message("d", "bob", 4_000, delete("b")), 0));
+        assert_eq!(ids(&cache), ["a", "b", "c"]);
+        assert!(!cache.insert(message("e", "alice", 4_000, expiring(60, delete("b"))), 0));
+        assert_eq!(ids(&cache), ["a", "c"]);
+        assert!(!cache.insert(message("b", "alice", 2_000, text("two")), 0));
+
+        // already expired disappearing messages are never stored
+        assert!(!cache.insert(message("f", "bob", 1_000, expiring(1, text("gone"))), 5_000));
+        assert!(cache.insert(
+            message("g", "bob", 5_000, expiring(10, text("soon"))),
+            5_000
+        ));
+        assert_eq!(ids(&cache), ["a", "c", "g"]);
+
+        // edits and reactions go with the message they target
+        assert!(cache.insert(message("h", "bob", 6_000, edit("c", "three!")), 0));
+        assert!(cache.insert(message("i", "alice", 6_000, reaction("c")), 0));
+        assert!(cache.insert(message("j", "alice", 6_000, reaction("a")), 0));
+        assert!(!cache.insert(message("k", "bob", 7_000, delete("c")), 0));
+        assert_eq!(ids(&cache), ["a", "g", "j"]);
+        assert!(!cache.insert(message("l", "bob", 8_000, edit("c", "late")), 0));
+    }
+
+    #[test]
+    fn prune_keeps_the_newest_within_limits() {
+        let mut cache = CacheFile::new(String::new(), vec![0; 32], "ws://x".to_string());
+        for i in 0..10u64 {
+            cache.insert(
+                message(&i.to_string(), "alice", i * 1_000, text("hello")),
+                0,
+            );
+        }
+        cache.insert(message("x", "alice", 500, expiring(1, text("brief"))), 0);
+
+        cache.prune(2_000, 6, usize::MAX);
+        assert_eq!(ids(&cache), ["4", "5", "6", "7", "8", "9"]);
+        assert!(!cache.ids.contains("x") && !cache.ids.contains("3"));
+
+        let size = cache.messages[0].encoded_len();
+        cache.prune(2_000, 100, size * 2);
+        assert_eq!(ids(&cache), ["8", "9"]);
+    }
+
+    #[test]
+    fn saved_cache_loads_back() {
+        let dir = std::env::temp_dir().join(format!("orwell-cache-{}", std::process::id()));
+        let path = dir.join("test.cache").to_string_lossy().to_string();
+        let mut cache = CacheFile::new(path.clone(), vec![7; 32], "ws://x".to_string());
+        let now = get_now_timestamp();
+        cache.insert(message("a", "alice", now, text("hello")), now);
+        cache.save().unwrap();
+        assert!(!fs::read(&path).unwrap().windows(5).any(|w| w == b"hello"));
+
+        let mut loaded = CacheFile::new(path.clone(), vec![7; 32], "ws://x".to_string());
+        loaded.load().unwrap();
+        assert_eq!(ids(&loaded), ["a"]);
+
+        let mut wrong_key = CacheFile::new(path.clone(), vec![8; 32], "ws://x".to_string());
+        assert!(wrong_key.load().is_err());
+        let mut wrong_server = CacheFile::new(path, vec![7; 32], "ws://y".to_string());
+        assert!(wrong_server.load().is_err());
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
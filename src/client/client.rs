@@ -1,34 +1,53 @@
-use std::{sync::RwLock, time::Duration};
+use std::{io::stdout, sync::RwLock, time::Duration};
 
 use anyhow::Result;
 use lazy_static::lazy_static;
 use orwell::{pb::orwell::ClientStatus, shared::helper::get_hash_version};
 use ratatui::{
-    crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers},
-    layout::{Constraint, Layout},
-    style::{Color, Style},
+    crossterm::{
+        event::{
+            self, DisableFocusChange, EnableFocusChange, Event, KeyCode, KeyEvent, KeyEventKind,
+            KeyModifiers, KeyboardEnhancementFlags, PopKeyboardEnhancementFlags,
+            PushKeyboardEnhancementFlags,
+        },
+        execute,
+        terminal::supports_keyboard_enhancement,
+    },
+    layout::{Alignment, Constraint, Flex, Layout, Rect},
+    style::Style,
     text::Line as RatatuiLine,
-    widgets::{Block, Borders},
+    widgets::{Block, Borders, Clear, Paragraph},
     DefaultTerminal, Frame,
 };
 
 use crate::{
+    cache::CacheManager,
     command_adapter::{CommandAdapterRegistry, CommandContext},
     commands::create_command_registry,
+    config::{flush_layout, get_keybindings, get_layout, get_theme, save_layout, LayoutConfig},
+    keymap::{Action, Keymap},
     message::{
-        add_chat_message, add_debug_message, get_chat_messages, get_debug_messages,
-        toggle_time_format, MessageLevel,
+        add_chat_message, add_debug_message, clear_unread, get_chat_messages, get_debug_messages,
+        get_unread, purge_expired_chat_messages, set_chat_away, toggle_time_format, MessageLevel,
     },
     renderer::{ChatRenderer, DebugRenderer, StateRenderer},
-    service::Service,
+    search::Search,
+    service::{ClientManager, ReceiptManager, Service},
+};
+use crate::{
+    theme::{degrade_buffer, set_theme, Theme, THEME},
+    widgets::MultiInput,
 };
-use crate::{theme::THEME, widgets::MultiInput};
 
 mod adapters;
+mod cache;
 mod command_adapter;
 mod commands;
 mod config;
+mod export;
 mod key;
+mod keymap;
+mod markup;
 mod message;
 mod message_adapter;
 mod message_adapters;
@@ -36,6 +55,7 @@ mod network;
 mod notify;
 mod packet_adapter;
 mod renderer;
+mod search;
 mod service;
 mod theme;
 mod widgets;
@@ -54,25 +74,111 @@ pub struct State {
     pub start_time: u64,
 }
 
+/// Below this many columns the side panes give way to the chat
+const COMPACT_WIDTH: u16 = 100;
+
+struct Completion {
+    candidates: Vec<String>,
+    index: usize,
+}
+
 struct App {
     chat_input: MultiInput,
     current_page: Page,
     scroll_offset: u16,
+    /// Candidates of the last Tab completion, further presses cycle through them
+    completion: Option<Completion>,
+    keymap: Keymap,
+    /// Quit was pressed, the next key confirms or cancels
+    confirm_quit: bool,
+    layout: LayoutConfig,
+    /// User list shown as a popup over the chat
+    show_user_list: bool,
+    /// Open search overlay, it takes the keyboard until closed
+    search: Option<Search>,
+    /// Scroll to the unread divider on the next render
+    jump_to_unread: bool,
 }
 
 impl App {
     fn new() -> Self {
+        if let Some(name) = get_theme() {
+            match Theme::load(&name) {
+                Ok(theme) => set_theme(theme),
+                Err(e) => add_chat_message(format!("主题 {} 加载失败: {}", name, e)),
+            }
+        }
+
         let mut chat_input = MultiInput::new();
-        chat_input.set_style(THEME.input_style());
+        chat_input.set_style(THEME.read().unwrap().input_style());
         chat_input.add_input("chat".to_string(), "".to_string());
 
+        let (keymap, errors) = Keymap::from_config(&get_keybindings());
+        for error in errors {
+            add_chat_message(format!("按键配置有误: {}", error));
+        }
+
         Self {
             chat_input,
             current_page: Page::Chat,
             scroll_offset: 0,
+            completion: None,
+            keymap,
+            confirm_quit: false,
+            layout: get_layout(),
+            show_user_list: false,
+            search: None,
+            jump_to_unread: false,
         }
     }
 
+    /// Complete the word at the cursor, repeated presses step through the
+    /// candidates by `step`
+    fn complete(&mut self, step: isize) {
+        if let Some(completion) = &mut self.completion {
+            let len = completion.candidates.len() as isize;
+            completion.index = (completion.index as isize + step).rem_euclid(len) as usize;
+            let candidate = completion.candidates[completion.index].clone();
+            self.chat_input.replace_word_before_cursor(&candidate);
+            return;
+        }
+
+        let Some(word) = self.chat_input.word_before_cursor() else {
+            return;
+        };
+        let candidates = if let Some(prefix) = word.strip_prefix('@') {
+            Self::mention_candidates(prefix)
+        } else {
+            match self.chat_input.text_before_cursor() {
+                Some(input) if input.starts_with('/') => {
+                    COMMAND_REGISTRY.read().unwrap().complete(&input)
+                }
+                _ => return,
+            }
+        };
+        if candidates.is_empty() {
+            return;
+        }
+
+        let index = if step < 0 { candidates.len() - 1 } else { 0 };
+        self.chat_input
+            .replace_word_before_cursor(&candidates[index]);
+        self.completion = Some(Completion { candidates, index });
+    }
+
+    fn mention_candidates(prefix: &str) -> Vec<String> {
+        let prefix = prefix.to_lowercase();
+        let self_id = ClientManager::get_self_id();
+        let mut names = ClientManager::get_all_clients()
+            .into_iter()
+            .filter(|client| Some(&client.id) != self_id.as_ref())
+            .filter(|client| client.name.to_lowercase().starts_with(&prefix))
+            .map(|client| format!("@{}", client.name))
+            .collect::<Vec<_>>();
+        names.sort();
+        names
+    }
+
     fn handle_key_event(&mut self, key: KeyEvent) {
         let state = STATE.read().unwrap();
         if state.processing {
@@ -82,87 +188,212 @@ impl App {
         if key.kind != KeyEventKind::Press {
             return;
         }
+        let action = self.keymap.action(&key);
+
+        // y, Enter or the quit chord again confirm, any other key cancels
+        if self.confirm_quit {
+            self.confirm_quit = false;
+            if action == Some(Action::Quit)
+                || matches!(
+                    key.code,
+                    KeyCode::Char('y') | KeyCode::Char('Y') | KeyCode::Enter
+                )
+            {
+                quit();
+            }
+            return;
+        }
+
+        if self.show_user_list && key.code == KeyCode::Esc {
+            self.show_user_list = false;
+            return;
+        }
+
+        if self.handle_search_key(&key, action) {
+            return;
+        }
+
+        if !matches!(action, Some(Action::Complete | Action::CompletePrev)) {
+            self.completion = None;
+        }
+        match action {
+            Some(action) => self.perform(action),
+            None => {
+                if let KeyCode::Char(c) = key.code {
+                    if !key
+                        .modifiers
+                        .intersects(KeyModifiers::CONTROL | KeyModifiers::ALT)
+                    {
+                        self.chat_input.handle_input(&c.to_string());
+                    }
+                }
+            }
+        }
+
+        if let Some(text) = self.chat_input.get_text_ref("chat") {
+            Service::update_typing(text);
+        }
+    }
+
+    /// Keys of the search overlay, any other action closes it and runs as usual
+    fn handle_search_key(&mut self, key: &KeyEvent, action: Option<Action>) -> bool {
+        let Some(search) = &mut self.search else {
+            return false;
+        };
+        let ctrl = key.modifiers.contains(KeyModifiers::CONTROL);
+        let alt = key.modifiers.contains(KeyModifiers::ALT);
         match key.code {
-            KeyCode::Char('z') if key.modifiers.contains(KeyModifiers::CONTROL) => {
-                std::process::exit(0);
+            KeyCode::Esc => self.search = None,
+            KeyCode::Enter | KeyCode::Up => search.older(),
+            KeyCode::Down => search.newer(),
+            KeyCode::Backspace => search.pop(),
+            KeyCode::Char('r') if ctrl => search.toggle_regex(),
+            KeyCode::Char(c) if !ctrl && !alt => search.push(c),
+            _ if action == Some(Action::Search) => search.older(),
+            _ => {
+                self.search = None;
+                return false;
             }
-            KeyCode::Char('d') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+        }
+        true
+    }
+
+    fn perform(&mut self, action: Action) {
+        match action {
+            Action::Quit => self.confirm_quit = true,
+            Action::ToggleTimeFormat => {
                 toggle_time_format();
                 add_debug_message(MessageLevel::Info, "时间格式已切换");
             }
-            KeyCode::Enter => {
-                if let Some(message) = self.chat_input.get_text("chat") {
-                    if !message.trim().is_empty() {
-                        if message.starts_with("/") {
-                            let registry = COMMAND_REGISTRY.read().unwrap();
-                            let context = CommandContext { app: self };
-                            if let Err(e) = registry.process_command(&message, context) {
-                                add_chat_message(format!("命令执行失败: {}", e));
-                            }
-                        } else if let Err(e) = Service::broadcast_message(message) {
-                            add_chat_message(format!("发送失败: {}", e));
-                        } else {
-                            add_debug_message(MessageLevel::Info, "发送成功");
-                        }
-                        // Clear the input by creating a new MultiInput
-                        let mut new_input = MultiInput::new();
-                        new_input.set_style(THEME.input_style());
-                        new_input.add_input("chat".to_string(), "".to_string());
-                        self.chat_input = new_input;
-                        self.chat_input.focus("chat");
-                        // Reset scroll offset when new message is added
-                        self.scroll_offset = 0;
-                    }
-                }
-            }
-            KeyCode::Char('i') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+            Action::Complete => self.complete(1),
+            Action::CompletePrev => self.complete(-1),
+            Action::FocusInput => {
                 let current = self.chat_input.get_focused_id().map(|s| s.to_string());
                 if current.is_none() {
                     self.chat_input.focus("chat");
                 }
             }
-            KeyCode::Char(c) => {
-                if !key.modifiers.contains(KeyModifiers::CONTROL) {
-                    self.chat_input.handle_input(&c.to_string());
+            Action::Send => self.send_input(),
+            Action::Newline => self.chat_input.insert_newline(),
+            // Up and Down move between lines of the input, past its edges they recall history
+            Action::Up => {
+                if !self.chat_input.move_cursor_up() {
+                    self.chat_input.history_prev();
                 }
             }
-            KeyCode::Backspace => self.chat_input.handle_backspace(),
-            KeyCode::Delete => self.chat_input.handle_delete(),
-            KeyCode::Left => self.chat_input.move_cursor_left(),
-            KeyCode::Right => self.chat_input.move_cursor_right(),
-            KeyCode::Up => {
-                // Scroll up
+            Action::Down => {
+                if !self.chat_input.move_cursor_down() {
+                    self.chat_input.history_next();
+                }
+            }
+            Action::ScrollUp => {
                 if self.scroll_offset < get_chat_messages().len() as u16 {
                     self.scroll_offset += 1;
                 }
             }
-            KeyCode::Down => {
-                // Scroll down
-                if self.scroll_offset > 0 {
-                    self.scroll_offset -= 1;
-                }
+            Action::ScrollDown => self.scroll_offset = self.scroll_offset.saturating_sub(1),
+            Action::PageUp => self.scroll_offset = self.scroll_offset.saturating_add(10),
+            Action::PageDown => self.scroll_offset = self.scroll_offset.saturating_sub(10),
+            Action::LineStart => self.chat_input.move_cursor_home(),
+            Action::LineEnd => self.chat_input.move_cursor_end(),
+            Action::CharLeft => self.chat_input.move_cursor_left(),
+            Action::CharRight => self.chat_input.move_cursor_right(),
+            Action::WordLeft => self.chat_input.move_word_left(),
+            Action::WordRight => self.chat_input.move_word_right(),
+            Action::HistoryPrev => self.chat_input.history_prev(),
+            Action::HistoryNext => self.chat_input.history_next(),
+            Action::Backspace => self.chat_input.handle_backspace(),
+            Action::Delete => self.chat_input.handle_delete(),
+            Action::KillWordBefore => self.chat_input.kill_word_before_cursor(),
+            Action::KillWordAfter => self.chat_input.kill_word_after_cursor(),
+            Action::KillLineStart => self.chat_input.kill_to_line_start(),
+            Action::KillLineEnd => self.chat_input.kill_to_line_end(),
+            Action::Yank => self.chat_input.yank(),
+            Action::YankPop => self.chat_input.yank_pop(),
+            Action::ToggleSide => self.update_layout(|layout| layout.show_side = !layout.show_side),
+            Action::ToggleDebug => {
+                self.update_layout(|layout| layout.show_debug = !layout.show_debug)
             }
-            KeyCode::PageUp => {
-                // Scroll up by page
-                self.scroll_offset = self.scroll_offset.saturating_add(10);
+            Action::UserList => self.show_user_list = !self.show_user_list,
+            Action::Search => self.search = Some(Search::new("", false)),
+            Action::JumpUnread => self.jump_to_unread = true,
+            Action::SideWider => {
+                self.update_layout(|layout| layout.side_width = (layout.side_width + 5).min(60))
             }
-            KeyCode::PageDown => {
-                // Scroll down by page
-                self.scroll_offset = self.scroll_offset.saturating_sub(10);
+            Action::SideNarrower => self.update_layout(|layout| {
+                layout.side_width = layout.side_width.saturating_sub(5).max(10)
+            }),
+            Action::DebugTaller => self
+                .update_layout(|layout| layout.debug_height = (layout.debug_height + 10).min(90)),
+            Action::DebugShorter => self.update_layout(|layout| {
+                layout.debug_height = layout.debug_height.saturating_sub(10).max(10)
+            }),
+        }
+    }
+
+    fn update_layout(&mut self, change: impl FnOnce(&mut LayoutConfig)) {
+        change(&mut self.layout);
+        save_layout(self.layout);
+    }
+
+    /// Send the input as a message or run it as a command
+    fn send_input(&mut self) {
+        let Some(message) = self.chat_input.get_text("chat") else {
+            return;
+        };
+        if message.trim().is_empty() {
+            return;
+        }
+        self.chat_input.push_history(message.clone());
+        if message.starts_with("/") {
+            let registry = COMMAND_REGISTRY.read().unwrap();
+            let context = CommandContext { app: self };
+            if let Err(e) = registry.process_command(&message, context) {
+                add_chat_message(format!("命令执行失败: {}", e));
             }
-            KeyCode::Esc => std::process::exit(0),
-            _ => {}
+        } else if let Err(e) = Service::broadcast_message(message) {
+            add_chat_message(format!("发送失败: {}", e));
+        } else {
+            add_debug_message(MessageLevel::Info, "发送成功");
         }
+        self.chat_input.clear();
+        // Reset scroll offset when new message is added
+        self.scroll_offset = 0;
     }
 }
 
 fn main() -> Result<()> {
     let terminal = ratatui::init();
+    // FocusGained and FocusLost tell the notifier when to stay quiet
+    let _ = execute!(stdout(), EnableFocusChange);
+    // lets the terminal report Shift+Enter apart from Enter where supported
+    if supports_keyboard_enhancement().unwrap_or(false) {
+        let _ = execute!(
+            stdout(),
+            PushKeyboardEnhancementFlags(KeyboardEnhancementFlags::DISAMBIGUATE_ESCAPE_CODES)
+        );
+    }
     let result = run(terminal);
-    ratatui::restore();
+    restore_terminal();
     result
 }
 
+fn restore_terminal() {
+    if supports_keyboard_enhancement().unwrap_or(false) {
+        let _ = execute!(stdout(), PopKeyboardEnhancementFlags);
+    }
+    let _ = execute!(stdout(), DisableFocusChange);
+    ratatui::restore();
+}
+
+/// Leave the client from anywhere with the terminal put back in order
+fn quit() -> ! {
+    CacheManager::save();
+    let _ = flush_layout(true);
+    restore_terminal();
+    std::process::exit(0)
+}
+
 lazy_static! {
     static ref APP: RwLock<Option<App>> = RwLock::new(None);
     static ref STATE: RwLock<State> = RwLock::new(State {
@@ -191,7 +422,14 @@ fn run(mut terminal: DefaultTerminal) -> Result<()> {
     let mut sleep_time = 200;
 
     loop {
+        purge_expired_chat_messages();
+        ReceiptManager::flush();
+        CacheManager::flush();
+        if let Err(e) = flush_layout(false) {
+            add_debug_message(MessageLevel::Error, format!("保存布局失败: {:?}", e));
+        }
         terminal.draw(|frame| render(frame, app))?;
+        notify::Notifier::flush_terminal_alert();
         if event::poll(Duration::from_millis(sleep_time))? {
             match event::read()? {
                 Event::Key(key) => app.handle_key_event(key),
@@ -201,6 +439,11 @@ fn run(mut terminal: DefaultTerminal) -> Result<()> {
                         app.chat_input.handle_input(&text);
                     }
                 }
+                Event::FocusGained => {
+                    notify::Notifier::set_focused(true);
+                    clear_unread();
+                }
+                Event::FocusLost => notify::Notifier::set_focused(false),
                 Event::Resize(_, _) => {}
                 _ => {}
             }
@@ -221,32 +464,59 @@ fn render(frame: &mut Frame, app: &mut App) {
 
     let mut widget = Block::bordered()
         .title("0RW3LL")
-        .style(THEME.title_style())
+        .style(THEME.read().unwrap().title_style())
         .borders(Borders::ALL)
-        .border_style(THEME.border_style());
+        .border_style(THEME.read().unwrap().border_style());
 
     let state = STATE.read().unwrap();
 
+    let theme = THEME.read().unwrap();
     if !state.logged {
-        widget = widget.title(RatatuiLine::from("UNLOGGED").style(Style::default().fg(Color::Red)));
+        widget = widget.title(RatatuiLine::from("UNLOGGED").style(Style::default().fg(theme.red)));
     } else {
-        widget = widget.title(RatatuiLine::from("LOGGED").style(Style::default().fg(Color::Green)));
+        widget = widget.title(RatatuiLine::from("LOGGED").style(Style::default().fg(theme.green)));
     }
 
     if !state.connected {
-        widget = widget.title(RatatuiLine::from("OFFLINE").style(Style::default().fg(Color::Red)));
+        widget = widget.title(RatatuiLine::from("OFFLINE").style(Style::default().fg(theme.red)));
     } else {
-        widget = widget.title(RatatuiLine::from("ONLINE").style(Style::default().fg(Color::Green)));
+        widget = widget.title(RatatuiLine::from("ONLINE").style(Style::default().fg(theme.green)));
+    }
+
+    let (unread, first_unread) = get_unread();
+    if unread > 0 {
+        let mut title = format!("{} 条未读", unread);
+        if let Some(chord) = app.keymap.chords(Action::JumpUnread).first() {
+            title = format!("{} ({} 跳转)", title, chord);
+        }
+        widget = widget.title(RatatuiLine::from(title).style(theme.unread_style()));
     }
 
+    // Narrow terminals such as an 80 column tmux split get the chat alone
+    let side_hidden = !app.layout.show_side || main_area.width < COMPACT_WIDTH;
+    if side_hidden {
+        if let Some(chord) = app.keymap.chords(Action::UserList).first() {
+            widget = widget.title(
+                RatatuiLine::from(format!("{} 用户列表", chord)).style(theme.highlight_style()),
+            );
+        }
+    }
+    drop(theme);
+
     // Render title with theme
     frame.render_widget(widget, title_area);
 
-    let horizontal = Layout::horizontal([Constraint::Percentage(80), Constraint::Percentage(20)]);
-    let [chat_area, right_area] = horizontal.areas(main_area);
-
-    let vertical = Layout::vertical([Constraint::Percentage(50), Constraint::Percentage(50)]);
-    let [state_area, debug_area] = vertical.areas(right_area);
+    let (chat_area, side_area) = if !side_hidden {
+        let side_width = app.layout.side_width;
+        let horizontal = Layout::horizontal([
+            Constraint::Percentage(100 - side_width),
+            Constraint::Percentage(side_width),
+        ]);
+        let [chat_area, side_area] = horizontal.areas(main_area);
+        (chat_area, Some(side_area))
+    } else {
+        (main_area, None)
+    };
 
     // Chat area layout
     let chat_layout = Layout::vertical([Constraint::Min(0), Constraint::Length(6)]);
@@ -254,32 +524,220 @@ fn render(frame: &mut Frame, app: &mut App) {
 
     // Get messages from the message manager
     let chat_messages = get_chat_messages();
-    let debug_messages = get_debug_messages();
+
+    if let Some(search) = &mut app.search {
+        search.update(&chat_messages);
+    }
+
+    let divider =
+        first_unread.and_then(|id| chat_messages.iter().position(|line| line.id() == Some(&id)));
 
     // Render chat messages using ChatRenderer
-    let ratatui_lines =
-        ChatRenderer::render(frame, messages_area, &chat_messages, app.scroll_offset);
+    let (ratatui_lines, line_ends) = ChatRenderer::render(
+        frame,
+        messages_area,
+        &chat_messages,
+        app.scroll_offset,
+        app.search.as_ref(),
+        divider,
+    );
+
+    // Bring the selected search hit to the middle of the chat
+    if let Some(index) = app.search.as_mut().and_then(Search::take_scroll) {
+        let total = line_ends.last().copied().unwrap_or(0);
+        let start = index.checked_sub(1).map_or(0, |prev| line_ends[prev]);
+        let end = line_ends[index];
+        let visible_height = messages_area.height.saturating_sub(2) as usize;
+        let below = visible_height.saturating_sub(end - start) / 2;
+        app.scroll_offset = total.saturating_sub(end + below) as u16;
+    }
+    // Put the unread divider at the top of the chat
+    if std::mem::take(&mut app.jump_to_unread) {
+        if let Some(index) = divider {
+            let total = line_ends.last().copied().unwrap_or(0);
+            let start = index.checked_sub(1).map_or(0, |prev| line_ends[prev]);
+            let visible_height = messages_area.height.saturating_sub(2) as usize;
+            app.scroll_offset = total.saturating_sub(start + visible_height) as u16;
+        } else {
+            add_debug_message(MessageLevel::Info, "没有未读消息");
+        }
+    }
     let (visible_lines, adjusted_scroll_offset) =
         ChatRenderer::handle_scrolling(ratatui_lines, messages_area, app.scroll_offset);
     app.scroll_offset = adjusted_scroll_offset;
 
+    // New messages pile up unseen while unfocused or scrolled away from the bottom
+    set_chat_away(!notify::Notifier::is_focused() || adjusted_scroll_offset > 0);
+
+    // Messages from others count as read once they are on screen in a focused terminal
+    if notify::Notifier::is_focused() {
+        let self_id = ClientManager::get_self_id();
+        let read =
+            ChatRenderer::visible_messages(&line_ends, visible_lines.len(), adjusted_scroll_offset)
+                .into_iter()
+                .filter_map(|index| {
+                    let message = &chat_messages[index];
+                    let id = message.id()?;
+                    (message.sender_id() != self_id.as_deref()).then(|| id.to_string())
+                })
+                .collect::<Vec<_>>();
+        ReceiptManager::queue_read(read);
+    }
+
     let messages_widget = ChatRenderer::create_widget(visible_lines);
     frame.render_widget(messages_widget, messages_area);
 
     // Render chat input
     frame.render_widget(&mut app.chat_input, input_area);
 
-    // Render debug output using DebugRenderer
-    let debug_lines = DebugRenderer::render(debug_area, &debug_messages);
-    let debug_widget = DebugRenderer::create_widget(debug_lines, debug_area);
-    frame.render_widget(debug_widget, debug_area);
+    if let Some(side_area) = side_area {
+        let state_area = if app.layout.show_debug {
+            let debug_height = app.layout.debug_height;
+            let vertical = Layout::vertical([
+                Constraint::Percentage(100 - debug_height),
+                Constraint::Percentage(debug_height),
+            ]);
+            let [state_area, debug_area] = vertical.areas(side_area);
 
-    // Render state information using StateRenderer
-    let state_lines = if state.connected {
-        StateRenderer::render_connected(&state)
+            // Render debug output using DebugRenderer
+            let debug_messages = get_debug_messages();
+            let debug_lines = DebugRenderer::render(debug_area, &debug_messages);
+            let debug_widget = DebugRenderer::create_widget(debug_lines, debug_area);
+            frame.render_widget(debug_widget, debug_area);
+            state_area
+        } else {
+            side_area
+        };
+
+        // Render state information using StateRenderer
+        let state_widget = StateRenderer::create_widget(state_lines(&state));
+        frame.render_widget(state_widget, state_area);
+    }
+
+    if let Some(completion) = &app.completion {
+        render_completion(frame, completion, messages_area);
+    }
+
+    if let Some(search) = &app.search {
+        render_search(frame, search, messages_area);
+    }
+
+    if app.show_user_list {
+        render_user_list(frame, &state);
+    }
+
+    if app.confirm_quit {
+        render_quit_confirm(frame);
+    }
+
+    degrade_buffer(frame.buffer_mut());
+}
+
+fn state_lines(state: &State) -> Vec<RatatuiLine<'_>> {
+    if state.connected {
+        StateRenderer::render_connected(state)
     } else {
         StateRenderer::render_disconnected()
-    };
-    let state_widget = StateRenderer::create_widget(state_lines);
-    frame.render_widget(state_widget, state_area);
+    }
+}
+
+/// The state pane with its user list as a popup, for when the side column is hidden
+fn render_user_list(frame: &mut Frame, state: &State) {
+    let [area] = Layout::vertical([Constraint::Percentage(70)])
+        .flex(Flex::Center)
+        .areas(frame.area());
+    let [area] = Layout::horizontal([Constraint::Length(40)])
+        .flex(Flex::Center)
+        .areas(area);
+
+    let theme = THEME.read().unwrap();
+    let popup = Paragraph::new(state_lines(state))
+        .style(theme.message_style())
+        .block(
+            Block::bordered()
+                .border_style(theme.highlight_style())
+                .title("用户列表 (Esc 关闭)"),
+        );
+    frame.render_widget(Clear, area);
+    frame.render_widget(popup, area);
+}
+
+/// Search bar over the bottom of the chat
+fn render_search(frame: &mut Frame, search: &Search, area: Rect) {
+    let height = 3.min(area.height);
+    let bar = Rect::new(area.x, area.bottom() - height, area.width, height);
+    let theme = THEME.read().unwrap();
+    let widget = Paragraph::new(search.status())
+        .style(theme.message_style())
+        .block(
+            Block::bordered()
+                .border_style(theme.highlight_style())
+                .title("Enter/↑ 更早  ↓ 更新  Ctrl+R 正则  Esc 关闭"),
+        );
+    frame.render_widget(Clear, bar);
+    frame.render_widget(widget, bar);
+}
+
+/// Candidate list drawn at the bottom of the chat, just above the input
+fn render_completion(frame: &mut Frame, completion: &Completion, area: Rect) {
+    const MAX_VISIBLE: usize = 8;
+    if completion.candidates.len() < 2 {
+        return;
+    }
+
+    let visible = completion.candidates.len().min(MAX_VISIBLE);
+    let start = (completion.index + 1).saturating_sub(visible);
+    let lines = completion.candidates[start..start + visible]
+        .iter()
+        .enumerate()
+        .map(|(offset, candidate)| {
+            let line = RatatuiLine::from(candidate.as_str());
+            if start + offset == completion.index {
+                line.style(THEME.read().unwrap().highlight_style())
+            } else {
+                line
+            }
+        })
+        .collect::<Vec<_>>();
+
+    let width = lines.iter().map(|line| line.width()).max().unwrap_or(0) as u16 + 2;
+    let title = format!("{}/{}", completion.index + 1, completion.candidates.len());
+    let width = width.max(title.len() as u16 + 2).min(area.width);
+    let height = (visible as u16 + 2).min(area.height);
+    let popup = Rect::new(
+        area.x + 1,
+        area.bottom().saturating_sub(height),
+        width,
+        height,
+    );
+
+    let widget = Paragraph::new(lines)
+        .style(THEME.read().unwrap().message_style())
+        .block(
+            Block::bordered()
+                .border_style(THEME.read().unwrap().border_style())
+                .title(title),
+        );
+    frame.render_widget(Clear, popup);
+    frame.render_widget(widget, popup);
+}
+
+fn render_quit_confirm(frame: &mut Frame) {
+    let [area] = Layout::vertical([Constraint::Length(3)])
+        .flex(Flex::Center)
+        .areas(frame.area());
+    let [area] = Layout::horizontal([Constraint::Length(24)])
+        .flex(Flex::Center)
+        .areas(area);
+
+    let popup = Paragraph::new("确认退出？(y/n)")
+        .alignment(Alignment::Center)
+        .style(THEME.read().unwrap().message_style())
+        .block(
+            Block::bordered()
+                .border_style(THEME.read().unwrap().highlight_style())
+                .title("退出"),
+        );
+    frame.render_widget(Clear, area);
+    frame.render_widget(popup, area);
 }
@@ -20,6 +20,12 @@ pub trait CommandAdapter: Send + Sync {
 
     /// Process the command
     fn process(&self, args: &[&str], context: CommandContext<'_>) -> Result<()>;
+
+    /// Candidates for the argument following `args`, the registry filters
+    /// them by what has been typed so far
+    fn complete(&self, _args: &[&str]) -> Vec<String> {
+        Vec::new()
+    }
 }
 
 /// Registry for command adapters
@@ -50,6 +56,39 @@ impl CommandAdapterRegistry {
             .collect()
     }
 
+    /// Completion candidates for the word at the end of `input`, command
+    /// names for the first word and the adapter's candidates after it
+    pub fn complete(&self, input: &str) -> Vec<String> {
+        let mut parts: Vec<&str> = input.split_whitespace().collect();
+        if input.is_empty() || input.ends_with(char::is_whitespace) {
+            parts.push("");
+        }
+        let Some((typed, args)) = parts.split_last() else {
+            return Vec::new();
+        };
+
+        let candidates = match args.split_first() {
+            None => {
+                let mut names = self.adapters.keys().cloned().collect::<Vec<_>>();
+                names.push("/help".to_string());
+                names
+            }
+            Some((command, args)) => match self.get(command) {
+                Some(adapter) => adapter.complete(args),
+                None => return Vec::new(),
+            },
+        };
+
+        let typed = typed.to_lowercase();
+        let mut candidates = candidates
+            .into_iter()
+            .filter(|candidate| candidate.to_lowercase().starts_with(&typed))
+            .collect::<Vec<_>>();
+        candidates.sort();
+        candidates.dedup();
+        candidates
+    }
+
     pub fn process_command(&self, command: &str, context: CommandContext<'_>) -> Result<()> {
         let parts: Vec<&str> = command.split_whitespace().collect();
         if parts.is_empty() {
@@ -80,3 +119,49 @@ impl CommandAdapterRegistry {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct TestCommand(&'static str);
+
+    impl CommandAdapter for TestCommand {
+        fn command_name(&self) -> &'static str {
+            self.0
+        }
+
+        fn description(&self) -> &'static str {
+            ""
+        }
+
+        fn usage(&self) -> &'static str {
+            ""
+        }
+
+        fn process(&self, _args: &[&str], _context: CommandContext<'_>) -> Result<()> {
+            Ok(())
+        }
+
+        fn complete(&self, args: &[&str]) -> Vec<String> {
+            match args {
+                [] => vec!["Bob".to_string(), "alice".to_string(), "bea".to_string()],
+                _ => Vec::new(),
+            }
+        }
+    }
+
+    #[test]
+    fn complete_commands_and_arguments() {
+        let mut registry = CommandAdapterRegistry::new();
+        registry.register(Box::new(TestCommand("/login")));
+        registry.register(Box::new(TestCommand("/logout")));
+
+        assert_eq!(registry.complete("/lo"), vec!["/login", "/logout"]);
+        assert_eq!(registry.complete("/h"), vec!["/help"]);
+        assert_eq!(registry.complete("/login "), vec!["Bob", "alice", "bea"]);
+        assert_eq!(registry.complete("/login b"), vec!["Bob", "bea"]);
+        assert!(registry.complete("/login bob ").is_empty());
+        assert!(registry.complete("/unknown ").is_empty());
+    }
+}
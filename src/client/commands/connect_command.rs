@@ -2,6 +2,7 @@ use anyhow::Result;
 
 use crate::{
     command_adapter::{CommandAdapter, CommandContext},
+    config::get_server_url,
     message::add_chat_message,
     network::Network,
 };
@@ -32,4 +33,12 @@ impl CommandAdapter for ConnectCommand {
 
         Ok(())
     }
+
+    fn complete(&self, args: &[&str]) -> Vec<String> {
+        if args.is_empty() {
+            get_server_url().into_iter().collect()
+        } else {
+            Vec::new()
+        }
+    }
 }
@@ -0,0 +1,57 @@
+use anyhow::Result;
+
+use crate::{
+    command_adapter::{CommandAdapter, CommandContext},
+    message::{add_chat_message, get_chat_message_by_offset},
+    service::{ClientManager, Service},
+    STATE,
+};
+
+pub struct DeleteCommand;
+
+impl CommandAdapter for DeleteCommand {
+    fn command_name(&self) -> &'static str {
+        "/delete"
+    }
+
+    fn description(&self) -> &'static str {
+        "删除自己发送的消息，1 表示最新一条消息"
+    }
+
+    fn usage(&self) -> &'static str {
+        "/delete <序号>"
+    }
+
+    fn process(&self, args: &[&str], _context: CommandContext<'_>) -> Result<()> {
+        if args.len() != 1 {
+            add_chat_message("使用方法: /delete <序号>");
+            return Ok(());
+        }
+
+        let state = STATE.read().unwrap();
+        if !state.connected {
+            add_chat_message("您尚未连接至服务器，无法删除消息");
+            return Ok(());
+        }
+        drop(state);
+
+        let Some(target) = args[0]
+            .parse::<usize>()
+            .ok()
+            .and_then(get_chat_message_by_offset)
+        else {
+            add_chat_message("找不到该消息");
+            return Ok(());
+        };
+        if ClientManager::get_self_id().as_deref() != Some(target.sender_id.as_str()) {
+            add_chat_message("只能删除自己的消息");
+            return Ok(());
+        }
+
+        if let Err(e) = Service::delete_message(target.id) {
+            add_chat_message(format!("删除失败: {}", e));
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,63 @@
+use anyhow::Result;
+
+use crate::{
+    command_adapter::{CommandAdapter, CommandContext},
+    message::add_chat_message,
+    service::Service,
+    STATE,
+};
+
+pub struct DisappearCommand;
+
+impl CommandAdapter for DisappearCommand {
+    fn command_name(&self) -> &'static str {
+        "/disappear"
+    }
+
+    fn description(&self) -> &'static str {
+        "设置会话的消失消息计时，到期后所有客户端删除消息"
+    }
+
+    fn usage(&self) -> &'static str {
+        "/disappear <秒数|off>"
+    }
+
+    fn process(&self, args: &[&str], _context: CommandContext<'_>) -> Result<()> {
+        if args.len() != 1 {
+            add_chat_message("使用方法: /disappear <秒数|off>");
+            return Ok(());
+        }
+
+        let state = STATE.read().unwrap();
+        if !state.connected {
+            add_chat_message("您尚未连接至服务器，无法设置消失消息");
+            return Ok(());
+        }
+        drop(state);
+
+        let seconds = match args[0] {
+            "off" => 0,
+            value => match value.parse::<u64>() {
+                Ok(seconds) if seconds > 0 => seconds,
+                _ => {
+                    add_chat_message("秒数必须是正整数");
+                    return Ok(());
+                }
+            },
+        };
+
+        if let Err(e) = Service::set_disappearing_timer(seconds) {
+            add_chat_message(format!("设置失败: {}", e));
+        }
+
+        Ok(())
+    }
+
+    fn complete(&self, args: &[&str]) -> Vec<String> {
+        if args.is_empty() {
+            vec!["off".to_string()]
+        } else {
+            Vec::new()
+        }
+    }
+}
@@ -0,0 +1,57 @@
+use anyhow::Result;
+
+use crate::{
+    command_adapter::{CommandAdapter, CommandContext},
+    message::{add_chat_message, get_chat_message_by_offset},
+    service::{ClientManager, Service},
+    STATE,
+};
+
+pub struct EditCommand;
+
+impl CommandAdapter for EditCommand {
+    fn command_name(&self) -> &'static str {
+        "/edit"
+    }
+
+    fn description(&self) -> &'static str {
+        "修改自己发送的消息，1 表示最新一条消息"
+    }
+
+    fn usage(&self) -> &'static str {
+        "/edit <序号> <新内容>"
+    }
+
+    fn process(&self, args: &[&str], _context: CommandContext<'_>) -> Result<()> {
+        if args.len() < 2 {
+            add_chat_message("使用方法: /edit <序号> <新内容>");
+            return Ok(());
+        }
+
+        let state = STATE.read().unwrap();
+        if !state.connected {
+            add_chat_message("您尚未连接至服务器，无法修改消息");
+            return Ok(());
+        }
+        drop(state);
+
+        let Some(target) = args[0]
+            .parse::<usize>()
+            .ok()
+            .and_then(get_chat_message_by_offset)
+        else {
+            add_chat_message("找不到该消息");
+            return Ok(());
+        };
+        if ClientManager::get_self_id().as_deref() != Some(target.sender_id.as_str()) {
+            add_chat_message("只能修改自己的消息");
+            return Ok(());
+        }
+
+        if let Err(e) = Service::edit_message(target.id, args[1..].join(" ")) {
+            add_chat_message(format!("修改失败: {}", e));
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,52 @@
+use anyhow::Result;
+
+use crate::{
+    command_adapter::{CommandAdapter, CommandContext},
+    message::add_chat_message,
+    service::Service,
+    STATE,
+};
+
+pub struct EphemeralCommand;
+
+impl CommandAdapter for EphemeralCommand {
+    fn command_name(&self) -> &'static str {
+        "/ephemeral"
+    }
+
+    fn description(&self) -> &'static str {
+        "发送一条限时保留的消息，到期后从服务器历史中删除"
+    }
+
+    fn usage(&self) -> &'static str {
+        "/ephemeral <秒数> <消息>"
+    }
+
+    fn process(&self, args: &[&str], _context: CommandContext<'_>) -> Result<()> {
+        if args.len() < 2 {
+            add_chat_message("使用方法: /ephemeral <秒数> <消息>");
+            return Ok(());
+        }
+
+        let state = STATE.read().unwrap();
+        if !state.connected {
+            add_chat_message("您尚未连接至服务器，无法发送消息");
+            return Ok(());
+        }
+        drop(state);
+
+        let ttl = match args[0].parse::<u64>() {
+            Ok(ttl) if ttl > 0 => ttl,
+            _ => {
+                add_chat_message("秒数必须是正整数");
+                return Ok(());
+            }
+        };
+
+        if let Err(e) = Service::broadcast_ephemeral_message(args[1..].join(" "), ttl) {
+            add_chat_message(format!("发送失败: {}", e));
+        }
+
+        Ok(())
+    }
+}
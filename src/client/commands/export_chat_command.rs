@@ -0,0 +1,122 @@
+use std::fs;
+
+use anyhow::Result;
+use orwell::shared::helper::get_now_timestamp;
+
+use crate::{
+    command_adapter::{CommandAdapter, CommandContext},
+    export::{parse_since, render, ExportFormat, ExportedMessage, Manifest},
+    key::KEY_MANAGER,
+    message::{add_chat_message, get_chat_messages},
+};
+
+pub struct ExportChatCommand;
+
+impl CommandAdapter for ExportChatCommand {
+    fn command_name(&self) -> &'static str {
+        "/export-chat"
+    }
+
+    fn description(&self) -> &'static str {
+        "把聊天记录导出为 txt、json 或 html，可附带签名清单"
+    }
+
+    fn usage(&self) -> &'static str {
+        "/export-chat <路径> [--format txt|json|html] [--since 2026-01-31|12h] [--sign]"
+    }
+
+    fn process(&self, args: &[&str], _context: CommandContext<'_>) -> Result<()> {
+        let mut path = None;
+        let mut format = None;
+        let mut since = 0;
+        let mut sign = false;
+
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            match *arg {
+                "--format" => match args.next().and_then(|name| ExportFormat::parse(name)) {
+                    Some(value) => format = Some(value),
+                    None => {
+                        add_chat_message("--format 只支持 txt、json、html");
+                        return Ok(());
+                    }
+                },
+                "--since" => {
+                    let Some(value) = args.next() else {
+                        add_chat_message("--since 缺少时间");
+                        return Ok(());
+                    };
+                    match parse_since(value, get_now_timestamp()) {
+                        Ok(value) => since = value,
+                        Err(e) => {
+                            add_chat_message(e.to_string());
+                            return Ok(());
+                        }
+                    }
+                }
+                "--sign" => sign = true,
+                value if path.is_none() => path = Some(value.to_string()),
+                value => {
+                    add_chat_message(format!("未知参数: {}", value));
+                    return Ok(());
+                }
+            }
+        }
+
+        let Some(path) = path else {
+            add_chat_message(format!("用法: {}", self.usage()));
+            return Ok(());
+        };
+        let format = format.unwrap_or_else(|| ExportFormat::from_path(&path));
+
+        let profile = KEY_MANAGER
+            .read()
+            .unwrap()
+            .as_ref()
+            .and_then(|manager| manager.profile.clone());
+        if sign && profile.is_none() {
+            add_chat_message("签名导出需要先登录");
+            return Ok(());
+        }
+
+        // Cached history is replayed into the chat at login, so the chat
+        // already holds everything the local cache has for this server
+        let messages: Vec<ExportedMessage> = get_chat_messages()
+            .iter()
+            .filter(|line| line.id().is_some() && line.timestamp() >= since)
+            .map(ExportedMessage::from_line)
+            .collect();
+        let content = render(&messages, format);
+
+        if let Err(e) = fs::write(&path, &content) {
+            add_chat_message(format!("导出失败: {}", e));
+            return Ok(());
+        }
+        if let (true, Some(profile)) = (sign, profile) {
+            let manifest =
+                Manifest::sign(&path, content.as_bytes(), format, messages.len(), &profile)
+                    .and_then(|manifest| manifest.save(&path));
+            if let Err(e) = manifest {
+                add_chat_message(format!("已导出到 {}，但签名清单写入失败: {}", path, e));
+                return Ok(());
+            }
+            add_chat_message(format!(
+                "已导出 {} 条消息到 {}，签名清单: {}",
+                messages.len(),
+                path,
+                Manifest::path(&path)
+            ));
+        } else {
+            add_chat_message(format!("已导出 {} 条消息到 {}", messages.len(), path));
+        }
+        Ok(())
+    }
+
+    fn complete(&self, args: &[&str]) -> Vec<String> {
+        match args {
+            [.., "--format"] => ["txt", "json", "html"].map(String::from).to_vec(),
+            [_, ..] => ["--format", "--since", "--sign"].map(String::from).to_vec(),
+            [] => Vec::new(),
+        }
+    }
+}
@@ -0,0 +1,43 @@
+use anyhow::Result;
+
+use crate::{
+    command_adapter::{CommandAdapter, CommandContext},
+    keymap::Action,
+    message::add_chat_message,
+};
+
+pub struct KeysCommand;
+
+impl CommandAdapter for KeysCommand {
+    fn command_name(&self) -> &'static str {
+        "/keys"
+    }
+
+    fn description(&self) -> &'static str {
+        "列出当前的按键绑定"
+    }
+
+    fn usage(&self) -> &'static str {
+        "/keys"
+    }
+
+    fn process(&self, _args: &[&str], context: CommandContext<'_>) -> Result<()> {
+        let mut lines = vec!["按键绑定:".to_string()];
+        for action in Action::ALL {
+            let chords = context.app.keymap.chords(action);
+            let chords = if chords.is_empty() {
+                "(未绑定)".to_string()
+            } else {
+                chords.join(", ")
+            };
+            lines.push(format!(
+                "  {:<18} {:<24} {}",
+                action.name(),
+                chords,
+                action.description()
+            ));
+        }
+        add_chat_message(lines.join("\n"));
+        Ok(())
+    }
+}
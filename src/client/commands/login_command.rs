@@ -33,4 +33,12 @@ impl CommandAdapter for LoginCommand {
 
         Ok(())
     }
+
+    fn complete(&self, args: &[&str]) -> Vec<String> {
+        if args.is_empty() {
+            KeyManager::list_profiles()
+        } else {
+            Vec::new()
+        }
+    }
 }
@@ -0,0 +1,44 @@
+use anyhow::Result;
+
+use crate::{
+    command_adapter::{CommandAdapter, CommandContext},
+    message::add_chat_message,
+    service::Service,
+    STATE,
+};
+
+pub struct MeCommand;
+
+impl CommandAdapter for MeCommand {
+    fn command_name(&self) -> &'static str {
+        "/me"
+    }
+
+    fn description(&self) -> &'static str {
+        "以第三人称发送动作消息"
+    }
+
+    fn usage(&self) -> &'static str {
+        "/me <动作>"
+    }
+
+    fn process(&self, args: &[&str], _context: CommandContext<'_>) -> Result<()> {
+        if args.is_empty() {
+            add_chat_message("使用方法: /me <动作>");
+            return Ok(());
+        }
+
+        let state = STATE.read().unwrap();
+        if !state.connected {
+            add_chat_message("您尚未连接至服务器，无法发送消息");
+            return Ok(());
+        }
+        drop(state);
+
+        if let Err(e) = Service::broadcast_action(args.join(" ")) {
+            add_chat_message(format!("发送失败: {}", e));
+        }
+
+        Ok(())
+    }
+}
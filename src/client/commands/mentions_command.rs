@@ -0,0 +1,48 @@
+use anyhow::Result;
+
+use crate::{
+    command_adapter::{CommandAdapter, CommandContext},
+    message::{add_chat_message, add_chat_message_rich, get_recent_mentions, LineBuilder},
+};
+
+/// How many mentions `/mentions` lists
+const MENTIONS_LIMIT: usize = 20;
+
+pub struct MentionsCommand;
+
+impl CommandAdapter for MentionsCommand {
+    fn command_name(&self) -> &'static str {
+        "/mentions"
+    }
+
+    fn description(&self) -> &'static str {
+        "列出最近提及你的消息"
+    }
+
+    fn usage(&self) -> &'static str {
+        "/mentions"
+    }
+
+    fn process(&self, _args: &[&str], _context: CommandContext<'_>) -> Result<()> {
+        let mentions = get_recent_mentions(MENTIONS_LIMIT);
+        if mentions.is_empty() {
+            add_chat_message("最近没有人提及你");
+            return Ok(());
+        }
+
+        add_chat_message(format!("最近 {} 条提及你的消息:", mentions.len()));
+        for line in mentions {
+            // copies without the message id, edits keep targeting the original
+            add_chat_message_rich(
+                LineBuilder::new()
+                    .time(line.timestamp())
+                    .sender(line.sender().clone())
+                    .plain(line.to_plain_text())
+                    .build(),
+                None,
+            );
+        }
+
+        Ok(())
+    }
+}
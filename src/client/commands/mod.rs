@@ -1,14 +1,35 @@
 pub mod afk_command;
 pub mod color_command;
 pub mod connect_command;
+pub mod delete_command;
+pub mod disappear_command;
+pub mod edit_command;
+pub mod ephemeral_command;
+pub mod export_chat_command;
+pub mod keys_command;
 pub mod login_command;
+pub mod me_command;
+pub mod mentions_command;
+pub mod raw_command;
+pub mod react_command;
 pub mod register_command;
+pub mod reply_command;
+pub mod search_command;
+pub mod theme_command;
+pub mod verify_export_command;
+pub mod wipe_cache_command;
 
 use crate::command_adapter::CommandAdapterRegistry;
 
 use self::{
     afk_command::AfkCommand, color_command::ColorCommand, connect_command::ConnectCommand,
-    login_command::LoginCommand, register_command::RegisterCommand,
+    delete_command::DeleteCommand, disappear_command::DisappearCommand, edit_command::EditCommand,
+    ephemeral_command::EphemeralCommand, export_chat_command::ExportChatCommand,
+    keys_command::KeysCommand, login_command::LoginCommand, me_command::MeCommand,
+    mentions_command::MentionsCommand, raw_command::RawCommand, react_command::ReactCommand,
+    register_command::RegisterCommand, reply_command::ReplyCommand, search_command::SearchCommand,
+    theme_command::ThemeCommand, verify_export_command::VerifyExportCommand,
+    wipe_cache_command::WipeCacheCommand,
 };
 
 /// Create and register all command adapters
@@ -20,6 +41,21 @@ pub fn create_command_registry() -> CommandAdapterRegistry {
     registry.register(Box::new(ConnectCommand));
     registry.register(Box::new(ColorCommand));
     registry.register(Box::new(AfkCommand));
+    registry.register(Box::new(EphemeralCommand));
+    registry.register(Box::new(DisappearCommand));
+    registry.register(Box::new(EditCommand));
+    registry.register(Box::new(DeleteCommand));
+    registry.register(Box::new(ReplyCommand));
+    registry.register(Box::new(ReactCommand));
+    registry.register(Box::new(MeCommand));
+    registry.register(Box::new(MentionsCommand));
+    registry.register(Box::new(RawCommand));
+    registry.register(Box::new(KeysCommand));
+    registry.register(Box::new(ThemeCommand));
+    registry.register(Box::new(SearchCommand));
+    registry.register(Box::new(WipeCacheCommand));
+    registry.register(Box::new(ExportChatCommand));
+    registry.register(Box::new(VerifyExportCommand));
 
     registry
 }
@@ -0,0 +1,31 @@
+use anyhow::Result;
+
+use crate::{
+    command_adapter::{CommandAdapter, CommandContext},
+    message::{add_chat_message, toggle_raw_markup},
+};
+
+pub struct RawCommand;
+
+impl CommandAdapter for RawCommand {
+    fn command_name(&self) -> &'static str {
+        "/raw"
+    }
+
+    fn description(&self) -> &'static str {
+        "切换显示格式化消息的原文"
+    }
+
+    fn usage(&self) -> &'static str {
+        "/raw"
+    }
+
+    fn process(&self, _args: &[&str], _context: CommandContext<'_>) -> Result<()> {
+        if toggle_raw_markup() {
+            add_chat_message("格式化消息显示为原文");
+        } else {
+            add_chat_message("格式化消息显示为富文本");
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,60 @@
+use anyhow::Result;
+
+use crate::{
+    command_adapter::{CommandAdapter, CommandContext},
+    message::{add_chat_message, get_chat_message_by_offset, has_reacted, is_valid_reaction},
+    service::{ClientManager, Service},
+    STATE,
+};
+
+pub struct ReactCommand;
+
+impl CommandAdapter for ReactCommand {
+    fn command_name(&self) -> &'static str {
+        "/react"
+    }
+
+    fn description(&self) -> &'static str {
+        "对消息添加表情回应，再次使用同一表情则取消，1 表示最新一条消息"
+    }
+
+    fn usage(&self) -> &'static str {
+        "/react <序号> <表情>"
+    }
+
+    fn process(&self, args: &[&str], _context: CommandContext<'_>) -> Result<()> {
+        if args.len() != 2 {
+            add_chat_message("使用方法: /react <序号> <表情>");
+            return Ok(());
+        }
+
+        let state = STATE.read().unwrap();
+        if !state.connected {
+            add_chat_message("您尚未连接至服务器，无法发送回应");
+            return Ok(());
+        }
+        drop(state);
+
+        let emoji = args[1];
+        if !is_valid_reaction(emoji) {
+            add_chat_message("回应只能是单个表情");
+            return Ok(());
+        }
+        let Some(target) = args[0]
+            .parse::<usize>()
+            .ok()
+            .and_then(get_chat_message_by_offset)
+        else {
+            add_chat_message("找不到该消息");
+            return Ok(());
+        };
+
+        let remove = ClientManager::get_self_id()
+            .is_some_and(|self_id| has_reacted(&target.id, &self_id, emoji));
+        if let Err(e) = Service::react_to_message(target.id, emoji.to_string(), remove) {
+            add_chat_message(format!("发送失败: {}", e));
+        }
+
+        Ok(())
+    }
+}
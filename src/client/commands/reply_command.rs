@@ -0,0 +1,58 @@
+use anyhow::Result;
+
+use crate::{
+    command_adapter::{CommandAdapter, CommandContext},
+    message::{add_chat_message, get_chat_message_by_offset, quote_excerpt},
+    service::Service,
+    STATE,
+};
+
+pub struct ReplyCommand;
+
+impl CommandAdapter for ReplyCommand {
+    fn command_name(&self) -> &'static str {
+        "/reply"
+    }
+
+    fn description(&self) -> &'static str {
+        "回复一条消息并引用其内容，1 表示最新一条消息"
+    }
+
+    fn usage(&self) -> &'static str {
+        "/reply <序号> <消息>"
+    }
+
+    fn process(&self, args: &[&str], _context: CommandContext<'_>) -> Result<()> {
+        if args.len() < 2 {
+            add_chat_message("使用方法: /reply <序号> <消息>");
+            return Ok(());
+        }
+
+        let state = STATE.read().unwrap();
+        if !state.connected {
+            add_chat_message("您尚未连接至服务器，无法发送消息");
+            return Ok(());
+        }
+        drop(state);
+
+        let Some(target) = args[0]
+            .parse::<usize>()
+            .ok()
+            .and_then(get_chat_message_by_offset)
+        else {
+            add_chat_message("找不到该消息");
+            return Ok(());
+        };
+
+        if let Err(e) = Service::reply_message(
+            target.id,
+            target.sender_name,
+            quote_excerpt(&target.text),
+            args[1..].join(" "),
+        ) {
+            add_chat_message(format!("发送失败: {}", e));
+        }
+
+        Ok(())
+    }
+}
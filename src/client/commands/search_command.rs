@@ -0,0 +1,35 @@
+use anyhow::Result;
+
+use crate::{
+    command_adapter::{CommandAdapter, CommandContext},
+    message::add_chat_message,
+    search::Search,
+};
+
+pub struct SearchCommand;
+
+impl CommandAdapter for SearchCommand {
+    fn command_name(&self) -> &'static str {
+        "/search"
+    }
+
+    fn description(&self) -> &'static str {
+        "搜索聊天记录，-r 使用正则，from:名字 只看某人的消息"
+    }
+
+    fn usage(&self) -> &'static str {
+        "/search [-r] [from:名字] <关键词>"
+    }
+
+    fn process(&self, args: &[&str], context: CommandContext<'_>) -> Result<()> {
+        let regex = args.first() == Some(&"-r");
+        let query = args[regex as usize..].join(" ");
+        if query.is_empty() {
+            add_chat_message("使用方法: /search [-r] [from:名字] <关键词>");
+            return Ok(());
+        }
+
+        context.app.search = Some(Search::new(query, regex));
+        Ok(())
+    }
+}
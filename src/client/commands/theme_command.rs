@@ -0,0 +1,62 @@
+use anyhow::Result;
+
+use crate::{
+    command_adapter::{CommandAdapter, CommandContext},
+    config::save_theme,
+    message::add_chat_message,
+    theme::{list_themes, set_theme, Theme, THEME},
+};
+
+pub struct ThemeCommand;
+
+impl CommandAdapter for ThemeCommand {
+    fn command_name(&self) -> &'static str {
+        "/theme"
+    }
+
+    fn description(&self) -> &'static str {
+        "切换界面主题，不带参数时列出可用主题"
+    }
+
+    fn usage(&self) -> &'static str {
+        "/theme [主题名]"
+    }
+
+    fn process(&self, args: &[&str], context: CommandContext<'_>) -> Result<()> {
+        let Some(name) = args.first() else {
+            let current = THEME.read().unwrap().name.clone();
+            add_chat_message(format!(
+                "当前主题: {}，可用主题: {}",
+                current,
+                list_themes().join(", ")
+            ));
+            return Ok(());
+        };
+
+        let theme = match Theme::load(name) {
+            Ok(theme) => theme,
+            Err(e) => {
+                add_chat_message(format!("主题 {} 加载失败: {}", name, e));
+                return Ok(());
+            }
+        };
+        context.app.chat_input.set_style(theme.input_style());
+        set_theme(theme);
+
+        // Remember the choice for the next start
+        if let Err(e) = save_theme(name) {
+            add_chat_message(format!("主题已切换为 {}，但保存失败: {:?}", name, e));
+        } else {
+            add_chat_message(format!("主题已切换为 {}", name));
+        }
+        Ok(())
+    }
+
+    fn complete(&self, args: &[&str]) -> Vec<String> {
+        if args.is_empty() {
+            list_themes()
+        } else {
+            Vec::new()
+        }
+    }
+}
@@ -0,0 +1,79 @@
+use std::fs;
+
+use anyhow::Result;
+
+use crate::{
+    command_adapter::{CommandAdapter, CommandContext},
+    export::{fingerprint, Manifest},
+    key::KEY_MANAGER,
+    message::add_chat_message,
+    service::ClientManager,
+};
+
+pub struct VerifyExportCommand;
+
+impl CommandAdapter for VerifyExportCommand {
+    fn command_name(&self) -> &'static str {
+        "/verify-export"
+    }
+
+    fn description(&self) -> &'static str {
+        "用签名清单检查导出的聊天记录是否被修改"
+    }
+
+    fn usage(&self) -> &'static str {
+        "/verify-export <路径>"
+    }
+
+    fn process(&self, args: &[&str], _context: CommandContext<'_>) -> Result<()> {
+        let Some(path) = args.first() else {
+            add_chat_message(format!("用法: {}", self.usage()));
+            return Ok(());
+        };
+
+        let manifest = match Manifest::load(path) {
+            Ok(manifest) => manifest,
+            Err(e) => {
+                add_chat_message(format!("无法读取签名清单 {}: {}", Manifest::path(path), e));
+                return Ok(());
+            }
+        };
+        let content = match fs::read(path) {
+            Ok(content) => content,
+            Err(e) => {
+                add_chat_message(format!("无法读取 {}: {}", path, e));
+                return Ok(());
+            }
+        };
+        if let Err(e) = manifest.verify(&content) {
+            add_chat_message(format!("校验失败: {}", e));
+            return Ok(());
+        }
+
+        // the manifest carries its own key, only a key we already know says who signed
+        let public_key = manifest.public_key_bytes()?;
+        let fingerprint = fingerprint(&public_key);
+        let own_key = KEY_MANAGER
+            .read()
+            .unwrap()
+            .as_ref()
+            .and_then(|manager| manager.profile.as_ref())
+            .is_some_and(|profile| profile.dilithium_pk == public_key);
+        let signer = if own_key {
+            Some(format!("{}（当前身份）", manifest.signer))
+        } else {
+            ClientManager::find_by_dilithium_pk(&public_key).map(|client| client.name)
+        };
+        match signer {
+            Some(signer) => add_chat_message(format!(
+                "校验通过: {} 条消息，由 {} 签名，密钥指纹 {}",
+                manifest.messages, signer, fingerprint
+            )),
+            None => add_chat_message(format!(
+                "未知密钥: 密钥 {} 不属于当前身份或已知用户，无法确认由 {} 签名",
+                fingerprint, manifest.signer
+            )),
+        }
+        Ok(())
+    }
+}
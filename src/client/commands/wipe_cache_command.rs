@@ -0,0 +1,34 @@
+use anyhow::Result;
+
+use crate::{
+    cache::CacheManager,
+    command_adapter::{CommandAdapter, CommandContext},
+    message::add_chat_message,
+};
+
+pub struct WipeCacheCommand;
+
+impl CommandAdapter for WipeCacheCommand {
+    fn command_name(&self) -> &'static str {
+        "/wipe-cache"
+    }
+
+    fn description(&self) -> &'static str {
+        "删除当前身份在本地缓存的所有消息"
+    }
+
+    fn usage(&self) -> &'static str {
+        "/wipe-cache"
+    }
+
+    fn process(&self, _args: &[&str], _context: CommandContext<'_>) -> Result<()> {
+        match CacheManager::wipe() {
+            Ok(removed) => add_chat_message(format!(
+                "已清除本地缓存（{} 个服务器），屏幕上的消息不受影响",
+                removed
+            )),
+            Err(e) => add_chat_message(format!("清除本地缓存失败: {}", e)),
+        }
+        Ok(())
+    }
+}
@@ -1,11 +1,130 @@
 use lazy_static::lazy_static;
 use orwell::shared::config::{Config, ConfigError};
 use serde::{Deserialize, Serialize};
-use std::sync::RwLock;
+use std::{
+    collections::HashMap,
+    path::Path,
+    sync::{Mutex, RwLock},
+    time::{Duration, Instant},
+};
+
+/// Which chat messages raise a desktop notification
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum NotificationMode {
+    #[default]
+    All,
+    /// Only messages that @mention us
+    Mentions,
+}
+
+/// Escape sequence written to the terminal for a notification, which
+/// reaches the local machine even when the client runs over SSH
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum TerminalNotification {
+    #[default]
+    Off,
+    Bell,
+    /// iTerm2, WezTerm, Windows Terminal and others
+    Osc9,
+    /// rxvt-unicode, foot, VTE based terminals
+    Osc777,
+}
+
+/// Which events notify and how, in `[notifications]`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct NotificationConfig {
+    /// Show a desktop notification
+    pub desktop: bool,
+    pub terminal: TerminalNotification,
+    /// Chat messages, limited by `notification_mode`
+    pub messages: bool,
+    /// Messages that @mention us
+    pub mentions: bool,
+    /// Users coming online or going offline
+    pub presence: bool,
+    /// Users entering or leaving AFK
+    pub afk: bool,
+}
+
+impl Default for NotificationConfig {
+    fn default() -> Self {
+        Self {
+            desktop: true,
+            terminal: TerminalNotification::Off,
+            messages: true,
+            mentions: true,
+            presence: true,
+            afk: true,
+        }
+    }
+}
+
+/// Key chords of one action in `[keybindings]`, an empty list unbinds it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum KeyBinding {
+    One(String),
+    Many(Vec<String>),
+}
+
+impl KeyBinding {
+    pub fn chords(&self) -> Vec<String> {
+        match self {
+            KeyBinding::One(chord) => vec![chord.clone()],
+            KeyBinding::Many(chords) => chords.clone(),
+        }
+    }
+}
+
+/// Pane sizes and visibility, changed from the keyboard and saved back
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LayoutConfig {
+    /// Width of the state and debug column in percent
+    pub side_width: u16,
+    /// Height of the debug pane in percent of the side column
+    pub debug_height: u16,
+    pub show_side: bool,
+    pub show_debug: bool,
+}
+
+/// Layout changes are written once the keys have been left alone this long
+const LAYOUT_SAVE_DELAY: Duration = Duration::from_secs(2);
+
+impl Default for LayoutConfig {
+    fn default() -> Self {
+        Self {
+            side_width: 20,
+            debug_height: 50,
+            show_side: true,
+            show_debug: true,
+        }
+    }
+}
 
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
 pub struct ClientConfig {
     pub server_url: Option<String>,
+    pub notification_mode: Option<NotificationMode>,
+    pub notifications: Option<NotificationConfig>,
+    /// Tell senders when we have read their messages, on unless set to false
+    pub read_receipts: Option<bool>,
+    /// Action name to key chords, replacing the defaults of that action
+    pub keybindings: Option<HashMap<String, KeyBinding>>,
+    /// Built-in theme or a file in ./themes, without the .toml
+    pub theme: Option<String>,
+    /// Force RGB colors on or off instead of checking COLORTERM
+    pub truecolor: Option<bool>,
+    pub layout: Option<LayoutConfig>,
+    /// Keep received messages in an encrypted local cache, on unless set to false
+    pub cache: Option<bool>,
+    /// Most messages kept in the cache of one server
+    pub cache_max_messages: Option<usize>,
+    /// Largest size of the cache of one server in KiB
+    pub cache_max_kib: Option<usize>,
 }
 
 impl Config for ClientConfig {
@@ -14,13 +133,34 @@ impl Config for ClientConfig {
     }
 }
 
+/// What the client saves by itself, kept out of orwell-client.toml so
+/// writing it never drops the comments there
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct ClientState {
+    pub layout: Option<LayoutConfig>,
+    /// Picked with `/theme`, takes the place of `theme` in the config
+    pub theme: Option<String>,
+}
+
+impl Config for ClientState {
+    fn config_file_name() -> &'static str {
+        "./orwell-client.state.toml"
+    }
+}
+
 lazy_static! {
     static ref CONFIG: RwLock<ClientConfig> = RwLock::new(ClientConfig::load().unwrap_or_default());
+    static ref CLIENT_STATE: RwLock<ClientState> = RwLock::new(load_state());
+    /// When the layout last changed, while it waits to be written
+    static ref LAYOUT_CHANGED: Mutex<Option<Instant>> = Mutex::new(None);
 }
 
-/// Get a copy of current configuration
-pub fn get_config() -> ClientConfig {
-    CONFIG.read().unwrap().clone()
+/// `Config::load` would write an empty state file on the first start
+fn load_state() -> ClientState {
+    if !Path::new(ClientState::config_file_name()).exists() {
+        return ClientState::default();
+    }
+    ClientState::load().unwrap_or_default()
 }
 
 /// Convenience helper to fetch the auto-connect URL if present.
@@ -28,16 +168,93 @@ pub fn get_server_url() -> Option<String> {
     CONFIG.read().unwrap().server_url.clone()
 }
 
+pub fn get_notification_mode() -> NotificationMode {
+    CONFIG.read().unwrap().notification_mode.unwrap_or_default()
+}
+
+pub fn get_notifications() -> NotificationConfig {
+    CONFIG.read().unwrap().notifications.unwrap_or_default()
+}
+
+pub fn get_read_receipts() -> bool {
+    CONFIG.read().unwrap().read_receipts.unwrap_or(true)
+}
+
+pub fn get_keybindings() -> HashMap<String, KeyBinding> {
+    CONFIG
+        .read()
+        .unwrap()
+        .keybindings
+        .clone()
+        .unwrap_or_default()
+}
+
+/// The theme picked with `/theme`, or the one set in the config
+pub fn get_theme() -> Option<String> {
+    CLIENT_STATE
+        .read()
+        .unwrap()
+        .theme
+        .clone()
+        .or_else(|| CONFIG.read().unwrap().theme.clone())
+}
+
+/// Remember the theme for the next start
+pub fn save_theme(name: &str) -> Result<(), ConfigError> {
+    let mut state = CLIENT_STATE.write().unwrap();
+    state.theme = Some(name.to_string());
+    state.save()
+}
+
+pub fn get_truecolor() -> Option<bool> {
+    CONFIG.read().unwrap().truecolor
+}
+
+/// The saved layout, or `[layout]` from the config until one is saved
+pub fn get_layout() -> LayoutConfig {
+    CLIENT_STATE
+        .read()
+        .unwrap()
+        .layout
+        .or(CONFIG.read().unwrap().layout)
+        .unwrap_or_default()
+}
+
+pub fn get_cache_enabled() -> bool {
+    CONFIG.read().unwrap().cache.unwrap_or(true)
+}
+
+/// Message count and byte limits of one server's cache
+pub fn get_cache_limits() -> (usize, usize) {
+    let config = CONFIG.read().unwrap();
+    (
+        config.cache_max_messages.unwrap_or(2000),
+        config.cache_max_kib.unwrap_or(4096) * 1024,
+    )
+}
+
+/// Remember the pane layout for the next start, [`flush_layout`] writes it
+pub fn save_layout(layout: LayoutConfig) {
+    CLIENT_STATE.write().unwrap().layout = Some(layout);
+    *LAYOUT_CHANGED.lock().unwrap() = Some(Instant::now());
+}
+
+/// Write a changed layout once it settled, `force` writes it right away
+pub fn flush_layout(force: bool) -> Result<(), ConfigError> {
+    let mut changed = LAYOUT_CHANGED.lock().unwrap();
+    match *changed {
+        Some(at) if force || at.elapsed() >= LAYOUT_SAVE_DELAY => {
+            *changed = None;
+            drop(changed);
+            CLIENT_STATE.read().unwrap().save()
+        }
+        _ => Ok(()),
+    }
+}
+
 /// Reload configuration from file
 pub fn reload_config() -> Result<(), ConfigError> {
     let new_config = ClientConfig::load()?;
     *CONFIG.write().unwrap() = new_config;
     Ok(())
 }
-
-/// Save current configuration to file
-pub fn save_config(config: &ClientConfig) -> Result<(), ConfigError> {
-    config.save()?;
-    *CONFIG.write().unwrap() = config.clone();
-    Ok(())
-}
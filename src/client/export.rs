@@ -0,0 +1,449 @@
+use std::{fmt::Write as _, fs, path::Path};
+
+use anyhow::{anyhow, Result};
+use chrono::{FixedOffset, NaiveDate, NaiveDateTime, TimeZone};
+use orwell::{
+    pb::orwell::Profile,
+    shared::{encryption::Encryption, helper::get_now_timestamp},
+};
+use ratatui::style::Color;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::message::{Line, TimeFormat};
+
+/// File formats `/export-chat` writes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExportFormat {
+    Txt,
+    Json,
+    Html,
+}
+
+impl ExportFormat {
+    pub fn parse(name: &str) -> Option<Self> {
+        match name.to_lowercase().as_str() {
+            "txt" | "text" => Some(Self::Txt),
+            "json" => Some(Self::Json),
+            "html" | "htm" => Some(Self::Html),
+            _ => None,
+        }
+    }
+
+    /// Guess from the extension of `path`, plain text otherwise
+    pub fn from_path(path: &str) -> Self {
+        Path::new(path)
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .and_then(Self::parse)
+            .unwrap_or(Self::Txt)
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Txt => "txt",
+            Self::Json => "json",
+            Self::Html => "html",
+        }
+    }
+}
+
+/// One exported message, taken from a chat line
+pub struct ExportedMessage {
+    pub timestamp: u64,
+    pub time: String,
+    pub id: Option<String>,
+    pub sender: String,
+    pub sender_id: Option<String>,
+    /// `#rrggbb` when the sender has an RGB colour
+    pub color: Option<String>,
+    pub text: String,
+    pub edited: bool,
+    pub deleted: bool,
+}
+
+impl ExportedMessage {
+    pub fn from_line(line: &Line) -> Self {
+        let color = match line.sender().style().fg {
+            Some(Color::Rgb(r, g, b)) => Some(format!("#{:02x}{:02x}{:02x}", r, g, b)),
+            _ => None,
+        };
+        Self {
+            timestamp: line.timestamp(),
+            time: line.format_timestamp(TimeFormat::Full),
+            id: line.id().map(str::to_string),
+            sender: line.sender().content().to_string(),
+            sender_id: line.sender_id().map(str::to_string),
+            color,
+            text: line.source_text(),
+            edited: line.is_edited(),
+            deleted: line.is_deleted(),
+        }
+    }
+}
+
+/// Lower bound for `--since`, either a date or an age such as `90m`, `12h` or `7d`
+pub fn parse_since(value: &str, now: u64) -> Result<u64> {
+    let units = [
+        ('s', 1_000),
+        ('m', 60_000),
+        ('h', 3_600_000),
+        ('d', 86_400_000),
+    ];
+    for (suffix, millis) in units {
+        if let Some(amount) = value.strip_suffix(suffix) {
+            if let Ok(amount) = amount.parse::<u64>() {
+                return Ok(now.saturating_sub(amount.saturating_mul(millis)));
+            }
+        }
+    }
+
+    // dates are read in the same UTC+8 the chat shows
+    let offset = FixedOffset::east_opt(8 * 3600).unwrap();
+    let datetime = NaiveDateTime::parse_from_str(value, "%Y-%m-%dT%H:%M")
+        .or_else(|_| NaiveDate::parse_from_str(value, "%Y-%m-%d").map(|date| date.into()))
+        .map_err(|_| {
+            anyhow!(
+                "无法识别的时间: {}，请使用 2026-01-31、2026-01-31T08:00 或 12h",
+                value
+            )
+        })?;
+    let datetime = offset
+        .from_local_datetime(&datetime)
+        .single()
+        .ok_or_else(|| anyhow!("无法识别的时间: {}", value))?;
+    Ok(datetime.timestamp_millis().max(0) as u64)
+}
+
+pub fn render(messages: &[ExportedMessage], format: ExportFormat) -> String {
+    match format {
+        ExportFormat::Txt => render_txt(messages),
+        ExportFormat::Json => render_json(messages),
+        ExportFormat::Html => render_html(messages),
+    }
+}
+
+fn render_txt(messages: &[ExportedMessage]) -> String {
+    let mut out = String::new();
+    for message in messages {
+        let text = if message.deleted {
+            "(已删除)".to_string()
+        } else if message.edited {
+            format!("{} (已编辑)", message.text)
+        } else {
+            message.text.clone()
+        };
+        // continuation lines are indented under the text
+        let indent = format!("\n{}", " ".repeat(message.time.len() + 1));
+        let _ = writeln!(
+            out,
+            "{} {} | {}",
+            message.time,
+            message.sender,
+            text.replace('\n', &indent)
+        );
+    }
+    out
+}
+
+fn render_json(messages: &[ExportedMessage]) -> String {
+    let optional =
+        |value: &Option<String>| value.as_deref().map_or("null".to_string(), json_string);
+    let mut out = String::from("[\n");
+    for (index, message) in messages.iter().enumerate() {
+        let _ = write!(
+            out,
+            "  {{\"timestamp\": {}, \"time\": {}, \"id\": {}, \"sender\": {}, \"sender_id\": {}, \
+             \"color\": {}, \"text\": {}, \"edited\": {}, \"deleted\": {}}}",
+            message.timestamp,
+            json_string(&message.time),
+            optional(&message.id),
+            json_string(&message.sender),
+            optional(&message.sender_id),
+            optional(&message.color),
+            json_string(&message.text),
+            message.edited,
+            message.deleted,
+        );
+        out.push_str(if index + 1 < messages.len() {
+            ",\n"
+        } else {
+            "\n"
+        });
+    }
+    out.push_str("]\n");
+    out
+}
+
+fn json_string(value: &str) -> String {
+    let mut out = String::with_capacity(value.len() + 2);
+    out.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => {
+                let _ = write!(out, "\\u{:04x}", c as u32);
+            }
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+fn render_html(messages: &[ExportedMessage]) -> String {
+    let mut out = String::from(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>Orwell 聊天记录</title>\n\
+         <style>\nbody { font-family: monospace; background: #1e1e2e; color: #cdd6f4; }\n\
+         .time { color: #6c7086; }\n.meta { color: #a6adc8; font-style: italic; }\n\
+         .text { white-space: pre-wrap; }\n</style>\n</head>\n<body>\n",
+    );
+    for message in messages {
+        let style = message.color.as_ref().map_or(String::new(), |color| {
+            format!(" style=\"color: {}\"", color)
+        });
+        let text = if message.deleted {
+            "<span class=\"meta\">(已删除)</span>".to_string()
+        } else {
+            let mut text = format!("<span class=\"text\">{}</span>", html_escape(&message.text));
+            if message.edited {
+                text.push_str(" <span class=\"meta\">(已编辑)</span>");
+            }
+            text
+        };
+        let _ = writeln!(
+            out,
+            "<div><span class=\"time\">{}</span> <b{}>{}</b> | {}</div>",
+            html_escape(&message.time),
+            style,
+            html_escape(&message.sender),
+            text
+        );
+    }
+    out.push_str("</body>\n</html>\n");
+    out
+}
+
+fn html_escape(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn from_hex(hex: &str) -> Result<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) {
+        return Err(anyhow!("十六进制长度有误"));
+    }
+    // by bytes, slicing the str would panic inside a multibyte character
+    hex.as_bytes()
+        .chunks(2)
+        .map(|pair| {
+            std::str::from_utf8(pair)
+                .ok()
+                .filter(|pair| pair.bytes().all(|byte| byte.is_ascii_hexdigit()))
+                .and_then(|pair| u8::from_str_radix(pair, 16).ok())
+                .ok_or_else(|| anyhow!("十六进制有误"))
+        })
+        .collect()
+}
+
+/// Written next to an export as `<file>.manifest`, the signature covers
+/// every other field so neither the export nor the manifest can be changed
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Manifest {
+    pub file: String,
+    pub format: String,
+    pub messages: usize,
+    pub sha256: String,
+    pub exported_at: u64,
+    pub signer: String,
+    pub public_key: String,
+    pub signature: String,
+}
+
+impl Manifest {
+    pub fn path(export_path: &str) -> String {
+        format!("{}.manifest", export_path)
+    }
+
+    pub fn sign(
+        export_path: &str,
+        content: &[u8],
+        format: ExportFormat,
+        messages: usize,
+        profile: &Profile,
+    ) -> Result<Self> {
+        let file = Path::new(export_path)
+            .file_name()
+            .and_then(|name| name.to_str())
+            .unwrap_or(export_path)
+            .to_string();
+        let mut manifest = Self {
+            file,
+            format: format.name().to_string(),
+            messages,
+            sha256: to_hex(&Sha256::digest(content)),
+            exported_at: get_now_timestamp(),
+            signer: profile.name.clone(),
+            public_key: to_hex(&profile.dilithium_pk),
+            signature: String::new(),
+        };
+        let signature = Encryption::dilithium_sign(&manifest.signed_data(), &profile.dilithium_sk)?;
+        manifest.signature = to_hex(&signature);
+        Ok(manifest)
+    }
+
+    fn signed_data(&self) -> Vec<u8> {
+        format!(
+            "orwell-export\n{}\n{}\n{}\n{}\n{}\n{}\n{}",
+            self.file,
+            self.format,
+            self.messages,
+            self.sha256,
+            self.exported_at,
+            self.signer,
+            self.public_key
+        )
+        .into_bytes()
+    }
+
+    pub fn save(&self, export_path: &str) -> Result<()> {
+        fs::write(Self::path(export_path), toml::to_string_pretty(self)?)?;
+        Ok(())
+    }
+
+    pub fn load(export_path: &str) -> Result<Self> {
+        let content = fs::read_to_string(Self::path(export_path))?;
+        Ok(toml::from_str(&content)?)
+    }
+
+    /// Check the signature and that `content` is what was signed
+    pub fn verify(&self, content: &[u8]) -> Result<()> {
+        let signature = from_hex(&self.signature)?;
+        let public_key = from_hex(&self.public_key)?;
+        if !Encryption::dilithium_verify(&self.signed_data(), &public_key, &signature)? {
+            return Err(anyhow!("清单签名无效"));
+        }
+        if to_hex(&Sha256::digest(content)) != self.sha256 {
+            return Err(anyhow!("导出文件已被修改"));
+        }
+        Ok(())
+    }
+
+    pub fn public_key_bytes(&self) -> Result<Vec<u8>> {
+        from_hex(&self.public_key)
+    }
+}
+
+/// First 8 bytes of the sha256 of a key in groups of four hex digits, short
+/// enough to compare with the signer out of band
+pub fn fingerprint(public_key: &[u8]) -> String {
+    let hex = to_hex(&Sha256::digest(public_key)[..8]);
+    hex.as_bytes()
+        .chunks(4)
+        .map(|group| String::from_utf8_lossy(group))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use crystals_dilithium::dilithium5;
+
+    use super::*;
+
+    fn message(text: &str) -> ExportedMessage {
+        ExportedMessage {
+            timestamp: 1_700_000_000_000,
+            time: "2023/11/15 06:13:20.000".to_string(),
+            id: Some("m1".to_string()),
+            sender: "a<b>".to_string(),
+            sender_id: None,
+            color: Some("#ff8800".to_string()),
+            text: text.to_string(),
+            edited: true,
+            deleted: false,
+        }
+    }
+
+    #[test]
+    fn formats_escape_content() {
+        let messages = [message("say \"hi\"\n<script>")];
+
+        let txt = render(&messages, ExportFormat::Txt);
+        assert!(txt.starts_with("2023/11/15 06:13:20.000 a<b> | say \"hi\"\n"));
+        assert!(txt.contains("<script> (已编辑)"));
+
+        let json = render(&messages, ExportFormat::Json);
+        assert!(json.contains(r#""text": "say \"hi\"\n<script>""#));
+        assert!(json.contains(r#""sender_id": null"#));
+        assert!(json.contains(r##""color": "#ff8800""##));
+
+        let html = render(&messages, ExportFormat::Html);
+        assert!(html.contains("<b style=\"color: #ff8800\">a&lt;b&gt;</b>"));
+        assert!(html.contains("say &quot;hi&quot;\n&lt;script&gt;"));
+        assert!(!html.contains("<script>"));
+    }
+
+    #[test]
+    fn since_accepts_ages_and_dates() {
+        let now = 10 * 86_400_000;
+        assert_eq!(parse_since("90m", now).unwrap(), now - 90 * 60_000);
+        assert_eq!(parse_since("2d", now).unwrap(), now - 2 * 86_400_000);
+        // midnight in UTC+8 is 16:00 the day before in UTC
+        assert_eq!(parse_since("1970-01-02", now).unwrap(), 16 * 3_600_000);
+        assert_eq!(
+            parse_since("1970-01-02T09:30", now).unwrap(),
+            (25 * 60 + 30) * 60_000
+        );
+        assert!(parse_since("yesterday", now).is_err());
+    }
+
+    #[test]
+    fn manifest_detects_tampering() {
+        let keys = dilithium5::Keypair::generate(None);
+        let profile = Profile {
+            name: "alice".to_string(),
+            dilithium_pk: keys.public.bytes.to_vec(),
+            dilithium_sk: keys.secret.bytes.to_vec(),
+            ..Default::default()
+        };
+        let content = b"2023/11/15 alice | hello\n";
+        let manifest =
+            Manifest::sign("/tmp/chat.txt", content, ExportFormat::Txt, 1, &profile).unwrap();
+        assert_eq!(manifest.file, "chat.txt");
+        assert_eq!(manifest.signer, "alice");
+        manifest.verify(content).unwrap();
+
+        assert!(manifest.verify(b"2023/11/15 alice | hell0\n").is_err());
+        let mut forged = manifest.clone();
+        forged.messages = 2;
+        assert!(forged.verify(content).is_err());
+
+        let reloaded: Manifest = toml::from_str(&toml::to_string(&manifest).unwrap()).unwrap();
+        reloaded.verify(content).unwrap();
+    }
+
+    #[test]
+    fn hex_rejects_non_ascii_and_signs() {
+        assert_eq!(from_hex("00ff7a").unwrap(), [0x00, 0xff, 0x7a]);
+        assert_eq!(from_hex(&to_hex(b"orwell")).unwrap(), b"orwell");
+        assert!(from_hex("abc").is_err());
+        assert!(from_hex("+f").is_err());
+        assert!(from_hex("zz").is_err());
+        // one two byte character, even length but not hex
+        assert!(from_hex("é").is_err());
+        assert!(from_hex("0é0").is_err());
+        assert_eq!(fingerprint(b"key").len(), 19);
+    }
+}
@@ -17,6 +17,7 @@ use crate::{
 };
 
 const PROFILE_FOLDER: &str = "./profiles";
+const CACHE_KEY_SALT: &str = "OrwellLocalMessageCache";
 
 lazy_static! {
     pub static ref KEY_MANAGER: RwLock<Option<KeyManager>> = RwLock::new(None);
@@ -24,11 +25,37 @@ lazy_static! {
 
 pub struct KeyManager {
     pub profile: Option<Profile>,
+    /// Encrypts the local message cache, derived from the profile password
+    pub cache_key: Option<Vec<u8>>,
 }
 
 impl KeyManager {
     pub fn new() -> Self {
-        Self { profile: None }
+        Self {
+            profile: None,
+            cache_key: None,
+        }
+    }
+
+    /// Names of the profiles saved in the profile folder
+    pub fn list_profiles() -> Vec<String> {
+        let Ok(entries) = fs::read_dir(PROFILE_FOLDER) else {
+            return Vec::new();
+        };
+        entries
+            .filter_map(|entry| {
+                let path = entry.ok()?.path();
+                if path.extension()? != "orwell" {
+                    return None;
+                }
+                Some(path.file_stem()?.to_str()?.to_string())
+            })
+            .collect()
+    }
+
+    /// Separate key for the message cache so it never reuses the profile key
+    fn derive_cache_key(profile_key: &[u8]) -> Vec<u8> {
+        Encryption::hkdf_derive_key(profile_key, CACHE_KEY_SALT.as_bytes()).to_vec()
     }
 
     pub fn get_profile_path(name: &str) -> String {
@@ -93,6 +120,7 @@ impl KeyManager {
                 dilithium_pk: keys.public.bytes.to_vec(),
                 dilithium_sk: keys.secret.bytes.to_vec(),
             }),
+            cache_key: Some(Self::derive_cache_key(&key)),
         };
 
         let mut key_manager = KEY_MANAGER.write().unwrap();
@@ -161,6 +189,7 @@ impl KeyManager {
         let mut key_manager = KEY_MANAGER.write().unwrap();
         key_manager.replace(Self {
             profile: Some(key_pair),
+            cache_key: Some(Self::derive_cache_key(&key)),
         });
         drop(key_manager);
 
@@ -0,0 +1,460 @@
+use std::{collections::HashMap, fmt};
+
+use anyhow::{anyhow, Result};
+use ratatui::crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+
+use crate::config::KeyBinding;
+
+/// Something a key chord can trigger
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Action {
+    Quit,
+    ToggleTimeFormat,
+    Complete,
+    CompletePrev,
+    FocusInput,
+    Send,
+    Newline,
+    Up,
+    Down,
+    ScrollUp,
+    ScrollDown,
+    PageUp,
+    PageDown,
+    LineStart,
+    LineEnd,
+    CharLeft,
+    CharRight,
+    WordLeft,
+    WordRight,
+    HistoryPrev,
+    HistoryNext,
+    Backspace,
+    Delete,
+    KillWordBefore,
+    KillWordAfter,
+    KillLineStart,
+    KillLineEnd,
+    Yank,
+    YankPop,
+    ToggleSide,
+    ToggleDebug,
+    UserList,
+    SideWider,
+    SideNarrower,
+    DebugTaller,
+    DebugShorter,
+    Search,
+    JumpUnread,
+}
+
+impl Action {
+    /// Every action in the order `/keys` lists them
+    pub const ALL: [Action; 38] = [
+        Action::Quit,
+        Action::ToggleTimeFormat,
+        Action::Complete,
+        Action::CompletePrev,
+        Action::FocusInput,
+        Action::Send,
+        Action::Newline,
+        Action::Up,
+        Action::Down,
+        Action::ScrollUp,
+        Action::ScrollDown,
+        Action::PageUp,
+        Action::PageDown,
+        Action::LineStart,
+        Action::LineEnd,
+        Action::CharLeft,
+        Action::CharRight,
+        Action::WordLeft,
+        Action::WordRight,
+        Action::HistoryPrev,
+        Action::HistoryNext,
+        Action::Backspace,
+        Action::Delete,
+        Action::KillWordBefore,
+        Action::KillWordAfter,
+        Action::KillLineStart,
+        Action::KillLineEnd,
+        Action::Yank,
+        Action::YankPop,
+        Action::ToggleSide,
+        Action::ToggleDebug,
+        Action::UserList,
+        Action::SideWider,
+        Action::SideNarrower,
+        Action::DebugTaller,
+        Action::DebugShorter,
+        Action::Search,
+        Action::JumpUnread,
+    ];
+
+    /// Name used in the `[keybindings]` table
+    pub fn name(&self) -> &'static str {
+        match self {
+            Action::Quit => "quit",
+            Action::ToggleTimeFormat => "toggle_time_format",
+            Action::Complete => "complete",
+            Action::CompletePrev => "complete_prev",
+            Action::FocusInput => "focus_input",
+            Action::Send => "send",
+            Action::Newline => "newline",
+            Action::Up => "up",
+            Action::Down => "down",
+            Action::ScrollUp => "scroll_up",
+            Action::ScrollDown => "scroll_down",
+            Action::PageUp => "page_up",
+            Action::PageDown => "page_down",
+            Action::LineStart => "line_start",
+            Action::LineEnd => "line_end",
+            Action::CharLeft => "char_left",
+            Action::CharRight => "char_right",
+            Action::WordLeft => "word_left",
+            Action::WordRight => "word_right",
+            Action::HistoryPrev => "history_prev",
+            Action::HistoryNext => "history_next",
+            Action::Backspace => "backspace",
+            Action::Delete => "delete",
+            Action::KillWordBefore => "kill_word_before",
+            Action::KillWordAfter => "kill_word_after",
+            Action::KillLineStart => "kill_line_start",
+            Action::KillLineEnd => "kill_line_end",
+            Action::Yank => "yank",
+            Action::YankPop => "yank_pop",
+            Action::ToggleSide => "toggle_side",
+            Action::ToggleDebug => "toggle_debug",
+            Action::UserList => "user_list",
+            Action::SideWider => "side_wider",
+            Action::SideNarrower => "side_narrower",
+            Action::DebugTaller => "debug_taller",
+            Action::DebugShorter => "debug_shorter",
+            Action::Search => "search",
+            Action::JumpUnread => "jump_unread",
+        }
+    }
+
+    pub fn description(&self) -> &'static str {
+        match self {
+            Action::Quit => "退出（需确认）",
+            Action::ToggleTimeFormat => "切换时间格式",
+            Action::Complete => "补全命令、参数或 @提及",
+            Action::CompletePrev => "反向切换补全候选",
+            Action::FocusInput => "聚焦输入框",
+            Action::Send => "发送消息",
+            Action::Newline => "换行",
+            Action::Up => "上一行，首行时调出上一条历史",
+            Action::Down => "下一行，末行时调出下一条历史",
+            Action::ScrollUp => "聊天向上滚动一行",
+            Action::ScrollDown => "聊天向下滚动一行",
+            Action::PageUp => "聊天向上翻页",
+            Action::PageDown => "聊天向下翻页",
+            Action::LineStart => "移到行首",
+            Action::LineEnd => "移到行尾",
+            Action::CharLeft => "左移一个字符",
+            Action::CharRight => "右移一个字符",
+            Action::WordLeft => "左移一个词",
+            Action::WordRight => "右移一个词",
+            Action::HistoryPrev => "上一条历史",
+            Action::HistoryNext => "下一条历史",
+            Action::Backspace => "删除前一个字符",
+            Action::Delete => "删除后一个字符",
+            Action::KillWordBefore => "剪切前一个词",
+            Action::KillWordAfter => "剪切后一个词",
+            Action::KillLineStart => "剪切到行首",
+            Action::KillLineEnd => "剪切到行尾",
+            Action::Yank => "粘贴剪切的内容",
+            Action::YankPop => "换成更早剪切的内容",
+            Action::ToggleSide => "显示或隐藏侧栏（全屏聊天）",
+            Action::ToggleDebug => "显示或隐藏调试面板",
+            Action::UserList => "弹出用户列表",
+            Action::SideWider => "加宽侧栏",
+            Action::SideNarrower => "收窄侧栏",
+            Action::DebugTaller => "调高调试面板",
+            Action::DebugShorter => "调矮调试面板",
+            Action::Search => "搜索消息",
+            Action::JumpUnread => "跳到第一条未读消息",
+        }
+    }
+
+    fn from_name(name: &str) -> Option<Action> {
+        Action::ALL.into_iter().find(|action| action.name() == name)
+    }
+
+    fn default_chords(&self) -> &'static [&'static str] {
+        match self {
+            Action::Quit => &["esc", "ctrl+z"],
+            Action::ToggleTimeFormat => &["ctrl+d"],
+            Action::Complete => &["tab"],
+            Action::CompletePrev => &["shift+tab"],
+            Action::FocusInput => &["ctrl+i"],
+            Action::Send => &["enter"],
+            // Alt+Enter for terminals that do not report Shift
+            Action::Newline => &["shift+enter", "alt+enter"],
+            Action::Up => &["up"],
+            Action::Down => &["down"],
+            Action::ScrollUp => &["ctrl+up"],
+            Action::ScrollDown => &["ctrl+down"],
+            Action::PageUp => &["pageup"],
+            Action::PageDown => &["pagedown"],
+            Action::LineStart => &["home", "ctrl+a"],
+            Action::LineEnd => &["end", "ctrl+e"],
+            Action::CharLeft => &["left", "ctrl+b"],
+            Action::CharRight => &["right"],
+            Action::WordLeft => &["ctrl+left", "alt+b"],
+            Action::WordRight => &["ctrl+right", "alt+f"],
+            Action::HistoryPrev => &["ctrl+p"],
+            Action::HistoryNext => &["ctrl+n"],
+            Action::Backspace => &["backspace", "ctrl+h"],
+            Action::Delete => &["delete"],
+            Action::KillWordBefore => &["ctrl+w", "alt+backspace"],
+            Action::KillWordAfter => &["alt+d"],
+            Action::KillLineStart => &["ctrl+u"],
+            Action::KillLineEnd => &["ctrl+k"],
+            Action::Yank => &["ctrl+y"],
+            Action::YankPop => &["alt+y"],
+            Action::ToggleSide => &["f4"],
+            Action::ToggleDebug => &["f2"],
+            Action::UserList => &["f3"],
+            Action::SideWider => &["alt+left"],
+            Action::SideNarrower => &["alt+right"],
+            Action::DebugTaller => &["alt+up"],
+            Action::DebugShorter => &["alt+down"],
+            Action::Search => &["ctrl+f"],
+            Action::JumpUnread => &["f5"],
+        }
+    }
+}
+
+/// A key together with its Ctrl, Alt and Shift modifiers
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct KeyChord {
+    code: KeyCode,
+    modifiers: KeyModifiers,
+}
+
+impl KeyChord {
+    fn new(code: KeyCode, modifiers: KeyModifiers) -> Self {
+        let mut modifiers =
+            modifiers & (KeyModifiers::CONTROL | KeyModifiers::ALT | KeyModifiers::SHIFT);
+        let code = match code {
+            // the character already says whether Shift was held
+            KeyCode::Char(c) => {
+                modifiers.remove(KeyModifiers::SHIFT);
+                KeyCode::Char(c.to_ascii_lowercase())
+            }
+            KeyCode::BackTab => {
+                modifiers.insert(KeyModifiers::SHIFT);
+                KeyCode::Tab
+            }
+            code => code,
+        };
+        Self { code, modifiers }
+    }
+
+    pub fn from_event(key: &KeyEvent) -> Self {
+        Self::new(key.code, key.modifiers)
+    }
+
+    /// Parse chords like `ctrl+d`, `shift+enter` or `f2`
+    pub fn parse(chord: &str) -> Result<Self> {
+        let chord = chord.trim().to_lowercase();
+        let mut parts = chord.split('+').collect::<Vec<_>>();
+        // "ctrl++" binds the plus key
+        if chord.ends_with("++") {
+            parts.truncate(parts.len() - 2);
+            parts.push("+");
+        }
+        let key = parts.pop().filter(|key| !key.is_empty());
+        let key = key.ok_or_else(|| anyhow!("按键为空: {}", chord))?;
+
+        let mut modifiers = KeyModifiers::NONE;
+        for modifier in parts {
+            modifiers |= match modifier {
+                "ctrl" | "control" => KeyModifiers::CONTROL,
+                "alt" | "meta" => KeyModifiers::ALT,
+                "shift" => KeyModifiers::SHIFT,
+                _ => return Err(anyhow!("未知的修饰键: {}", modifier)),
+            };
+        }
+
+        let code = match key {
+            "esc" | "escape" => KeyCode::Esc,
+            "enter" | "return" => KeyCode::Enter,
+            "tab" => KeyCode::Tab,
+            "backspace" => KeyCode::Backspace,
+            "delete" | "del" => KeyCode::Delete,
+            "insert" => KeyCode::Insert,
+            "up" => KeyCode::Up,
+            "down" => KeyCode::Down,
+            "left" => KeyCode::Left,
+            "right" => KeyCode::Right,
+            "home" => KeyCode::Home,
+            "end" => KeyCode::End,
+            "pageup" => KeyCode::PageUp,
+            "pagedown" => KeyCode::PageDown,
+            "space" => KeyCode::Char(' '),
+            key if key.chars().count() == 1 => KeyCode::Char(key.chars().next().unwrap()),
+            key => match key.strip_prefix('f').and_then(|n| n.parse::<u8>().ok()) {
+                Some(n) if (1..=24).contains(&n) => KeyCode::F(n),
+                _ => return Err(anyhow!("未知的按键: {}", key)),
+            },
+        };
+        Ok(Self::new(code, modifiers))
+    }
+}
+
+impl fmt::Display for KeyChord {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.modifiers.contains(KeyModifiers::CONTROL) {
+            write!(f, "Ctrl+")?;
+        }
+        if self.modifiers.contains(KeyModifiers::ALT) {
+            write!(f, "Alt+")?;
+        }
+        if self.modifiers.contains(KeyModifiers::SHIFT) {
+            write!(f, "Shift+")?;
+        }
+        match self.code {
+            KeyCode::Char(' ') => write!(f, "Space"),
+            KeyCode::Char(c) => write!(f, "{}", c.to_ascii_uppercase()),
+            KeyCode::F(n) => write!(f, "F{}", n),
+            KeyCode::PageUp => write!(f, "PageUp"),
+            KeyCode::PageDown => write!(f, "PageDown"),
+            code => write!(f, "{:?}", code),
+        }
+    }
+}
+
+/// Key chords mapped to actions, the defaults overridden by `[keybindings]`
+pub struct Keymap {
+    bindings: HashMap<KeyChord, Action>,
+}
+
+impl Keymap {
+    /// Build the keymap from the configured overrides, returns the problems
+    /// found in them as well. A configured action replaces all its default chords.
+    pub fn from_config(overrides: &HashMap<String, KeyBinding>) -> (Self, Vec<String>) {
+        let mut errors = Vec::new();
+        let mut chords = Action::ALL
+            .into_iter()
+            .map(|action| {
+                let defaults = action
+                    .default_chords()
+                    .iter()
+                    .map(|chord| KeyChord::parse(chord).expect("invalid default key chord"))
+                    .collect::<Vec<_>>();
+                (action, defaults)
+            })
+            .collect::<Vec<_>>();
+
+        let mut configured = Vec::new();
+        for (name, binding) in overrides {
+            let Some(action) = Action::from_name(name) else {
+                errors.push(format!("未知的操作: {}", name));
+                continue;
+            };
+            let mut parsed = Vec::new();
+            for chord in binding.chords() {
+                match KeyChord::parse(&chord) {
+                    Ok(chord) => parsed.push(chord),
+                    Err(e) => errors.push(format!("{}: {}", name, e)),
+                }
+            }
+            configured.push((action, parsed));
+        }
+
+        // a configured chord is taken away from whatever default had it
+        for (_, parsed) in &configured {
+            for (_, defaults) in chords.iter_mut() {
+                defaults.retain(|chord| !parsed.contains(chord));
+            }
+        }
+        for (action, parsed) in configured {
+            if let Some((_, current)) = chords.iter_mut().find(|(a, _)| *a == action) {
+                *current = parsed;
+            }
+        }
+
+        let mut bindings = HashMap::new();
+        for (action, chords) in chords {
+            for chord in chords {
+                bindings.insert(chord, action);
+            }
+        }
+        (Self { bindings }, errors)
+    }
+
+    pub fn action(&self, key: &KeyEvent) -> Option<Action> {
+        self.bindings.get(&KeyChord::from_event(key)).copied()
+    }
+
+    /// Chords bound to `action`, sorted for display
+    pub fn chords(&self, action: Action) -> Vec<String> {
+        let mut chords = self
+            .bindings
+            .iter()
+            .filter(|(_, bound)| **bound == action)
+            .map(|(chord, _)| chord.to_string())
+            .collect::<Vec<_>>();
+        chords.sort();
+        chords
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(code: KeyCode, modifiers: KeyModifiers) -> KeyEvent {
+        KeyEvent::new(code, modifiers)
+    }
+
+    #[test]
+    fn parse_chords() {
+        let chord = KeyChord::parse("Ctrl+Shift+Enter").unwrap();
+        assert_eq!(
+            chord,
+            KeyChord::new(KeyCode::Enter, KeyModifiers::CONTROL | KeyModifiers::SHIFT)
+        );
+        assert_eq!(chord.to_string(), "Ctrl+Shift+Enter");
+        assert_eq!(
+            KeyChord::parse("ctrl++").unwrap(),
+            KeyChord::new(KeyCode::Char('+'), KeyModifiers::CONTROL)
+        );
+        assert_eq!(KeyChord::parse("f12").unwrap().to_string(), "F12");
+        assert!(KeyChord::parse("hyper+a").is_err());
+        assert!(KeyChord::parse("ctrl+").is_err());
+        assert!(KeyChord::parse("f25").is_err());
+    }
+
+    #[test]
+    fn overrides_replace_defaults() {
+        let overrides = HashMap::from([
+            ("quit".to_string(), KeyBinding::One("ctrl+q".to_string())),
+            (
+                "yank".to_string(),
+                KeyBinding::Many(vec!["esc".to_string(), "bogus+y".to_string()]),
+            ),
+            ("fly".to_string(), KeyBinding::One("f1".to_string())),
+        ]);
+        let (keymap, errors) = Keymap::from_config(&overrides);
+        assert_eq!(errors.len(), 2);
+
+        let ctrl_q = key(KeyCode::Char('q'), KeyModifiers::CONTROL);
+        let ctrl_z = key(KeyCode::Char('z'), KeyModifiers::CONTROL);
+        let esc = key(KeyCode::Esc, KeyModifiers::NONE);
+        assert_eq!(keymap.action(&ctrl_q), Some(Action::Quit));
+        assert_eq!(keymap.action(&ctrl_z), None);
+        // taken from quit's defaults even though quit was overridden too
+        assert_eq!(keymap.action(&esc), Some(Action::Yank));
+        assert_eq!(keymap.chords(Action::Yank), vec!["Esc".to_string()]);
+
+        let shifted = key(
+            KeyCode::Char('D'),
+            KeyModifiers::CONTROL | KeyModifiers::SHIFT,
+        );
+        assert_eq!(keymap.action(&shifted), Some(Action::ToggleTimeFormat));
+    }
+}
@@ -0,0 +1,326 @@
+use anyhow::Result;
+
+/// Leads the Text payload of a message written in markup. The byte never
+/// occurs in UTF-8, so plain text and the history stored before the flag
+/// existed decode as they always did.
+const MARKUP_FLAG: u8 = 0xff;
+
+/// One piece of a message written in the lightweight markup
+///
+/// Supported: `**bold**`, `*italic*` or `_italic_`, `` `code` ``,
+/// fenced code blocks, `[text](url)` and bare http(s) links.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Segment {
+    Plain(String),
+    Bold(String),
+    Italic(String),
+    Code(String),
+    CodeBlock { lang: String, code: String },
+    Link { text: String, url: String },
+}
+
+/// Split a message into markup segments, anything unclosed stays plain text
+pub fn parse(text: &str) -> Vec<Segment> {
+    let mut segments = Vec::new();
+    let mut plain = String::new();
+    let mut prev = None;
+    let mut rest = text;
+
+    while let Some(c) = rest.chars().next() {
+        if let Some((segment, len)) = match_markup(rest, prev) {
+            if !plain.is_empty() {
+                segments.push(Segment::Plain(std::mem::take(&mut plain)));
+            }
+            segments.push(segment);
+            prev = rest[..len].chars().last();
+            rest = &rest[len..];
+        } else {
+            plain.push(c);
+            prev = Some(c);
+            rest = &rest[c.len_utf8()..];
+        }
+    }
+    if !plain.is_empty() {
+        segments.push(Segment::Plain(plain));
+    }
+    segments
+}
+
+/// Whether sending `text` needs the markup flag
+pub fn has_markup(text: &str) -> bool {
+    parse(text)
+        .iter()
+        .any(|segment| !matches!(segment, Segment::Plain(_)))
+}
+
+/// Text payload of a chat message, flagged when it needs rendering
+pub fn encode_text(text: &str) -> Vec<u8> {
+    let mut data = text.as_bytes().to_vec();
+    if has_markup(text) {
+        data.insert(0, MARKUP_FLAG);
+    }
+    data
+}
+
+/// Text of a Text payload and whether it is written in markup
+pub fn decode_text(data: Vec<u8>) -> Result<(String, bool)> {
+    match data.split_first() {
+        Some((&MARKUP_FLAG, text)) => Ok((String::from_utf8(text.to_vec())?, true)),
+        _ => Ok((String::from_utf8(data)?, false)),
+    }
+}
+
+/// Segment starting at the beginning of `rest` and the bytes it spans
+fn match_markup(rest: &str, prev: Option<char>) -> Option<(Segment, usize)> {
+    let after_word = prev.is_some_and(char::is_alphanumeric);
+
+    if let Some(body) = rest.strip_prefix("```") {
+        let end = body.find("```")?;
+        let inner = &body[..end];
+        let (lang, code) = match inner.split_once('\n') {
+            Some((lang, code)) if !lang.trim().contains(' ') => (lang.trim(), code),
+            _ => ("", inner),
+        };
+        let code = code.strip_suffix('\n').unwrap_or(code);
+        if code.trim().is_empty() {
+            return None;
+        }
+        return Some((
+            Segment::CodeBlock {
+                lang: lang.to_string(),
+                code: code.to_string(),
+            },
+            end + 6,
+        ));
+    }
+
+    if let Some(body) = rest.strip_prefix('`') {
+        let end = body.find('`')?;
+        let inner = &body[..end];
+        if inner.is_empty() || inner.contains('\n') {
+            return None;
+        }
+        return Some((Segment::Code(inner.to_string()), end + 2));
+    }
+
+    if let Some(body) = rest.strip_prefix("**") {
+        let inner = &body[..body.find("**")?];
+        if !is_emphasis(inner) {
+            return None;
+        }
+        return Some((Segment::Bold(inner.to_string()), inner.len() + 4));
+    }
+
+    for marker in ['*', '_'] {
+        let Some(body) = rest.strip_prefix(marker) else {
+            continue;
+        };
+        // snake_case and 2*3*4 are not emphasis
+        if after_word {
+            return None;
+        }
+        let inner = &body[..body.find(marker)?];
+        let next = body[inner.len() + 1..].chars().next();
+        if !is_emphasis(inner) || next.is_some_and(char::is_alphanumeric) {
+            return None;
+        }
+        return Some((Segment::Italic(inner.to_string()), inner.len() + 2));
+    }
+
+    if let Some(body) = rest.strip_prefix('[') {
+        let text_end = body.find("](")?;
+        let text = &body[..text_end];
+        let url_part = &body[text_end + 2..];
+        let url = &url_part[..url_part.find(')')?];
+        if text.is_empty() || text.contains('\n') || !is_url(url) {
+            return None;
+        }
+        return Some((
+            Segment::Link {
+                text: text.to_string(),
+                url: url.to_string(),
+            },
+            text_end + url.len() + 4,
+        ));
+    }
+
+    if !after_word && (rest.starts_with("http://") || rest.starts_with("https://")) {
+        let end = rest.find(char::is_whitespace).unwrap_or(rest.len());
+        // trailing punctuation belongs to the sentence
+        let url = rest[..end].trim_end_matches(['.', ',', ';', ':', '!', '?', ')', '"', '\'']);
+        if !is_url(url) {
+            return None;
+        }
+        return Some((
+            Segment::Link {
+                text: url.to_string(),
+                url: url.to_string(),
+            },
+            url.len(),
+        ));
+    }
+
+    None
+}
+
+fn is_emphasis(inner: &str) -> bool {
+    !inner.is_empty()
+        && !inner.contains('\n')
+        && !inner.starts_with(char::is_whitespace)
+        && !inner.ends_with(char::is_whitespace)
+}
+
+fn is_url(url: &str) -> bool {
+    let host = url
+        .strip_prefix("https://")
+        .or_else(|| url.strip_prefix("http://"));
+    host.is_some_and(|host| !host.is_empty()) && !url.contains(char::is_whitespace)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn plain(text: &str) -> Segment {
+        Segment::Plain(text.to_string())
+    }
+
+    fn link(url: &str) -> Segment {
+        Segment::Link {
+            text: url.to_string(),
+            url: url.to_string(),
+        }
+    }
+
+    #[test]
+    fn text_payload_keeps_plain_text_as_utf8() {
+        // what every Text message looked like before the flag
+        assert_eq!(encode_text("hello"), b"hello");
+        assert_eq!(
+            decode_text(b"hello".to_vec()).unwrap(),
+            ("hello".to_string(), false)
+        );
+        assert_eq!(
+            decode_text("**旧消息**".as_bytes().to_vec()).unwrap(),
+            ("**旧消息**".to_string(), false)
+        );
+
+        let flagged = encode_text("**bold**");
+        assert_eq!(flagged[0], MARKUP_FLAG);
+        assert_eq!(
+            decode_text(flagged).unwrap(),
+            ("**bold**".to_string(), true)
+        );
+        assert!(decode_text(vec![MARKUP_FLAG, 0xc3]).is_err());
+    }
+
+    #[test]
+    fn unclosed_markers_stay_plain() {
+        for text in [
+            "**bold",
+            "*italic",
+            "_italic",
+            "`code",
+            "```rust\nfn main() {}",
+            "[a](b",
+        ] {
+            assert_eq!(parse(text), vec![plain(text)], "{text}");
+        }
+        assert_eq!(parse("** spaced **"), vec![plain("** spaced **")]);
+    }
+
+    #[test]
+    fn markers_inside_words_are_not_emphasis() {
+        assert_eq!(parse("snake_case_name"), vec![plain("snake_case_name")]);
+        assert_eq!(parse("2*3*4"), vec![plain("2*3*4")]);
+        assert!(!has_markup("snake_case and 2*3*4"));
+    }
+
+    #[test]
+    fn bold_and_italic() {
+        assert_eq!(
+            parse("**bold** and *it* _too_"),
+            vec![
+                Segment::Bold("bold".to_string()),
+                plain(" and "),
+                Segment::Italic("it".to_string()),
+                plain(" "),
+                Segment::Italic("too".to_string()),
+            ]
+        );
+        // segments do not nest, the inner markers are kept as text
+        assert_eq!(
+            parse("**a *b* c**"),
+            vec![Segment::Bold("a *b* c".to_string())]
+        );
+    }
+
+    #[test]
+    fn links_leave_trailing_punctuation() {
+        assert_eq!(
+            parse("see https://example.com/a?b=1."),
+            vec![plain("see "), link("https://example.com/a?b=1"), plain(".")]
+        );
+        assert_eq!(
+            parse("(http://example.com), ok"),
+            vec![plain("("), link("http://example.com"), plain("), ok")]
+        );
+        assert_eq!(
+            parse("[docs](https://example.com)!"),
+            vec![
+                Segment::Link {
+                    text: "docs".to_string(),
+                    url: "https://example.com".to_string(),
+                },
+                plain("!"),
+            ]
+        );
+        assert!(has_markup("https://example.com"));
+        assert!(!has_markup("https:// nothing"));
+    }
+
+    #[test]
+    fn fenced_blocks_with_and_without_language() {
+        assert_eq!(
+            parse("```rust\nfn main() {}\n```"),
+            vec![Segment::CodeBlock {
+                lang: "rust".to_string(),
+                code: "fn main() {}".to_string(),
+            }]
+        );
+        assert_eq!(
+            parse("```\nlet a = 1;\nlet b = 2;\n```"),
+            vec![Segment::CodeBlock {
+                lang: String::new(),
+                code: "let a = 1;\nlet b = 2;".to_string(),
+            }]
+        );
+        assert_eq!(
+            parse("run ```ls -la``` here"),
+            vec![
+                plain("run "),
+                Segment::CodeBlock {
+                    lang: String::new(),
+                    code: "ls -la".to_string(),
+                },
+                plain(" here"),
+            ]
+        );
+    }
+
+    #[test]
+    fn multibyte_text() {
+        assert_eq!(
+            parse("你好 **世界** `代码` 🎉_结束_"),
+            vec![
+                plain("你好 "),
+                Segment::Bold("世界".to_string()),
+                plain(" "),
+                Segment::Code("代码".to_string()),
+                plain(" 🎉"),
+                Segment::Italic("结束".to_string()),
+            ]
+        );
+        assert_eq!(parse("中文*未闭合"), vec![plain("中文*未闭合")]);
+    }
+}
@@ -1,8 +1,15 @@
 use chrono::TimeZone;
 use lazy_static::lazy_static;
-use orwell::shared::helper::get_now_timestamp;
-use ratatui::style::{Color, Style};
-use std::sync::Mutex;
+use orwell::{pb::orwell::ServerReceipt, shared::helper::get_now_timestamp};
+use ratatui::style::{Color, Modifier, Style};
+use std::{collections::HashMap, sync::Mutex};
+use unicode_segmentation::UnicodeSegmentation;
+
+use crate::{
+    markup::{self, Segment},
+    service::ClientManager,
+    theme::THEME,
+};
 
 #[derive(Debug, Clone, Copy, PartialEq)]
 pub enum MessageLevel {
@@ -33,12 +40,13 @@ impl MessageLevel {
 
     #[allow(dead_code)]
     pub fn style(&self) -> Style {
+        let theme = THEME.read().unwrap();
         match self {
-            MessageLevel::Info => Style::default().fg(Color::Rgb(137, 220, 235)), // Sky blue
-            MessageLevel::Warning => Style::default().fg(Color::Rgb(249, 226, 175)), // Yellow
-            MessageLevel::Error => Style::default().fg(Color::Rgb(243, 139, 168)), // Red
-            MessageLevel::Debug => Style::default().fg(Color::Rgb(166, 173, 200)), // Subtext0
-            MessageLevel::Success => Style::default().fg(Color::Rgb(166, 227, 161)), // Green
+            MessageLevel::Info => Style::default().fg(theme.sky),
+            MessageLevel::Warning => Style::default().fg(theme.yellow),
+            MessageLevel::Error => Style::default().fg(theme.red),
+            MessageLevel::Debug => Style::default().fg(theme.subtext0),
+            MessageLevel::Success => Style::default().fg(theme.green),
         }
     }
 }
@@ -81,12 +89,52 @@ impl TextSpan {
     }
 }
 
+/// Delivery state of one of our own messages, the sender not counted
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Receipt {
+    pub recipients: u32,
+    pub delivered: u32,
+    pub read: u32,
+}
+
+impl From<&ServerReceipt> for Receipt {
+    fn from(receipt: &ServerReceipt) -> Self {
+        Receipt {
+            recipients: receipt.recipients,
+            delivered: receipt.delivered,
+            read: receipt.read,
+        }
+    }
+}
+
+/// Excerpt of an earlier message shown above a reply
+#[derive(Debug, Clone)]
+pub struct Quote {
+    pub sender: String,
+    pub excerpt: String,
+}
+
 /// A line composed of multiple text spans with different styles
 #[derive(Debug, Clone)]
 pub struct Line {
     timestamp: u64,
     sender: TextSpan,
     spans: Vec<TextSpan>,
+    expires_at: Option<u64>,
+    /// Server id of the message this line shows, only set for user messages
+    id: Option<String>,
+    sender_id: Option<String>,
+    edited_at: Option<u64>,
+    deleted: bool,
+    quote: Option<Quote>,
+    /// Reaction summary shown under the line, emoji and count
+    reactions: Vec<(String, usize)>,
+    /// The message @mentions the local user
+    mentioned: bool,
+    /// Only set on our own messages
+    receipt: Option<Receipt>,
+    /// Source of a message written in markup, shown instead of the spans in raw mode
+    raw: Option<String>,
 }
 
 impl Line {
@@ -95,6 +143,16 @@ impl Line {
             timestamp,
             sender,
             spans: vec![],
+            expires_at: None,
+            id: None,
+            sender_id: None,
+            edited_at: None,
+            deleted: false,
+            quote: None,
+            reactions: vec![],
+            mentioned: false,
+            receipt: None,
+            raw: None,
         }
     }
 
@@ -166,14 +224,22 @@ impl Line {
         // Add formatted prefix parts
         result.push(TextSpan::with_color(
             time_str,
-            *TIMESTAMP_STYLE.fg.as_ref().unwrap_or(&Color::Gray),
+            THEME
+                .read()
+                .unwrap()
+                .timestamp_style()
+                .fg
+                .unwrap_or(Color::Gray),
         ));
         result.push(TextSpan::plain(" ".repeat(padding)));
         result.push(self.sender.clone());
         result.push(TextSpan::plain(" | "));
 
         // Add content spans
-        result.extend(self.spans.clone());
+        match self.raw.as_ref().filter(|_| is_raw_markup()) {
+            Some(raw) => result.push(TextSpan::plain(raw.clone())),
+            None => result.extend(self.spans.clone()),
+        }
 
         result
     }
@@ -186,11 +252,90 @@ impl Line {
         &self.sender
     }
 
+    /// When a disappearing message is removed from the chat.
+    pub fn expires_at(&self) -> Option<u64> {
+        self.expires_at
+    }
+
+    pub fn is_edited(&self) -> bool {
+        self.edited_at.is_some()
+    }
+
+    pub fn is_deleted(&self) -> bool {
+        self.deleted
+    }
+
+    pub fn quote(&self) -> Option<&Quote> {
+        self.quote.as_ref()
+    }
+
+    pub fn reactions(&self) -> &[(String, usize)] {
+        &self.reactions
+    }
+
+    pub fn is_mentioned(&self) -> bool {
+        self.mentioned
+    }
+
+    pub fn receipt(&self) -> Option<Receipt> {
+        self.receipt
+    }
+
+    pub fn id(&self) -> Option<&str> {
+        self.id.as_deref()
+    }
+
+    pub fn sender_id(&self) -> Option<&str> {
+        self.sender_id.as_deref()
+    }
+
+    /// Replace the content, older edits than the shown one are ignored.
+    /// Mentions of `self_name` are found again, an edit can add or drop them.
+    fn apply_edit(&mut self, text: String, markup: bool, edited_at: u64, self_name: Option<&str>) {
+        if self.deleted || self.edited_at.is_some_and(|at| at >= edited_at) {
+            return;
+        }
+        if markup {
+            (self.spans, self.mentioned) = markup_spans(&text, self_name);
+            self.raw = Some(text);
+        } else {
+            self.spans.clear();
+            self.mentioned = push_mentions(&mut self.spans, &text, Style::default(), self_name);
+            self.raw = None;
+        }
+        self.edited_at = Some(edited_at);
+    }
+
+    fn apply_delete(&mut self) {
+        self.spans = vec![TextSpan::new(
+            "消息已删除",
+            THEME.read().unwrap().edited_style(),
+        )];
+        self.edited_at = None;
+        self.deleted = true;
+        self.raw = None;
+        self.quote = None;
+        self.reactions.clear();
+    }
+
     /// Convert to plain text (no styling)
     pub fn to_plain_text(&self) -> String {
         self.spans.iter().map(|span| span.content()).collect()
     }
 
+    /// Text as the sender typed it, markup included
+    pub fn source_text(&self) -> String {
+        self.raw.clone().unwrap_or_else(|| self.to_plain_text())
+    }
+
+    /// Content as currently shown, the source in raw markup mode
+    pub fn display_text(&self) -> String {
+        match self.raw.as_ref().filter(|_| is_raw_markup()) {
+            Some(raw) => raw.clone(),
+            None => self.to_plain_text(),
+        }
+    }
+
     /// Check if the line is empty
     pub fn is_empty(&self) -> bool {
         self.spans.is_empty() || self.spans.iter().all(|span| span.content().is_empty())
@@ -248,13 +393,83 @@ impl DebugMessage {
 
 lazy_static! {
     static ref MESSAGE_MANAGER: Mutex<MessageManager> = Mutex::new(MessageManager::new());
-    static ref TIMESTAMP_STYLE: Style = Style::default().fg(Color::Rgb(100, 100, 100)); // Dark gray color
     static ref TIME_FORMAT: Mutex<TimeFormat> = Mutex::new(TimeFormat::Short);
+    /// Show messages written in markup as typed instead of rendered
+    static ref RAW_MARKUP: Mutex<bool> = Mutex::new(false);
+    /// Disappearing timer of the conversation in seconds and the timestamp it was set at.
+    static ref DISAPPEARING_TIMER: Mutex<(u64, u64)> = Mutex::new((0, 0));
+}
+
+/// Edit or delete that arrived before the message it targets
+#[derive(Debug, Clone)]
+enum PendingChange {
+    Edit {
+        sender_id: String,
+        text: String,
+        markup: bool,
+        edited_at: u64,
+        self_name: Option<String>,
+    },
+    Delete {
+        sender_id: String,
+    },
+}
+
+/// Reactions to one message.
+///
+/// Only the newest add or remove per emoji and sender counts, so replaying
+/// history in any order ends in the same state.
+#[derive(Debug, Default)]
+struct Reactions {
+    /// (emoji, sender id) -> (timestamp, reacted)
+    entries: HashMap<(String, String), (u64, bool)>,
+}
+
+impl Reactions {
+    fn apply(&mut self, emoji: &str, sender_id: &str, reacted: bool, timestamp: u64) {
+        let entry = self
+            .entries
+            .entry((emoji.to_string(), sender_id.to_string()))
+            .or_insert((0, false));
+        // removals win ties so the outcome does not depend on arrival order
+        if (timestamp, !reacted) > (entry.0, !entry.1) {
+            *entry = (timestamp, reacted);
+        }
+    }
+
+    fn has(&self, emoji: &str, sender_id: &str) -> bool {
+        self.entries
+            .get(&(emoji.to_string(), sender_id.to_string()))
+            .is_some_and(|(_, reacted)| *reacted)
+    }
+
+    /// Emoji with their counts, most used first
+    fn summary(&self) -> Vec<(String, usize)> {
+        let mut counts = HashMap::<&str, usize>::new();
+        for ((emoji, _), _) in self.entries.iter().filter(|(_, (_, reacted))| *reacted) {
+            *counts.entry(emoji.as_str()).or_default() += 1;
+        }
+        let mut summary = counts
+            .into_iter()
+            .map(|(emoji, count)| (emoji.to_string(), count))
+            .collect::<Vec<_>>();
+        summary.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        summary
+    }
 }
 
 pub struct MessageManager {
     chat_messages: Vec<Line>,
     debug_messages: Vec<DebugMessage>,
+    /// History arrives newest first, so changes can precede their target
+    pending_changes: HashMap<String, PendingChange>,
+    /// Keyed by message id, kept apart from the lines so early reactions are not lost
+    reactions: HashMap<String, Reactions>,
+    /// New messages go unseen, the terminal is unfocused or the chat scrolled up
+    away: bool,
+    unread: usize,
+    /// Id of the first message that arrived while away, marked by a divider
+    first_unread: Option<String>,
 }
 
 impl MessageManager {
@@ -262,17 +477,157 @@ impl MessageManager {
         Self {
             chat_messages: vec![],
             debug_messages: vec![],
+            pending_changes: HashMap::new(),
+            reactions: HashMap::new(),
+            away: false,
+            unread: 0,
+            first_unread: None,
         }
     }
 
-    pub fn insert_chat_message(&mut self, index: usize, message: Line) {
+    pub fn insert_chat_message(&mut self, index: usize, mut message: Line) {
+        self.apply_pending_change(&mut message);
+        self.refresh_reactions(&mut message);
         self.chat_messages.insert(index, message);
     }
 
-    pub fn add_chat_message(&mut self, message: Line) {
+    /// Place an older line by its timestamp, history arrives newest first
+    pub fn insert_history_message(&mut self, message: Line) {
+        let index = self
+            .chat_messages
+            .partition_point(|line| line.timestamp < message.timestamp);
+        self.insert_chat_message(index, message);
+    }
+
+    pub fn add_chat_message(&mut self, mut message: Line) {
+        self.apply_pending_change(&mut message);
+        self.refresh_reactions(&mut message);
         self.chat_messages.push(message);
     }
 
+    fn refresh_reactions(&self, line: &mut Line) {
+        if line.deleted {
+            return;
+        }
+        if let Some(reactions) = line.id.as_ref().and_then(|id| self.reactions.get(id)) {
+            line.reactions = reactions.summary();
+        }
+    }
+
+    pub fn react_to_chat_message(
+        &mut self,
+        id: &str,
+        sender_id: &str,
+        emoji: &str,
+        reacted: bool,
+        timestamp: u64,
+    ) {
+        let reactions = self.reactions.entry(id.to_string()).or_default();
+        reactions.apply(emoji, sender_id, reacted, timestamp);
+        let summary = reactions.summary();
+        if let Some(line) = self.find_chat_message_mut(id) {
+            if !line.deleted {
+                line.reactions = summary;
+            }
+        }
+    }
+
+    pub fn update_receipt(&mut self, id: &str, receipt: Receipt) {
+        if let Some(line) = self.find_chat_message_mut(id) {
+            line.receipt = Some(receipt);
+        }
+    }
+
+    pub fn has_reacted(&self, id: &str, sender_id: &str, emoji: &str) -> bool {
+        self.reactions
+            .get(id)
+            .is_some_and(|reactions| reactions.has(emoji, sender_id))
+    }
+
+    fn apply_pending_change(&mut self, line: &mut Line) {
+        let Some(change) = line
+            .id
+            .as_ref()
+            .and_then(|id| self.pending_changes.remove(id))
+        else {
+            return;
+        };
+        match change {
+            PendingChange::Edit {
+                sender_id,
+                text,
+                markup,
+                edited_at,
+                self_name,
+            } if line.sender_id.as_deref() == Some(sender_id.as_str()) => {
+                line.apply_edit(text, markup, edited_at, self_name.as_deref())
+            }
+            PendingChange::Delete { sender_id }
+                if line.sender_id.as_deref() == Some(sender_id.as_str()) =>
+            {
+                line.apply_delete()
+            }
+            _ => {}
+        }
+    }
+
+    fn find_chat_message_mut(&mut self, id: &str) -> Option<&mut Line> {
+        self.chat_messages
+            .iter_mut()
+            .find(|line| line.id.as_deref() == Some(id))
+    }
+
+    /// Only the author may edit, anything else is dropped silently.
+    /// `self_name` is our name unless the author is us, as for new messages.
+    pub fn edit_chat_message(
+        &mut self,
+        id: &str,
+        sender_id: &str,
+        text: String,
+        markup: bool,
+        edited_at: u64,
+        self_name: Option<String>,
+    ) {
+        if let Some(line) = self.find_chat_message_mut(id) {
+            if line.sender_id.as_deref() == Some(sender_id) {
+                line.apply_edit(text, markup, edited_at, self_name.as_deref());
+            }
+            return;
+        }
+        let newer = match self.pending_changes.get(id) {
+            Some(PendingChange::Delete { .. }) => false,
+            Some(PendingChange::Edit { edited_at: at, .. }) => *at < edited_at,
+            None => true,
+        };
+        if newer {
+            self.pending_changes.insert(
+                id.to_string(),
+                PendingChange::Edit {
+                    sender_id: sender_id.to_string(),
+                    text,
+                    markup,
+                    edited_at,
+                    self_name,
+                },
+            );
+        }
+    }
+
+    pub fn delete_chat_message(&mut self, id: &str, sender_id: &str) {
+        if let Some(line) = self.find_chat_message_mut(id) {
+            if line.sender_id.as_deref() == Some(sender_id) {
+                line.apply_delete();
+            }
+            return;
+        }
+        self.pending_changes.insert(
+            id.to_string(),
+            PendingChange::Delete {
+                sender_id: sender_id.to_string(),
+            },
+        );
+    }
+
     pub fn add_debug_message(&mut self, level: MessageLevel, message: String) {
         let debug_message = DebugMessage::new(level, message);
         self.debug_messages.push(debug_message);
@@ -291,6 +646,41 @@ impl MessageManager {
 
     pub fn clear_chat_messages(&mut self) {
         self.chat_messages.clear();
+        self.pending_changes.clear();
+        self.reactions.clear();
+        self.unread = 0;
+        self.first_unread = None;
+    }
+
+    /// Count a live message from someone else, the first of a batch moves the divider
+    fn count_unread(&mut self, id: &str) {
+        if !self.away {
+            return;
+        }
+        if self.unread == 0 {
+            self.first_unread = Some(id.to_string());
+        }
+        self.unread += 1;
+    }
+
+    pub fn set_away(&mut self, away: bool) {
+        self.away = away;
+        if !away {
+            self.unread = 0;
+        }
+    }
+
+    pub fn clear_unread(&mut self) {
+        self.unread = 0;
+    }
+
+    pub fn get_unread(&self) -> (usize, Option<String>) {
+        (self.unread, self.first_unread.clone())
+    }
+
+    pub fn remove_expired_chat_messages(&mut self, now: u64) {
+        self.chat_messages
+            .retain(|line| line.expires_at.is_none_or(|expires_at| expires_at > now));
     }
 
     pub fn clear_debug_messages(&mut self) {
@@ -302,6 +692,12 @@ impl MessageManager {
 
 /// Add a chat message with rich text support
 pub fn add_chat_message_rich(line: Line, index: Option<usize>) {
+    // only messages from others that arrive live count as unread
+    let unread_id = line
+        .id
+        .clone()
+        .filter(|_| index.is_none() && line.sender_id.is_some())
+        .filter(|_| line.sender_id != ClientManager::get_self_id());
     if let Ok(mut manager) = MESSAGE_MANAGER.lock() {
         // Notifier::flash_window();
         if let Some(index) = index {
@@ -309,6 +705,16 @@ pub fn add_chat_message_rich(line: Line, index: Option<usize>) {
         } else {
             manager.add_chat_message(line);
         }
+        if let Some(id) = unread_id {
+            manager.count_unread(&id);
+        }
+    }
+}
+
+/// Add a line loaded from history or the cache in timestamp order
+pub fn add_history_message(line: Line) {
+    if let Ok(mut manager) = MESSAGE_MANAGER.lock() {
+        manager.insert_history_message(line);
     }
 }
 
@@ -353,6 +759,266 @@ pub fn clear_chat_messages() {
     }
 }
 
+/// Whether the chat is out of sight, reading it again clears the unread count
+pub fn set_chat_away(away: bool) {
+    if let Ok(mut manager) = MESSAGE_MANAGER.lock() {
+        manager.set_away(away);
+    }
+}
+
+pub fn clear_unread() {
+    if let Ok(mut manager) = MESSAGE_MANAGER.lock() {
+        manager.clear_unread();
+    }
+}
+
+/// Unread count and the id of the message the divider sits above
+pub fn get_unread() -> (usize, Option<String>) {
+    MESSAGE_MANAGER
+        .lock()
+        .map(|manager| manager.get_unread())
+        .unwrap_or_default()
+}
+
+/// Apply an edit from `sender_id` to the message with server id `id`
+pub fn edit_chat_message(
+    id: &str,
+    sender_id: &str,
+    text: String,
+    markup: bool,
+    edited_at: u64,
+    self_name: Option<String>,
+) {
+    if let Ok(mut manager) = MESSAGE_MANAGER.lock() {
+        manager.edit_chat_message(id, sender_id, text, markup, edited_at, self_name);
+    }
+}
+
+/// Show the message with server id `id` as deleted if `sender_id` wrote it
+pub fn delete_chat_message(id: &str, sender_id: &str) {
+    if let Ok(mut manager) = MESSAGE_MANAGER.lock() {
+        manager.delete_chat_message(id, sender_id);
+    }
+}
+
+/// Add or take back `sender_id`'s `emoji` on the message with server id `id`
+pub fn react_to_chat_message(
+    id: &str,
+    sender_id: &str,
+    emoji: &str,
+    reacted: bool,
+    timestamp: u64,
+) {
+    if let Ok(mut manager) = MESSAGE_MANAGER.lock() {
+        manager.react_to_chat_message(id, sender_id, emoji, reacted, timestamp);
+    }
+}
+
+/// New delivery state of our own message with server id `id`
+pub fn update_chat_message_receipt(id: &str, receipt: Receipt) {
+    if let Ok(mut manager) = MESSAGE_MANAGER.lock() {
+        manager.update_receipt(id, receipt);
+    }
+}
+
+/// Whether `sender_id` currently has `emoji` on the message with server id `id`
+pub fn has_reacted(id: &str, sender_id: &str, emoji: &str) -> bool {
+    MESSAGE_MANAGER
+        .lock()
+        .map(|manager| manager.has_reacted(id, sender_id, emoji))
+        .unwrap_or(false)
+}
+
+/// The newest `limit` messages that @mention the local user, oldest first
+pub fn get_recent_mentions(limit: usize) -> Vec<Line> {
+    let Ok(manager) = MESSAGE_MANAGER.lock() else {
+        return vec![];
+    };
+    let mut mentions = manager
+        .chat_messages
+        .iter()
+        .rev()
+        .filter(|line| line.mentioned && !line.deleted)
+        .take(limit)
+        .cloned()
+        .collect::<Vec<_>>();
+    mentions.reverse();
+    mentions
+}
+
+/// Byte ranges of `@name` in `text`.
+///
+/// ASCII case is ignored and the mention must not be part of a longer word.
+pub fn find_mentions(text: &str, name: &str) -> Vec<(usize, usize)> {
+    if name.is_empty() {
+        return vec![];
+    }
+    let is_word = |c: char| c.is_alphanumeric() || c == '_';
+    let haystack = text.to_ascii_lowercase();
+    let needle = format!("@{}", name.to_ascii_lowercase());
+    haystack
+        .match_indices(&needle)
+        .map(|(start, _)| (start, start + needle.len()))
+        .filter(|(start, end)| {
+            !text[..*start].chars().next_back().is_some_and(is_word)
+                && !text[*end..].chars().next().is_some_and(is_word)
+        })
+        .collect()
+}
+
+/// A reaction is a single emoji, anything longer is rejected
+pub fn is_valid_reaction(emoji: &str) -> bool {
+    emoji.len() <= 32 && !emoji.trim().is_empty() && emoji.graphemes(true).count() == 1
+}
+
+/// A user message picked by the `/edit`, `/delete`, `/reply` and `/react` commands
+#[derive(Debug, Clone)]
+pub struct ChatMessageRef {
+    pub id: String,
+    pub sender_id: String,
+    pub sender_name: String,
+    pub text: String,
+}
+
+/// The `n`th newest user message, counting from 1
+pub fn get_chat_message_by_offset(n: usize) -> Option<ChatMessageRef> {
+    let manager = MESSAGE_MANAGER.lock().ok()?;
+    manager
+        .chat_messages
+        .iter()
+        .rev()
+        .filter(|line| !line.deleted)
+        .filter_map(|line| {
+            Some(ChatMessageRef {
+                id: line.id.clone()?,
+                sender_id: line.sender_id.clone()?,
+                sender_name: line.sender.content().to_string(),
+                text: line.source_text(),
+            })
+        })
+        .nth(n.checked_sub(1)?)
+}
+
+/// Push `content` in `style` with mentions of `self_name` highlighted,
+/// returns whether there were any
+fn push_mentions(
+    spans: &mut Vec<TextSpan>,
+    content: &str,
+    style: Style,
+    self_name: Option<&str>,
+) -> bool {
+    let mentions = self_name.map_or(vec![], |name| find_mentions(content, name));
+    let mut last = 0;
+    for (start, end) in &mentions {
+        if *start > last {
+            spans.push(TextSpan::new(&content[last..*start], style));
+        }
+        spans.push(TextSpan::new(
+            &content[*start..*end],
+            style.patch(THEME.read().unwrap().mention_style()),
+        ));
+        last = *end;
+    }
+    if last < content.len() {
+        spans.push(TextSpan::new(&content[last..], style));
+    }
+    !mentions.is_empty()
+}
+
+/// Styled spans of a message written in markup, and whether it mentions `self_name`.
+///
+/// Code blocks get a box of their own rows, line breaks are kept in the
+/// spans and honoured by the renderer.
+fn markup_spans(content: &str, self_name: Option<&str>) -> (Vec<TextSpan>, bool) {
+    let mut spans = Vec::new();
+    let mut mentioned = false;
+    let mut after_block = false;
+    for segment in markup::parse(content) {
+        if after_block {
+            spans.push(TextSpan::plain("\n"));
+        }
+        let is_block = matches!(segment, Segment::CodeBlock { .. });
+        match segment {
+            Segment::Plain(text) => {
+                // a code block always ends its row, one typed line break is enough
+                let text = if after_block {
+                    text.strip_prefix('\n').unwrap_or(&text)
+                } else {
+                    &text
+                };
+                mentioned |= push_mentions(&mut spans, text, Style::default(), self_name);
+            }
+            Segment::Bold(text) => {
+                let style = Style::default().add_modifier(Modifier::BOLD);
+                mentioned |= push_mentions(&mut spans, &text, style, self_name);
+            }
+            Segment::Italic(text) => {
+                let style = Style::default().add_modifier(Modifier::ITALIC);
+                mentioned |= push_mentions(&mut spans, &text, style, self_name);
+            }
+            Segment::Code(code) => {
+                spans.push(TextSpan::new(code, THEME.read().unwrap().code_style()))
+            }
+            Segment::Link { text, url } => {
+                let same = text == url;
+                spans.push(TextSpan::new(text, THEME.read().unwrap().link_style()));
+                if !same {
+                    spans.push(TextSpan::new(
+                        format!(" ({})", url),
+                        THEME.read().unwrap().edited_style(),
+                    ));
+                }
+            }
+            Segment::CodeBlock { lang, code } => {
+                let starts_row = spans
+                    .last()
+                    .is_none_or(|span: &TextSpan| span.content().ends_with('\n'));
+                if !starts_row {
+                    spans.push(TextSpan::plain("\n"));
+                }
+                spans.push(TextSpan::new(
+                    format!("┌─ {}\n", if lang.is_empty() { "code" } else { &lang }),
+                    THEME.read().unwrap().code_border_style(),
+                ));
+                for row in code.lines() {
+                    spans.push(TextSpan::new(
+                        "│ ",
+                        THEME.read().unwrap().code_border_style(),
+                    ));
+                    spans.push(TextSpan::new(row, THEME.read().unwrap().code_style()));
+                    spans.push(TextSpan::plain("\n"));
+                }
+                spans.push(TextSpan::new(
+                    "└─",
+                    THEME.read().unwrap().code_border_style(),
+                ));
+            }
+        }
+        after_block = is_block;
+    }
+    (spans, mentioned)
+}
+
+/// First line of `text`, cut to a short excerpt for quoting
+pub fn quote_excerpt(text: &str) -> String {
+    const MAX_CHARS: usize = 40;
+    let first_line = text.lines().next().unwrap_or_default();
+    if first_line.chars().count() > MAX_CHARS || text.lines().nth(1).is_some() {
+        let mut excerpt = first_line.chars().take(MAX_CHARS).collect::<String>();
+        excerpt.push('…');
+        excerpt
+    } else {
+        first_line.to_string()
+    }
+}
+
+/// Drop disappearing messages whose timer ran out
+pub fn purge_expired_chat_messages() {
+    if let Ok(mut manager) = MESSAGE_MANAGER.lock() {
+        manager.remove_expired_chat_messages(get_now_timestamp());
+    }
+}
+
 pub fn clear_debug_messages() {
     if let Ok(mut manager) = MESSAGE_MANAGER.lock() {
         manager.clear_debug_messages();
@@ -381,6 +1047,55 @@ impl LineBuilder {
         self
     }
 
+    /// Server id and sender of a user message, needed for edits and deletes
+    pub fn message_id(mut self, id: impl Into<String>, sender_id: impl Into<String>) -> Self {
+        self.line.id = Some(id.into());
+        self.line.sender_id = Some(sender_id.into());
+        self
+    }
+
+    /// Plain text with every `@name` of the local user highlighted
+    pub fn mention_text(self, content: impl Into<String>, self_name: Option<&str>) -> Self {
+        self.styled_mention_text(content, Style::default(), self_name)
+    }
+
+    /// Like [`LineBuilder::mention_text`] with `style` outside of the mentions
+    pub fn styled_mention_text(
+        mut self,
+        content: impl Into<String>,
+        style: Style,
+        self_name: Option<&str>,
+    ) -> Self {
+        let content = content.into();
+        self.line.mentioned |= push_mentions(&mut self.line.spans, &content, style, self_name);
+        self
+    }
+
+    /// Render markup into styled spans, mentions are highlighted outside of code
+    pub fn markup_text(mut self, content: impl Into<String>, self_name: Option<&str>) -> Self {
+        let content = content.into();
+        let (spans, mentioned) = markup_spans(&content, self_name);
+        self.line.spans.extend(spans);
+        self.line.mentioned = mentioned;
+        self.line.raw = Some(content);
+        self
+    }
+
+    pub fn receipt(mut self, receipt: Option<Receipt>) -> Self {
+        self.line.receipt = receipt;
+        self
+    }
+
+    pub fn quote(mut self, quote: Option<Quote>) -> Self {
+        self.line.quote = quote;
+        self
+    }
+
+    pub fn expires_at(mut self, expires_at: Option<u64>) -> Self {
+        self.line.expires_at = expires_at;
+        self
+    }
+
     pub fn plain(mut self, content: impl Into<String>) -> Self {
         self.line.push_plain(content);
         self
@@ -461,3 +1176,153 @@ pub fn toggle_time_format() {
         TimeFormat::Full => TimeFormat::Short,
     };
 }
+
+pub fn is_raw_markup() -> bool {
+    *RAW_MARKUP.lock().unwrap()
+}
+
+/// Toggle between rendered and raw markup, returns whether raw is shown now
+pub fn toggle_raw_markup() -> bool {
+    let mut raw = RAW_MARKUP.lock().unwrap();
+    *raw = !*raw;
+    *raw
+}
+
+/// Get the disappearing timer of the conversation in seconds, 0 when off
+pub fn get_disappearing_timer() -> u64 {
+    DISAPPEARING_TIMER.lock().unwrap().0
+}
+
+/// Adopt a timer set at `set_at` unless a newer setting is known, history arrives newest first
+pub fn update_disappearing_timer(seconds: u64, set_at: u64) -> bool {
+    let mut timer = DISAPPEARING_TIMER.lock().unwrap();
+    if set_at < timer.1 {
+        return false;
+    }
+    *timer = (seconds, set_at);
+    true
+}
+
+/// Format seconds compactly, e.g. `1h05m` or `42s`
+pub fn format_duration(seconds: u64) -> String {
+    match seconds {
+        0..60 => format!("{}s", seconds),
+        60..3600 => format!("{}m{:02}s", seconds / 60, seconds % 60),
+        3600..86400 => format!("{}h{:02}m", seconds / 3600, seconds % 3600 / 60),
+        _ => format!("{}d{:02}h", seconds / 86400, seconds % 86400 / 3600),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unread_counts_only_while_away() {
+        let mut manager = MessageManager::new();
+        manager.count_unread("m1");
+        assert_eq!(manager.get_unread(), (0, None));
+
+        manager.set_away(true);
+        manager.count_unread("m2");
+        manager.count_unread("m3");
+        assert_eq!(manager.get_unread(), (2, Some("m2".to_string())));
+
+        // reading clears the count but keeps the divider where it was
+        manager.set_away(false);
+        assert_eq!(manager.get_unread(), (0, Some("m2".to_string())));
+
+        // the next batch moves it
+        manager.set_away(true);
+        manager.count_unread("m4");
+        assert_eq!(manager.get_unread(), (1, Some("m4".to_string())));
+    }
+
+    #[test]
+    fn reactions_do_not_depend_on_arrival_order() {
+        // (emoji, sender, reacted, timestamp)
+        let events = [
+            ("👍", "alice", true, 100),
+            ("👍", "bob", true, 110),
+            ("👍", "alice", false, 120),
+            ("🎉", "carol", true, 130),
+            ("🎉", "carol", false, 130),
+            ("👀", "bob", true, 140),
+            ("👍", "alice", true, 150),
+            ("👀", "dave", false, 160),
+            ("👀", "dave", true, 155),
+        ];
+        let apply = |order: &[usize]| {
+            let mut reactions = Reactions::default();
+            for &i in order {
+                let (emoji, sender, reacted, timestamp) = events[i];
+                reactions.apply(emoji, sender, reacted, timestamp);
+            }
+            reactions
+        };
+
+        let forward = apply(&[0, 1, 2, 3, 4, 5, 6, 7, 8]);
+        let expected = vec![("👍".to_string(), 2), ("👀".to_string(), 1)];
+        assert_eq!(forward.summary(), expected);
+        assert!(forward.has("👍", "alice") && !forward.has("👀", "dave"));
+        // newest first like history, then removals ahead of their adds
+        for order in [[8, 7, 6, 5, 4, 3, 2, 1, 0], [2, 4, 7, 0, 3, 8, 6, 5, 1]] {
+            let reactions = apply(&order);
+            assert_eq!(reactions.summary(), expected, "{order:?}");
+            assert_eq!(reactions.entries, forward.entries, "{order:?}");
+        }
+    }
+
+    #[test]
+    fn edits_recompute_mentions() {
+        let bob = || Some("bob".to_string());
+        let mut manager = MessageManager::new();
+        manager.add_chat_message(
+            LineBuilder::new()
+                .mention_text("hi @bob", Some("bob"))
+                .message_id("m1", "alice")
+                .build(),
+        );
+        let line = |manager: &MessageManager| manager.get_chat_messages()[0].clone();
+        assert!(line(&manager).is_mentioned());
+
+        manager.edit_chat_message("m1", "alice", "hi all".to_string(), false, 10, bob());
+        assert!(!line(&manager).is_mentioned());
+        assert_eq!(line(&manager).to_plain_text(), "hi all");
+
+        manager.edit_chat_message("m1", "alice", "**@bob** look".to_string(), true, 20, bob());
+        assert!(line(&manager).is_mentioned());
+
+        // an edit that arrives before its message, as in history
+        manager.edit_chat_message("m2", "alice", "ping @bob".to_string(), false, 30, bob());
+        manager.add_chat_message(
+            LineBuilder::new()
+                .mention_text("nothing", Some("bob"))
+                .message_id("m2", "alice")
+                .build(),
+        );
+        assert!(manager.get_chat_messages()[1].is_mentioned());
+    }
+
+    #[test]
+    fn history_is_placed_by_timestamp() {
+        let line = |timestamp: u64| {
+            LineBuilder::new()
+                .time(timestamp)
+                .plain(timestamp.to_string())
+                .build()
+        };
+        let mut manager = MessageManager::new();
+        manager.add_chat_message(line(500));
+        // cached messages newest first, then the server history around them
+        for timestamp in [300, 100, 400, 200, 50] {
+            manager.insert_history_message(line(timestamp));
+        }
+        let timestamps = manager
+            .get_chat_messages()
+            .iter()
+            .map(Line::timestamp)
+            .collect::<Vec<_>>();
+        assert_eq!(timestamps, [50, 100, 200, 300, 400, 500]);
+    }
+}
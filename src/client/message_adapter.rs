@@ -1,9 +1,31 @@
-use anyhow::Result;
-use orwell::pb::orwell::{MessageType, ServerBroadcastMessage};
+use anyhow::{anyhow, Result};
+use orwell::{
+    pb::orwell::{ExpiringMessage, MessageType, ServerBroadcastMessage},
+    shared::helper::get_now_timestamp,
+};
+use prost::Message;
+
+use crate::message::{add_chat_message_rich, add_history_message, Line};
 
 /// Context for message processing
 pub struct MessageContext {
     pub is_history: bool,
+    /// Missed while offline, not live but shown in order after the history
+    pub is_offline: bool,
+    /// Set when the payload came wrapped in an `Expiring` message
+    pub expires_at: Option<u64>,
+}
+
+impl MessageContext {
+    /// Show a rendered line, history is placed by its timestamp since the
+    /// cache and the server both hand it out newest first
+    pub fn show(&self, line: Line) {
+        if self.is_history && !self.is_offline {
+            add_history_message(line);
+        } else {
+            add_chat_message_rich(line, None);
+        }
+    }
 }
 
 /// Trait for message adapters
@@ -46,9 +68,31 @@ impl MessageAdapterRegistry {
         data: Vec<u8>,
         context: MessageContext,
     ) -> Result<()> {
-        let msg_type = MessageType::try_from(data[0] as i32)?;
+        let Some(&msg_type) = data.first() else {
+            return Err(anyhow!("消息为空"));
+        };
+        let msg_type = MessageType::try_from(msg_type as i32)?;
         let actual_data = data[1..].to_vec();
 
+        if msg_type == MessageType::Expiring {
+            if context.expires_at.is_some() {
+                return Err(anyhow!("限时消息不能嵌套"));
+            }
+            let expiring = ExpiringMessage::decode(actual_data.as_slice())?;
+            let expires_at = message
+                .timestamp
+                .saturating_add(expiring.timer.saturating_mul(1000));
+            // already gone for everyone else, never show it
+            if expires_at <= get_now_timestamp() {
+                return Ok(());
+            }
+            let context = MessageContext {
+                expires_at: Some(expires_at),
+                ..context
+            };
+            return self.process_message(message, expiring.data, context);
+        }
+
         if let Some(adapter) = self.get(msg_type) {
             adapter.process(message, actual_data, context)
         } else {
@@ -2,7 +2,7 @@ use anyhow::Result;
 use orwell::pb::orwell::{MessageType, ServerBroadcastChangeColor, ServerBroadcastMessage};
 
 use crate::{
-    message::{add_chat_message_rich, LineBuilder},
+    message::LineBuilder,
     message_adapter::{MessageAdapter, MessageContext},
     service::ClientManager,
 };
@@ -22,7 +22,7 @@ impl MessageAdapter for ColorChangeMessageAdapter {
     ) -> Result<()> {
         let color_data = ServerBroadcastChangeColor::decode(data.as_slice())?;
 
-        add_chat_message_rich(
+        context.show(
             LineBuilder::new()
                 .time(message.timestamp)
                 .colored(
@@ -40,7 +40,6 @@ impl MessageAdapter for ColorChangeMessageAdapter {
                     Color::from_u32(color_data.new_color as u32),
                 )
                 .build(),
-            if context.is_history { Some(0) } else { None },
         );
 
         ClientManager::update_color(color_data.id.clone(), color_data.new_color);
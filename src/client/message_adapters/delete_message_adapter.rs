@@ -0,0 +1,45 @@
+use anyhow::Result;
+use orwell::pb::orwell::{MessageDelete, MessageType, ServerBroadcastMessage};
+
+use crate::{
+    message::{add_debug_message, delete_chat_message, MessageLevel},
+    message_adapter::{MessageAdapter, MessageContext},
+    service::ClientManager,
+};
+
+pub struct DeleteMessageAdapter;
+
+impl MessageAdapter for DeleteMessageAdapter {
+    fn message_type(&self) -> MessageType {
+        MessageType::Delete
+    }
+
+    fn process(
+        &self,
+        message: &ServerBroadcastMessage,
+        data: Vec<u8>,
+        _context: MessageContext,
+    ) -> Result<()> {
+        let delete = MessageDelete::decode(data.as_slice())?;
+
+        let mut unsigned = MessageDelete {
+            sign: vec![],
+            ..delete.clone()
+        }
+        .encode_to_vec();
+        unsigned.insert(0, MessageType::Delete as u8);
+        if !ClientManager::verify_signature(&message.sender_id, &unsigned, &delete.sign) {
+            add_debug_message(
+                MessageLevel::Warning,
+                format!("忽略 {} 的删除: 签名无效", message.sender_name),
+            );
+            return Ok(());
+        }
+
+        delete_chat_message(&delete.target_id, &message.sender_id);
+
+        Ok(())
+    }
+}
+
+use prost::Message as ProstMessage;
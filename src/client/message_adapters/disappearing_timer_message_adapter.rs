@@ -0,0 +1,43 @@
+use anyhow::Result;
+use orwell::pb::orwell::{DisappearingTimerSetting, MessageType, ServerBroadcastMessage};
+
+use crate::{
+    message::{format_duration, update_disappearing_timer, LineBuilder},
+    message_adapter::{MessageAdapter, MessageContext},
+};
+
+pub struct DisappearingTimerMessageAdapter;
+
+impl MessageAdapter for DisappearingTimerMessageAdapter {
+    fn message_type(&self) -> MessageType {
+        MessageType::DisappearingTimer
+    }
+
+    fn process(
+        &self,
+        message: &ServerBroadcastMessage,
+        data: Vec<u8>,
+        context: MessageContext,
+    ) -> Result<()> {
+        let setting = DisappearingTimerSetting::decode(data.as_slice())?;
+        update_disappearing_timer(setting.seconds, message.timestamp);
+
+        let builder = LineBuilder::new().time(message.timestamp).colored(
+            message.sender_name.clone(),
+            Color::from_u32(message.color as u32),
+        );
+        let builder = if setting.seconds == 0 {
+            builder.plain(" 关闭了消失消息")
+        } else {
+            builder
+                .plain(" 将消失消息设置为 ")
+                .warning(format_duration(setting.seconds))
+        };
+        context.show(builder.build());
+
+        Ok(())
+    }
+}
+
+use prost::Message as ProstMessage;
+use ratatui::style::Color;
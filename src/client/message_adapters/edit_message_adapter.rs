@@ -0,0 +1,54 @@
+use anyhow::Result;
+use orwell::pb::orwell::{MessageEdit, MessageType, ServerBroadcastMessage};
+
+use crate::{
+    message::{add_debug_message, edit_chat_message, MessageLevel},
+    message_adapter::{MessageAdapter, MessageContext},
+    service::ClientManager,
+};
+
+pub struct EditMessageAdapter;
+
+impl MessageAdapter for EditMessageAdapter {
+    fn message_type(&self) -> MessageType {
+        MessageType::Edit
+    }
+
+    fn process(
+        &self,
+        message: &ServerBroadcastMessage,
+        data: Vec<u8>,
+        _context: MessageContext,
+    ) -> Result<()> {
+        let edit = MessageEdit::decode(data.as_slice())?;
+
+        let mut unsigned = MessageEdit {
+            sign: vec![],
+            ..edit.clone()
+        }
+        .encode_to_vec();
+        unsigned.insert(0, MessageType::Edit as u8);
+        if !ClientManager::verify_signature(&message.sender_id, &unsigned, &edit.sign) {
+            add_debug_message(
+                MessageLevel::Warning,
+                format!("忽略 {} 的编辑: 签名无效", message.sender_name),
+            );
+            return Ok(());
+        }
+
+        // our own edits never count as mentions
+        let self_name = ClientManager::get_self_name().filter(|name| *name != message.sender_name);
+        edit_chat_message(
+            &edit.target_id,
+            &message.sender_id,
+            edit.text,
+            edit.markup,
+            message.timestamp,
+            self_name,
+        );
+
+        Ok(())
+    }
+}
+
+use prost::Message as ProstMessage;
@@ -2,9 +2,9 @@ use anyhow::Result;
 use orwell::pb::orwell::{MessageType, ServerBroadcastMessage};
 
 use crate::{
-    message::{add_chat_message_rich, LineBuilder, TextSpan},
+    message::{LineBuilder, TextSpan},
     message_adapter::{MessageAdapter, MessageContext},
-    notify::Notifier,
+    notify::{Notifier, NotifyEvent},
 };
 
 pub struct LeftAfkMessageAdapter;
@@ -22,12 +22,13 @@ impl MessageAdapter for LeftAfkMessageAdapter {
     ) -> Result<()> {
         if !context.is_history {
             Notifier::notify_message(
+                NotifyEvent::Afk,
                 &message.sender_name,
                 &format!("{} 离开了AFK状态", message.sender_name),
             );
         }
 
-        add_chat_message_rich(
+        context.show(
             LineBuilder::new()
                 .time(message.timestamp)
                 .sender(TextSpan::new(
@@ -42,7 +43,6 @@ impl MessageAdapter for LeftAfkMessageAdapter {
                 )
                 .plain(" 离开了AFK状态")
                 .build(),
-            if context.is_history { Some(0) } else { None },
         );
 
         Ok(())
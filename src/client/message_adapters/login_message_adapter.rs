@@ -2,9 +2,9 @@ use anyhow::Result;
 use orwell::pb::orwell::{MessageType, ServerBroadcastMessage};
 
 use crate::{
-    message::{add_chat_message_rich, LineBuilder, TextSpan},
+    message::{LineBuilder, TextSpan},
     message_adapter::{MessageAdapter, MessageContext},
-    notify::Notifier,
+    notify::{Notifier, NotifyEvent},
 };
 
 pub struct LoginMessageAdapter;
@@ -22,12 +22,13 @@ impl MessageAdapter for LoginMessageAdapter {
     ) -> Result<()> {
         if !context.is_history {
             Notifier::notify_message(
+                NotifyEvent::Presence,
                 &message.sender_name,
                 &format!("{} 上线了", message.sender_name),
             );
         }
 
-        add_chat_message_rich(
+        context.show(
             LineBuilder::new()
                 .time(message.timestamp)
                 .sender(TextSpan::new(
@@ -42,7 +43,6 @@ impl MessageAdapter for LoginMessageAdapter {
                 )
                 .plain(" 上线了")
                 .build(),
-            if context.is_history { Some(0) } else { None },
         );
 
         Ok(())
@@ -2,9 +2,9 @@ use anyhow::Result;
 use orwell::pb::orwell::{ClientStatus, MessageType, ServerBroadcastMessage};
 
 use crate::{
-    message::{add_chat_message_rich, LineBuilder, TextSpan},
+    message::{LineBuilder, TextSpan},
     message_adapter::{MessageAdapter, MessageContext},
-    notify::Notifier,
+    notify::{Notifier, NotifyEvent},
     service::ClientManager,
 };
 
@@ -23,12 +23,13 @@ impl MessageAdapter for LogoutMessageAdapter {
     ) -> Result<()> {
         if !context.is_history {
             Notifier::notify_message(
+                NotifyEvent::Presence,
                 &message.sender_name,
                 &format!("{} 下线了", message.sender_name),
             );
         }
 
-        add_chat_message_rich(
+        context.show(
             LineBuilder::new()
                 .time(message.timestamp)
                 .sender(TextSpan::new(
@@ -41,7 +42,6 @@ impl MessageAdapter for LogoutMessageAdapter {
                 )
                 .plain(" 下线了")
                 .build(),
-            if context.is_history { Some(0) } else { None },
         );
 
         ClientManager::update_status(message.sender_id.clone(), ClientStatus::Offline);
@@ -0,0 +1,65 @@
+use anyhow::Result;
+use orwell::pb::orwell::{MessageType, ServerBroadcastMessage};
+
+use crate::{
+    message::{find_mentions, LineBuilder, TextSpan},
+    message_adapter::{MessageAdapter, MessageContext},
+    notify::Notifier,
+    service::ClientManager,
+};
+
+pub struct MeMessageAdapter;
+
+impl MessageAdapter for MeMessageAdapter {
+    fn message_type(&self) -> MessageType {
+        MessageType::Me
+    }
+
+    fn process(
+        &self,
+        message: &ServerBroadcastMessage,
+        data: Vec<u8>,
+        context: MessageContext,
+    ) -> Result<()> {
+        let action = String::from_utf8(data)?;
+        let color = Color::from_u32(message.color as u32);
+
+        let self_name = ClientManager::get_self_name().filter(|name| *name != message.sender_name);
+        let mentioned = self_name
+            .as_deref()
+            .is_some_and(|name| !find_mentions(&action, name).is_empty());
+
+        if !context.is_history {
+            Notifier::notify_chat_message(
+                &message.sender_name,
+                &format!("* {} {}", message.sender_name, action),
+                mentioned,
+            );
+        }
+
+        context.show(
+            LineBuilder::new()
+                .time(message.timestamp)
+                .sender(TextSpan::new(
+                    "*",
+                    Style::default().fg(color).add_modifier(Modifier::BOLD),
+                ))
+                .styled(
+                    message.sender_name.clone(),
+                    Style::default().fg(color).add_modifier(Modifier::BOLD),
+                )
+                .styled_mention_text(
+                    format!(" {}", action),
+                    Style::default().fg(color).add_modifier(Modifier::ITALIC),
+                    self_name.as_deref(),
+                )
+                .expires_at(context.expires_at)
+                .message_id(message.id.clone(), message.sender_id.clone())
+                .build(),
+        );
+
+        Ok(())
+    }
+}
+
+use ratatui::style::{Color, Modifier, Style};
@@ -1,17 +1,27 @@
 pub mod color_change_message_adapter;
+pub mod delete_message_adapter;
+pub mod disappearing_timer_message_adapter;
+pub mod edit_message_adapter;
 pub mod enter_afk_message_adapter;
 pub mod left_afk_message_adapter;
 pub mod login_message_adapter;
 pub mod logout_message_adapter;
+pub mod me_message_adapter;
+pub mod reaction_message_adapter;
+pub mod reply_message_adapter;
 pub mod text_message_adapter;
 
 use crate::message_adapter::MessageAdapterRegistry;
 
 use self::{
     color_change_message_adapter::ColorChangeMessageAdapter,
-    enter_afk_message_adapter::EnterAfkMessageAdapter,
+    delete_message_adapter::DeleteMessageAdapter,
+    disappearing_timer_message_adapter::DisappearingTimerMessageAdapter,
+    edit_message_adapter::EditMessageAdapter, enter_afk_message_adapter::EnterAfkMessageAdapter,
     left_afk_message_adapter::LeftAfkMessageAdapter, login_message_adapter::LoginMessageAdapter,
-    logout_message_adapter::LogoutMessageAdapter, text_message_adapter::TextMessageAdapter,
+    logout_message_adapter::LogoutMessageAdapter, me_message_adapter::MeMessageAdapter,
+    reaction_message_adapter::ReactionMessageAdapter, reply_message_adapter::ReplyMessageAdapter,
+    text_message_adapter::TextMessageAdapter,
 };
 
 /// Create and register all message adapters
@@ -19,11 +29,17 @@ pub fn create_message_registry() -> MessageAdapterRegistry {
     let mut registry = MessageAdapterRegistry::new();
 
     registry.register(Box::new(TextMessageAdapter));
+    registry.register(Box::new(MeMessageAdapter));
+    registry.register(Box::new(ReplyMessageAdapter));
     registry.register(Box::new(LoginMessageAdapter));
     registry.register(Box::new(LogoutMessageAdapter));
     registry.register(Box::new(ColorChangeMessageAdapter));
     registry.register(Box::new(EnterAfkMessageAdapter));
     registry.register(Box::new(LeftAfkMessageAdapter));
+    registry.register(Box::new(DisappearingTimerMessageAdapter));
+    registry.register(Box::new(EditMessageAdapter));
+    registry.register(Box::new(DeleteMessageAdapter));
+    registry.register(Box::new(ReactionMessageAdapter));
 
     registry
 }
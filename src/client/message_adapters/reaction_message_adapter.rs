@@ -0,0 +1,39 @@
+use anyhow::Result;
+use orwell::pb::orwell::{MessageType, ReactionMessage, ServerBroadcastMessage};
+
+use crate::{
+    message::{is_valid_reaction, react_to_chat_message},
+    message_adapter::{MessageAdapter, MessageContext},
+};
+
+pub struct ReactionMessageAdapter;
+
+impl MessageAdapter for ReactionMessageAdapter {
+    fn message_type(&self) -> MessageType {
+        MessageType::Reaction
+    }
+
+    fn process(
+        &self,
+        message: &ServerBroadcastMessage,
+        data: Vec<u8>,
+        _context: MessageContext,
+    ) -> Result<()> {
+        let reaction = ReactionMessage::decode(data.as_slice())?;
+        if !is_valid_reaction(&reaction.emoji) {
+            return Ok(());
+        }
+
+        react_to_chat_message(
+            &reaction.target_id,
+            &message.sender_id,
+            &reaction.emoji,
+            !reaction.remove,
+            message.timestamp,
+        );
+
+        Ok(())
+    }
+}
+
+use prost::Message as ProstMessage;
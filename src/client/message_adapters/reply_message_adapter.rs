@@ -0,0 +1,34 @@
+use anyhow::Result;
+use orwell::pb::orwell::{MessageType, ReplyMessage, ServerBroadcastMessage};
+
+use super::text_message_adapter::TextMessageAdapter;
+use crate::{
+    message::{quote_excerpt, Quote},
+    message_adapter::{MessageAdapter, MessageContext},
+};
+
+pub struct ReplyMessageAdapter;
+
+impl MessageAdapter for ReplyMessageAdapter {
+    fn message_type(&self) -> MessageType {
+        MessageType::Reply
+    }
+
+    fn process(
+        &self,
+        message: &ServerBroadcastMessage,
+        data: Vec<u8>,
+        context: MessageContext,
+    ) -> Result<()> {
+        let reply = ReplyMessage::decode(data.as_slice())?;
+        let quote = Quote {
+            sender: reply.quoted_sender,
+            // the excerpt comes from the sender, keep it short whatever they sent
+            excerpt: quote_excerpt(&reply.excerpt),
+        };
+        TextMessageAdapter::render(message, reply.text, reply.markup, Some(quote), context);
+        Ok(())
+    }
+}
+
+use prost::Message as ProstMessage;
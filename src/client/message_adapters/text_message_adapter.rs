@@ -2,13 +2,71 @@ use anyhow::Result;
 use orwell::pb::orwell::{MessageType, ServerBroadcastMessage};
 
 use crate::{
-    message::{add_chat_message_rich, LineBuilder, TextSpan},
+    markup::decode_text,
+    message::{find_mentions, LineBuilder, Quote, Receipt, TextSpan},
     message_adapter::{MessageAdapter, MessageContext},
     notify::Notifier,
+    service::{ClientManager, ReceiptManager, TypingManager},
 };
 
 pub struct TextMessageAdapter;
 
+impl TextMessageAdapter {
+    /// Show a user message, replies pass the quoted message along and
+    /// `markup` renders the text as rich text
+    pub fn render(
+        message: &ServerBroadcastMessage,
+        text: String,
+        markup: bool,
+        quote: Option<Quote>,
+        context: MessageContext,
+    ) {
+        // our own messages never count as mentions
+        let self_name = ClientManager::get_self_name().filter(|name| *name != message.sender_name);
+        let mentioned = self_name
+            .as_deref()
+            .is_some_and(|name| !find_mentions(&text, name).is_empty());
+
+        let from_self = ClientManager::get_self_id().is_some_and(|id| id == message.sender_id);
+        if !from_self && !message.id.is_empty() {
+            ReceiptManager::queue_delivered(&message.id);
+        }
+
+        if !context.is_history {
+            Notifier::notify_chat_message(&message.sender_name, &text, mentioned);
+            // the message they were typing has arrived
+            TypingManager::update(
+                message.sender_id.clone(),
+                message.sender_name.clone(),
+                message.color,
+                false,
+            );
+        }
+
+        let builder = LineBuilder::new()
+            .time(message.timestamp)
+            .sender(TextSpan::new(
+                message.sender_name.clone(),
+                Style::default()
+                    .fg(Color::from_u32(message.color as u32))
+                    .add_modifier(Modifier::BOLD),
+            ));
+        let builder = if markup {
+            builder.markup_text(text, self_name.as_deref())
+        } else {
+            builder.mention_text(text, self_name.as_deref())
+        };
+        context.show(
+            builder
+                .message_id(message.id.clone(), message.sender_id.clone())
+                .quote(quote)
+                .receipt(message.receipt.as_ref().map(Receipt::from))
+                .expires_at(context.expires_at)
+                .build(),
+        );
+    }
+}
+
 impl MessageAdapter for TextMessageAdapter {
     fn message_type(&self) -> MessageType {
         MessageType::Text
@@ -20,26 +78,8 @@ impl MessageAdapter for TextMessageAdapter {
         data: Vec<u8>,
         context: MessageContext,
     ) -> Result<()> {
-        let text = String::from_utf8(data)?;
-
-        if !context.is_history {
-            Notifier::notify_message(&message.sender_name, &text);
-        }
-
-        add_chat_message_rich(
-            LineBuilder::new()
-                .time(message.timestamp)
-                .sender(TextSpan::new(
-                    message.sender_name.clone(),
-                    Style::default()
-                        .fg(Color::from_u32(message.color as u32))
-                        .add_modifier(Modifier::BOLD),
-                ))
-                .plain(text)
-                .build(),
-            if context.is_history { Some(0) } else { None },
-        );
-
+        let (text, markup) = decode_text(data)?;
+        Self::render(message, text, markup, None, context);
         Ok(())
     }
 }
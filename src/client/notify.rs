@@ -1,6 +1,54 @@
+use std::{
+    io::{stdout, Write},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Mutex,
+    },
+    thread,
+};
+
+use crate::{
+    config::{
+        get_notification_mode, get_notifications, NotificationConfig, NotificationMode,
+        TerminalNotification,
+    },
+    message::{add_debug_message, MessageLevel},
+};
+
+/// Terminal focus as reported by crossterm focus events
+static FOCUSED: AtomicBool = AtomicBool::new(true);
+/// Bell or OSC sequence waiting for the UI loop, writing it from the message
+/// thread could land in the middle of a frame ratatui is drawing
+static TERMINAL_ALERT: Mutex<Option<String>> = Mutex::new(None);
+
+/// What a notification is about, each can be turned off in `[notifications]`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NotifyEvent {
+    Message,
+    Mention,
+    Presence,
+    Afk,
+}
+
+impl NotifyEvent {
+    fn enabled(&self, config: &NotificationConfig) -> bool {
+        match self {
+            NotifyEvent::Message => config.messages,
+            NotifyEvent::Mention => config.mentions,
+            NotifyEvent::Presence => config.presence,
+            NotifyEvent::Afk => config.afk,
+        }
+    }
+}
+
 pub struct Notifier {}
 
 impl Notifier {
+    /// Called on `FocusGained` and `FocusLost`
+    pub fn set_focused(focused: bool) {
+        FOCUSED.store(focused, Ordering::Relaxed);
+    }
+
     pub fn is_focused() -> bool {
         #[cfg(target_os = "windows")]
         unsafe {
@@ -20,47 +68,120 @@ impl Notifier {
         }
         #[cfg(not(target_os = "windows"))]
         {
-            true // Assume focused on non-Windows platforms
+            FOCUSED.load(Ordering::Relaxed)
+        }
+    }
+
+    pub fn notify_message(event: NotifyEvent, username: &str, message: &str) {
+        let config = get_notifications();
+        if !event.enabled(&config) || Self::is_focused() {
+            return;
+        }
+
+        if config.desktop {
+            Self::show_desktop(username, message);
+        }
+        Self::flash_window();
+        if let Some(sequence) = terminal_sequence(config.terminal, username, message) {
+            // only the latest one is worth showing
+            *TERMINAL_ALERT.lock().unwrap() = Some(sequence);
+        }
+    }
+
+    /// Write the queued terminal alert, called by the UI loop after drawing
+    pub fn flush_terminal_alert() {
+        let Some(sequence) = TERMINAL_ALERT.lock().unwrap().take() else {
+            return;
+        };
+        let mut out = stdout();
+        let _ = out.write_all(sequence.as_bytes());
+        let _ = out.flush();
+    }
+
+    /// Notify about a chat message, honouring the mentions-only mode
+    pub fn notify_chat_message(username: &str, message: &str, mentioned: bool) {
+        if mentioned {
+            Self::notify_message(NotifyEvent::Mention, username, message);
+        } else if get_notification_mode() == NotificationMode::All {
+            Self::notify_message(NotifyEvent::Message, username, message);
         }
     }
-    pub fn notify_message(username: &str, message: &str) {
+
+    /// Shown from its own thread, the D-Bus round trip on Linux can take a while
+    fn show_desktop(username: &str, message: &str) {
+        let username = username.to_string();
+        let message = message.to_string();
+        thread::spawn(move || {
+            use notify_rust::Notification;
+
+            if let Err(e) = Notification::new()
+                .appname("Orwell")
+                .summary(&username)
+                .body(&message)
+                .show()
+            {
+                add_debug_message(MessageLevel::Warning, format!("桌面通知失败: {}", e));
+            }
+        });
+    }
+
+    fn flash_window() {
         #[cfg(target_os = "windows")]
         unsafe {
             use winapi::um::wincon::GetConsoleWindow;
             use winapi::um::winuser::{
-                FlashWindowEx, GetForegroundWindow, GetParent, GetWindowThreadProcessId,
-                FLASHWINFO, FLASHW_TIMERNOFG, FLASHW_TRAY,
+                FlashWindowEx, GetParent, FLASHWINFO, FLASHW_TIMERNOFG, FLASHW_TRAY,
             };
 
-            let hwnd = GetForegroundWindow();
-            let mut focus_process_id = 0;
-            // name
-            GetWindowThreadProcessId(hwnd, &mut focus_process_id);
-            let this_process_hwnd = GetConsoleWindow();
-            let parent_hwnd = GetParent(this_process_hwnd);
-            let mut parent_process_id = 0;
-            GetWindowThreadProcessId(parent_hwnd, &mut parent_process_id);
+            let parent_hwnd = GetParent(GetConsoleWindow());
+            let mut pfwi = FLASHWINFO {
+                cbSize: std::mem::size_of::<FLASHWINFO>() as u32,
+                hwnd: parent_hwnd,
+                dwFlags: FLASHW_TRAY | FLASHW_TIMERNOFG,
+                uCount: u32::MAX,
+                dwTimeout: 100,
+            };
 
-            if focus_process_id != parent_process_id {
-                use notify_rust::Notification;
-
-                Notification::new()
-                    .appname("Orwell")
-                    .summary(username)
-                    .body(message)
-                    .show()
-                    .unwrap();
-
-                let mut pfwi = FLASHWINFO {
-                    cbSize: std::mem::size_of::<FLASHWINFO>() as u32,
-                    hwnd: parent_hwnd,
-                    dwFlags: FLASHW_TRAY | FLASHW_TIMERNOFG,
-                    uCount: u32::MAX,
-                    dwTimeout: 100,
-                };
-
-                FlashWindowEx(&mut pfwi);
-            }
+            FlashWindowEx(&mut pfwi);
         }
     }
 }
+
+/// Bell or OSC notification, control characters are dropped so message
+/// text cannot end the sequence early
+fn terminal_sequence(kind: TerminalNotification, title: &str, body: &str) -> Option<String> {
+    let clean = |text: &str| -> String { text.chars().filter(|c| !c.is_control()).collect() };
+    match kind {
+        TerminalNotification::Off => None,
+        TerminalNotification::Bell => Some("\x07".to_string()),
+        TerminalNotification::Osc9 => Some(format!("\x1b]9;{}: {}\x07", clean(title), clean(body))),
+        TerminalNotification::Osc777 => Some(format!(
+            "\x1b]777;notify;{};{}\x07",
+            clean(title).replace(';', ","),
+            clean(body)
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn terminal_sequences_strip_control_characters() {
+        assert_eq!(terminal_sequence(TerminalNotification::Off, "a", "b"), None);
+        assert_eq!(
+            terminal_sequence(TerminalNotification::Bell, "a", "b").as_deref(),
+            Some("\x07")
+        );
+        assert_eq!(
+            terminal_sequence(TerminalNotification::Osc9, "alice", "hi\x07\x1b]0;x\nthere")
+                .as_deref(),
+            Some("\x1b]9;alice: hi]0;xthere\x07")
+        );
+        assert_eq!(
+            terminal_sequence(TerminalNotification::Osc777, "a;b", "c;d").as_deref(),
+            Some("\x1b]777;notify;a,b;c;d\x07")
+        );
+    }
+}
@@ -1,6 +1,6 @@
 use ratatui::{
     layout::Rect,
-    style::Style,
+    style::{Color, Style},
     text::{Line as RatatuiLine, Span},
     widgets::{Block, Borders, Paragraph, Wrap},
     Frame,
@@ -9,11 +9,14 @@ use unicode_segmentation::UnicodeSegmentation;
 use unicode_width::UnicodeWidthStr;
 
 use crate::{
-    message::{calculate_optimal_prefix_width, get_time_format, DebugMessage, Line},
-    service::{ClientManager, Service},
-    theme::{Theme, THEME},
+    message::{
+        calculate_optimal_prefix_width, format_duration, get_time_format, DebugMessage, Line,
+    },
+    search::{highlight_spans, Search},
+    service::{ClientManager, Service, TypingManager},
+    theme::THEME,
 };
-use orwell::pb::orwell::ClientStatus;
+use orwell::{pb::orwell::ClientStatus, shared::helper::get_now_timestamp};
 
 /// 聊天消息渲染器
 pub struct ChatRenderer;
@@ -25,13 +28,22 @@ impl ChatRenderer {
         area: Rect,
         messages: &[Line],
         _scroll_offset: u16,
-    ) -> Vec<RatatuiLine<'static>> {
+        search: Option<&Search>,
+        divider: Option<usize>,
+    ) -> (Vec<RatatuiLine<'static>>, Vec<usize>) {
         let mut ratatui_lines: Vec<RatatuiLine> = Vec::new();
+        // where each message's rows end, lets the caller tell which messages are on screen
+        let mut line_ends = Vec::with_capacity(messages.len());
         let area_width = area.width.saturating_sub(2) as usize; // Account for borders
         let prefix_width = calculate_optimal_prefix_width(messages); // Auto-calculated width
         let time_format = get_time_format();
 
-        for msg in messages {
+        for (index, msg) in messages.iter().enumerate() {
+            // The divider counts as a row of the first unread message
+            if divider == Some(index) {
+                ratatui_lines.push(Self::divider_line(area_width));
+            }
+
             // Get formatted spans with fixed-width prefix
             let formatted_spans = msg.formatted_spans(prefix_width, time_format);
 
@@ -63,6 +75,60 @@ impl ChatRenderer {
                 }
             }
 
+            if let Some(search) = search.filter(|search| search.is_hit(index)) {
+                let text = content_spans
+                    .iter()
+                    .map(|span| span.content.as_ref())
+                    .collect::<String>();
+                let style = if search.is_current(index) {
+                    THEME.read().unwrap().search_current_style()
+                } else {
+                    THEME.read().unwrap().search_style()
+                };
+                content_spans = highlight_spans(content_spans, &search.match_ranges(&text), style);
+            }
+
+            // the " | " separator marks lines that mention us
+            if msg.is_mentioned() {
+                if let Some(separator) = prefix_spans.last_mut() {
+                    *separator = Span::styled(
+                        separator.content.clone(),
+                        THEME.read().unwrap().mention_style(),
+                    );
+                }
+            }
+
+            if msg.is_edited() {
+                content_spans.push(Span::styled(
+                    " (已编辑)",
+                    THEME.read().unwrap().edited_style(),
+                ));
+            }
+
+            if let Some(receipt) = msg.receipt().filter(|receipt| receipt.recipients > 0) {
+                let marker = if receipt.read >= receipt.recipients {
+                    Span::styled(" ✓✓", THEME.read().unwrap().read_receipt_style())
+                } else if receipt.delivered >= receipt.recipients {
+                    Span::styled(" ✓✓", THEME.read().unwrap().receipt_style())
+                } else {
+                    Span::styled(
+                        format!(" ✓ {}/{}", receipt.delivered, receipt.recipients),
+                        THEME.read().unwrap().receipt_style(),
+                    )
+                };
+                content_spans.push(marker);
+            }
+
+            if let Some(expires_at) = msg.expires_at() {
+                let remaining = expires_at
+                    .saturating_sub(get_now_timestamp())
+                    .div_ceil(1000);
+                content_spans.push(Span::styled(
+                    format!(" ⏱{}", format_duration(remaining)),
+                    THEME.read().unwrap().countdown_style(),
+                ));
+            }
+
             // Calculate prefix width for continuation lines
             let prefix_text_width = prefix_spans
                 .iter()
@@ -80,6 +146,28 @@ impl ChatRenderer {
             let continuation_padding_width = prefix_text_width.saturating_sub(time_span_width + 3); // -3 for " | "
             let continuation_padding = " ".repeat(continuation_padding_width);
 
+            // Quoted message of a reply sits on its own row above the reply
+            if let Some(quote) = msg.quote() {
+                let mut quote_width = prefix_text_width + 2;
+                let mut quote_text = String::new();
+                for grapheme in format!("{}: {}", quote.sender, quote.excerpt).graphemes(true) {
+                    quote_width += UnicodeWidthStr::width(grapheme);
+                    if quote_width > area_width {
+                        break;
+                    }
+                    quote_text.push_str(grapheme);
+                }
+                ratatui_lines.push(RatatuiLine::from(vec![
+                    Span::styled(
+                        " ".repeat(prefix_text_width.saturating_sub(3)),
+                        Style::default(),
+                    ),
+                    Span::styled(" | ", Style::default()),
+                    Span::styled("┌ ", THEME.read().unwrap().quote_style()),
+                    Span::styled(quote_text, THEME.read().unwrap().quote_style()),
+                ]));
+            }
+
             // Process content spans with wrapping
             let mut current_line_spans = prefix_spans.clone();
             let mut current_width = prefix_text_width;
@@ -91,8 +179,10 @@ impl ChatRenderer {
                 // Process each grapheme in the span
                 for grapheme in content_string.graphemes(true) {
                     let grapheme_width = UnicodeWidthStr::width(grapheme);
+                    // line breaks inside a message, e.g. around code blocks
+                    let line_break = grapheme == "\n" || grapheme == "\r\n";
 
-                    if current_width + grapheme_width > area_width {
+                    if line_break || current_width + grapheme_width > area_width {
                         // Need to wrap - push current line and start new one
                         if !current_line_spans.is_empty() {
                             ratatui_lines.push(RatatuiLine::from(current_line_spans.clone()));
@@ -112,6 +202,9 @@ impl ChatRenderer {
                             ]
                         };
                         current_width = prefix_text_width; // Reset to the base prefix width
+                        if line_break {
+                            continue;
+                        }
                     }
 
                     // Add grapheme to current span
@@ -138,9 +231,60 @@ impl ChatRenderer {
             if !current_line_spans.is_empty() {
                 ratatui_lines.push(RatatuiLine::from(current_line_spans));
             }
+
+            if !msg.reactions().is_empty() {
+                let mut reaction_spans = vec![
+                    Span::styled(
+                        " ".repeat(prefix_text_width.saturating_sub(3)),
+                        Style::default(),
+                    ),
+                    Span::styled(" | ", Style::default()),
+                ];
+                for (emoji, count) in msg.reactions() {
+                    reaction_spans.push(Span::styled(
+                        format!(" {} {} ", emoji, count),
+                        THEME.read().unwrap().reaction_style(),
+                    ));
+                    reaction_spans.push(Span::raw(" "));
+                }
+                ratatui_lines.push(RatatuiLine::from(reaction_spans));
+            }
+
+            line_ends.push(ratatui_lines.len());
         }
 
-        ratatui_lines
+        (ratatui_lines, line_ends)
+    }
+
+    /// "新消息" centered in a rule across the chat
+    fn divider_line(width: usize) -> RatatuiLine<'static> {
+        let label = " 新消息 ";
+        let side = width.saturating_sub(UnicodeWidthStr::width(label)) / 2;
+        let rule = "─".repeat(side);
+        RatatuiLine::from(Span::styled(
+            format!("{}{}{}", rule, label, rule),
+            THEME.read().unwrap().unread_style(),
+        ))
+    }
+
+    /// Indices of the messages with at least one row inside the visible window
+    pub fn visible_messages(
+        line_ends: &[usize],
+        visible_count: usize,
+        scroll_offset: u16,
+    ) -> Vec<usize> {
+        let total_lines = line_ends.last().copied().unwrap_or(0);
+        let end = total_lines.saturating_sub(scroll_offset as usize);
+        let start = end.saturating_sub(visible_count);
+        let mut message_start = 0;
+        let mut visible = Vec::new();
+        for (index, &message_end) in line_ends.iter().enumerate() {
+            if message_start < end && message_end > start {
+                visible.push(index);
+            }
+            message_start = message_end;
+        }
+        visible
     }
 
     /// 处理滚动逻辑并返回可见的行
@@ -184,8 +328,8 @@ impl ChatRenderer {
         let messages_block = Block::default()
             .title("Chat")
             .borders(Borders::ALL)
-            .border_style(THEME.border_style())
-            .style(THEME.message_style());
+            .border_style(THEME.read().unwrap().border_style())
+            .style(THEME.read().unwrap().message_style());
 
         Paragraph::new(lines)
             .block(messages_block)
@@ -244,13 +388,13 @@ impl DebugRenderer {
                     // First line has level
                     vec![
                         Span::styled(format!("[{}] ", level.to_string()), level.style()),
-                        Span::styled(line.clone(), THEME.debug_style()),
+                        Span::styled(line.clone(), THEME.read().unwrap().debug_style()),
                     ]
                 } else {
                     // Subsequent lines only have content
                     vec![
-                        Span::styled(prefix, THEME.debug_style()),
-                        Span::styled(line.clone(), THEME.debug_style()),
+                        Span::styled(prefix, THEME.read().unwrap().debug_style()),
+                        Span::styled(line.clone(), THEME.read().unwrap().debug_style()),
                     ]
                 };
 
@@ -276,8 +420,8 @@ impl DebugRenderer {
                 Block::default()
                     .title("Debug")
                     .borders(Borders::ALL)
-                    .border_style(THEME.border_style())
-                    .style(THEME.debug_style()),
+                    .border_style(THEME.read().unwrap().border_style())
+                    .style(THEME.read().unwrap().debug_style()),
             )
             .scroll((scroll_offset, 0))
     }
@@ -289,37 +433,62 @@ pub struct StateRenderer;
 impl StateRenderer {
     /// 渲染状态信息区域（连接状态）
     pub fn render_connected(state: &crate::State) -> Vec<RatatuiLine> {
+        let theme = THEME.read().unwrap();
         let mut state_text = vec![
             RatatuiLine::from(vec![Span::styled(
                 format!(" \u{eb50} {}", state.server_url),
-                Style::default().fg(Theme::catppuccin().lavender),
+                Style::default().fg(theme.lavender),
             )]),
             RatatuiLine::from(vec![Span::styled(
                 format!(" \u{eae8} {} B", state.processed_bytes),
-                Style::default().fg(Theme::catppuccin().lavender),
+                Style::default().fg(theme.lavender),
             )]),
             RatatuiLine::from(vec![Span::styled(
                 format!(" \u{f013} 棘轮转动 {} 次", state.ratchet_roll_time),
-                Style::default().fg(Theme::catppuccin().lavender),
+                Style::default().fg(theme.lavender),
             )]),
             RatatuiLine::from(vec![Span::styled(
                 format!(" \u{f199f} {}", Service::get_online_time(state.start_time)),
-                Style::default().fg(Theme::catppuccin().lavender),
+                Style::default().fg(theme.lavender),
             )]),
             RatatuiLine::from(vec![]),
-            RatatuiLine::from(vec![Span::styled(
-                format!(
-                    "\u{f007} 用户列表 ({}/{})",
-                    ClientManager::get_all_clients()
-                        .iter()
-                        .filter(|c| c.status == ClientStatus::Online)
-                        .count(),
-                    ClientManager::get_all_clients().len(),
-                ),
-                Style::default().fg(Theme::catppuccin().lavender),
-            )]),
         ];
 
+        let typing = TypingManager::get_typing_clients();
+        if !typing.is_empty() {
+            let mut spans = vec![Span::styled(
+                " \u{f040} ",
+                Style::default().fg(theme.lavender),
+            )];
+            for (i, (name, color)) in typing.iter().enumerate() {
+                if i > 0 {
+                    spans.push(Span::styled(", ", Style::default().fg(theme.lavender)));
+                }
+                spans.push(Span::styled(
+                    name.clone(),
+                    Style::default().fg(Color::from_u32(*color as u32)),
+                ));
+            }
+            spans.push(Span::styled(
+                " 正在输入…",
+                Style::default().fg(theme.lavender),
+            ));
+            state_text.push(RatatuiLine::from(spans));
+            state_text.push(RatatuiLine::from(vec![]));
+        }
+
+        state_text.push(RatatuiLine::from(vec![Span::styled(
+            format!(
+                "\u{f007} 用户列表 ({}/{})",
+                ClientManager::get_all_clients()
+                    .iter()
+                    .filter(|c| c.status == ClientStatus::Online)
+                    .count(),
+                ClientManager::get_all_clients().len(),
+            ),
+            Style::default().fg(theme.lavender),
+        )]));
+
         ClientManager::get_all_clients_sorted()
             .iter()
             .for_each(|client| {
@@ -327,15 +496,12 @@ impl StateRenderer {
                     Span::styled(
                         "\u{f1eb} ".to_string(),
                         Style::default().fg(match client.status {
-                            ClientStatus::Online => Theme::catppuccin().green,
-                            ClientStatus::Offline => Theme::catppuccin().red,
-                            ClientStatus::Afk => Theme::catppuccin().yellow,
+                            ClientStatus::Online => theme.green,
+                            ClientStatus::Offline => theme.red,
+                            ClientStatus::Afk => theme.yellow,
                         }),
                     ),
-                    Span::styled(
-                        client.name.to_string(),
-                        Style::default().fg(Theme::catppuccin().lavender),
-                    ),
+                    Span::styled(client.name.to_string(), Style::default().fg(theme.lavender)),
                 ]));
             });
 
@@ -344,9 +510,10 @@ impl StateRenderer {
 
     /// 渲染状态信息区域（未连接状态）
     pub fn render_disconnected<'a>() -> Vec<RatatuiLine<'a>> {
+        let theme = THEME.read().unwrap();
         vec![RatatuiLine::from(vec![Span::styled(
             "未连接",
-            Style::default().fg(Theme::catppuccin().lavender),
+            Style::default().fg(theme.lavender),
         )])]
     }
 
@@ -355,8 +522,8 @@ impl StateRenderer {
         let state_block = Block::default()
             .title("State")
             .borders(Borders::ALL)
-            .border_style(THEME.border_style())
-            .style(THEME.message_style());
+            .border_style(THEME.read().unwrap().border_style())
+            .style(THEME.read().unwrap().message_style());
 
         Paragraph::new(lines)
             .block(state_block)
@@ -0,0 +1,343 @@
+use std::ops::Range;
+
+use ratatui::{style::Style, text::Span};
+use regex::{Regex, RegexBuilder};
+
+use crate::message::Line;
+
+/// Incremental search over the chat, `from:name` in the query keeps only
+/// messages whose sender starts with `name`
+pub struct Search {
+    query: String,
+    regex: bool,
+    matcher: Result<Matcher, String>,
+    /// Indices of the matching messages, oldest first
+    hits: Vec<usize>,
+    /// Message index of the selected hit
+    current: Option<usize>,
+    /// The selected hit changed and should be scrolled into view
+    scroll_pending: bool,
+}
+
+struct Matcher {
+    pattern: Option<Regex>,
+    sender: Option<String>,
+}
+
+impl Matcher {
+    fn new(query: &str, regex: bool) -> Result<Self, String> {
+        let mut sender = None;
+        let mut words = Vec::new();
+        for word in query.split(' ') {
+            match word.strip_prefix("from:") {
+                Some(name) if !name.is_empty() => sender = Some(name.to_lowercase()),
+                _ => words.push(word),
+            }
+        }
+        let text = words.join(" ");
+        let text = text.trim();
+
+        let pattern = if text.is_empty() {
+            None
+        } else {
+            let source = if regex {
+                text.to_string()
+            } else {
+                regex::escape(text)
+            };
+            let pattern = RegexBuilder::new(&source)
+                .case_insensitive(true)
+                .build()
+                .map_err(|e| e.to_string())?;
+            Some(pattern)
+        };
+        Ok(Self { pattern, sender })
+    }
+
+    fn matches(&self, line: &Line) -> bool {
+        if self.pattern.is_none() && self.sender.is_none() {
+            return false;
+        }
+        if !self.matches_sender(line) {
+            return false;
+        }
+        self.pattern
+            .as_ref()
+            .is_none_or(|pattern| pattern.is_match(&line.display_text()))
+    }
+
+    fn matches_sender(&self, line: &Line) -> bool {
+        self.sender.as_ref().is_none_or(|sender| {
+            line.sender()
+                .content()
+                .to_lowercase()
+                .starts_with(sender.as_str())
+        })
+    }
+}
+
+impl Search {
+    pub fn new(query: impl Into<String>, regex: bool) -> Self {
+        let query = query.into();
+        Self {
+            matcher: Matcher::new(&query, regex),
+            query,
+            regex,
+            hits: Vec::new(),
+            current: None,
+            scroll_pending: false,
+        }
+    }
+
+    pub fn push(&mut self, c: char) {
+        self.query.push(c);
+        self.recompile();
+    }
+
+    pub fn pop(&mut self) {
+        self.query.pop();
+        self.recompile();
+    }
+
+    pub fn toggle_regex(&mut self) {
+        self.regex = !self.regex;
+        self.recompile();
+    }
+
+    /// A changed query starts again from the newest hit
+    fn recompile(&mut self) {
+        self.matcher = Matcher::new(&self.query, self.regex);
+        self.current = None;
+    }
+
+    /// Find the hits among `messages`, keeping the selection where possible
+    pub fn update(&mut self, messages: &[Line]) {
+        self.hits = match &self.matcher {
+            Ok(matcher) => messages
+                .iter()
+                .enumerate()
+                .filter(|(_, line)| matcher.matches(line))
+                .map(|(index, _)| index)
+                .collect(),
+            Err(_) => Vec::new(),
+        };
+
+        let current = match self.current {
+            Some(current) => self
+                .hits
+                .iter()
+                .rev()
+                .find(|&&hit| hit <= current)
+                .or(self.hits.first())
+                .copied(),
+            None => self.hits.last().copied(),
+        };
+        if current != self.current {
+            self.current = current;
+            self.scroll_pending = current.is_some();
+        }
+    }
+
+    /// Select the next older hit, wrapping to the newest
+    pub fn older(&mut self) {
+        let Some(current) = self.current else {
+            return;
+        };
+        self.current = self
+            .hits
+            .iter()
+            .rev()
+            .find(|&&hit| hit < current)
+            .or(self.hits.last())
+            .copied();
+        self.scroll_pending = true;
+    }
+
+    /// Select the next newer hit, wrapping to the oldest
+    pub fn newer(&mut self) {
+        let Some(current) = self.current else {
+            return;
+        };
+        self.current = self
+            .hits
+            .iter()
+            .find(|&&hit| hit > current)
+            .or(self.hits.first())
+            .copied();
+        self.scroll_pending = true;
+    }
+
+    /// Message to scroll to, once per change of the selected hit
+    pub fn take_scroll(&mut self) -> Option<usize> {
+        if !std::mem::take(&mut self.scroll_pending) {
+            return None;
+        }
+        self.current
+    }
+
+    pub fn is_hit(&self, index: usize) -> bool {
+        self.hits.binary_search(&index).is_ok()
+    }
+
+    pub fn is_current(&self, index: usize) -> bool {
+        self.current == Some(index)
+    }
+
+    /// Byte ranges of `text` matching the query
+    pub fn match_ranges(&self, text: &str) -> Vec<Range<usize>> {
+        match &self.matcher {
+            Ok(Matcher {
+                pattern: Some(pattern),
+                ..
+            }) => pattern
+                .find_iter(text)
+                .filter(|found| !found.is_empty())
+                .map(|found| found.range())
+                .collect(),
+            _ => Vec::new(),
+        }
+    }
+
+    /// Overlay line such as `搜索 [正则]: foo  2/5`
+    pub fn status(&self) -> String {
+        let mode = if self.regex { " [正则]" } else { "" };
+        let result = match &self.matcher {
+            Err(e) => format!("正则有误: {}", e.lines().last().unwrap_or_default()),
+            Ok(_) if self.query.trim().is_empty() => String::new(),
+            Ok(_) => match self.current {
+                Some(current) => {
+                    let position = self.hits.iter().position(|&hit| hit == current);
+                    format!("{}/{}", position.map_or(0, |p| p + 1), self.hits.len())
+                }
+                None => "无结果".to_string(),
+            },
+        };
+        format!("搜索{}: {}  {}", mode, self.query, result)
+    }
+}
+
+/// Patch `style` onto the parts of `spans` inside `ranges`, which are byte
+/// ranges into the concatenated span contents
+pub fn highlight_spans(
+    spans: Vec<Span<'static>>,
+    ranges: &[Range<usize>],
+    style: Style,
+) -> Vec<Span<'static>> {
+    if ranges.is_empty() {
+        return spans;
+    }
+
+    let mut result = Vec::with_capacity(spans.len());
+    let mut offset = 0;
+    for span in spans {
+        let content = span.content.to_string();
+        let span_range = offset..offset + content.len();
+        offset = span_range.end;
+
+        let mut cursor = span_range.start;
+        for range in ranges {
+            let start = range.start.max(cursor);
+            let end = range.end.min(span_range.end);
+            if start >= end {
+                continue;
+            }
+            if start > cursor {
+                result.push(Span::styled(
+                    content[cursor - span_range.start..start - span_range.start].to_string(),
+                    span.style,
+                ));
+            }
+            result.push(Span::styled(
+                content[start - span_range.start..end - span_range.start].to_string(),
+                span.style.patch(style),
+            ));
+            cursor = end;
+        }
+        if cursor < span_range.end {
+            result.push(Span::styled(
+                content[cursor - span_range.start..].to_string(),
+                span.style,
+            ));
+        }
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use ratatui::style::Color;
+
+    use super::*;
+    use crate::message::{LineBuilder, TextSpan};
+
+    fn line(sender: &str, text: &str) -> Line {
+        LineBuilder::new()
+            .sender(TextSpan::plain(sender))
+            .mention_text(text, None)
+            .build()
+    }
+
+    #[test]
+    fn navigate_hits() {
+        let messages = vec![
+            line("alice", "Hello there"),
+            line("bob", "nothing"),
+            line("bob", "hello again"),
+            line("alice", "HELLO!"),
+        ];
+
+        let mut search = Search::new("hello", false);
+        search.update(&messages);
+        assert_eq!(search.take_scroll(), Some(3));
+        assert_eq!(search.take_scroll(), None);
+        assert_eq!(search.status(), "搜索: hello  3/3");
+
+        search.older();
+        assert!(search.is_current(2));
+        search.older();
+        search.older();
+        assert!(search.is_current(3));
+        search.newer();
+        assert!(search.is_current(0));
+
+        let mut search = Search::new("from:bob hel", false);
+        search.update(&messages);
+        assert!(search.is_hit(2) && !search.is_hit(0) && !search.is_hit(3));
+
+        let mut search = Search::new("l{2}o a", true);
+        search.update(&messages);
+        assert!(search.is_hit(2) && !search.is_hit(0));
+
+        let mut search = Search::new("(", true);
+        search.update(&messages);
+        assert!(search.status().contains("正则有误"));
+        search.toggle_regex();
+        search.update(&messages);
+        assert!(search.status().ends_with("无结果"));
+    }
+
+    #[test]
+    fn highlight_across_spans() {
+        let bold = Style::default().fg(Color::Red);
+        let mark = Style::default().bg(Color::Yellow);
+        let spans = vec![Span::raw("hello "), Span::styled("world", bold)];
+        let search = Search::new("O W", false);
+        let ranges = search.match_ranges("hello world");
+        assert_eq!(ranges, vec![4..7]);
+
+        let result = highlight_spans(spans, &ranges, mark);
+        let parts = result
+            .iter()
+            .map(|span| (span.content.as_ref(), span.style))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            parts,
+            vec![
+                ("hell", Style::default()),
+                ("o ", mark),
+                ("w", bold.patch(mark)),
+                ("orld", bold),
+            ]
+        );
+    }
+}
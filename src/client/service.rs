@@ -1,22 +1,32 @@
-use std::{collections::HashMap, sync::RwLock};
+use std::{
+    collections::{HashMap, HashSet},
+    sync::RwLock,
+};
 
 use anyhow::{anyhow, Result};
 use color_eyre::owo_colors::OwoColorize;
 use lazy_static::lazy_static;
 use orwell::{
     pb::orwell::{
-        ClientAfk, ClientChangeColor, ClientMessage, ClientStatus, Key, MessageType, OrwellPacket,
-        PacketType, ServerBroadcastMessage,
+        ClientAfk, ClientChangeColor, ClientMessage, ClientReceipt, ClientStatus, ClientTyping,
+        DisappearingTimerSetting, ExpiringMessage, Key, MessageAction, MessageDelete, MessageEdit,
+        MessageType, OrwellPacket, PacketType, ReactionMessage, ReplyMessage,
+        ServerBroadcastMessage,
     },
     shared::{encryption::Encryption, helper::get_now_timestamp},
 };
+use prost::Message;
 use rand::Rng;
 use ratatui::style::{Color, Style};
 
 use crate::{
+    cache::CacheManager,
+    config::get_read_receipts,
     key::KEY_MANAGER,
+    markup::{encode_text, has_markup},
     message::{
-        add_chat_message, add_chat_message_rich, add_debug_message, LineBuilder, MessageLevel,
+        add_chat_message, add_chat_message_rich, add_debug_message, get_disappearing_timer,
+        update_disappearing_timer, LineBuilder, MessageLevel,
     },
     network::{Network, NETWORK},
     App, STATE,
@@ -30,13 +40,32 @@ pub struct ClientInfo {
     pub name: String,
     pub color: i32,
     pub kyber_pk: Vec<u8>,
+    pub dilithium_pk: Vec<u8>,
     pub status: ClientStatus,
 }
 
 lazy_static! {
     pub static ref OTHER_CLIENTS: RwLock<HashMap<String, ClientInfo>> = RwLock::new(HashMap::new());
+    /// Who is typing: client id -> (name, color, when the signal runs out)
+    static ref TYPING_CLIENTS: RwLock<HashMap<String, (String, i32, u64)>> =
+        RwLock::new(HashMap::new());
+    /// Whether we last told the server we are typing, and when
+    static ref TYPING_SENT: RwLock<(bool, u64)> = RwLock::new((false, 0));
+    /// Message ids waiting to be acknowledged: (delivered, read)
+    static ref PENDING_RECEIPTS: RwLock<(Vec<String>, Vec<String>)> =
+        RwLock::new((Vec::new(), Vec::new()));
+    /// Message ids already acknowledged this session: (delivered, read)
+    static ref SENT_RECEIPTS: RwLock<(HashSet<String>, HashSet<String>)> =
+        RwLock::new((HashSet::new(), HashSet::new()));
 }
 
+/// A typing signal is resent this often while the input has content
+const TYPING_RESEND_MS: u64 = 3000;
+/// Others stop showing as typing when no signal arrived for this long
+const TYPING_TIMEOUT_MS: u64 = 6000;
+/// Upper bound of message ids in one receipt packet, the server allows 200
+const RECEIPT_BATCH: usize = 100;
+
 pub struct ClientManager {}
 
 impl ClientManager {
@@ -58,6 +87,46 @@ impl ClientManager {
         clients
     }
 
+    pub fn get_client(id: &str) -> Option<ClientInfo> {
+        OTHER_CLIENTS.read().unwrap().get(id).cloned()
+    }
+
+    /// Our own entry in the client list, matched by the profile's kyber key
+    pub fn get_self_id() -> Option<String> {
+        let key_manager = KEY_MANAGER.read().unwrap();
+        let kyber_pk = key_manager.as_ref()?.profile.as_ref()?.kyber_pk.clone();
+        drop(key_manager);
+        OTHER_CLIENTS
+            .read()
+            .unwrap()
+            .values()
+            .find(|client| client.kyber_pk == kyber_pk)
+            .map(|client| client.id.clone())
+    }
+
+    /// Name of the loaded profile, the one others @mention us with
+    pub fn get_self_name() -> Option<String> {
+        let key_manager = KEY_MANAGER.read().unwrap();
+        Some(key_manager.as_ref()?.profile.as_ref()?.name.clone())
+    }
+
+    /// Known client that signs with `dilithium_pk`
+    pub fn find_by_dilithium_pk(dilithium_pk: &[u8]) -> Option<ClientInfo> {
+        OTHER_CLIENTS
+            .read()
+            .unwrap()
+            .values()
+            .find(|client| client.dilithium_pk == dilithium_pk)
+            .cloned()
+    }
+
+    /// Check a payload signature against the sender's dilithium key
+    pub fn verify_signature(id: &str, data: &[u8], sign: &[u8]) -> bool {
+        Self::get_client(id)
+            .and_then(|client| Encryption::dilithium_verify(data, &client.dilithium_pk, sign).ok())
+            .unwrap_or(false)
+    }
+
     pub fn add_client(client: ClientInfo) {
         let mut clients = OTHER_CLIENTS.write().unwrap();
         clients.insert(client.id.clone(), client);
@@ -77,6 +146,104 @@ impl ClientManager {
     }
 }
 
+pub struct TypingManager {}
+
+impl TypingManager {
+    pub fn update(id: String, name: String, color: i32, typing: bool) {
+        let mut clients = TYPING_CLIENTS.write().unwrap();
+        if typing {
+            clients.insert(id, (name, color, get_now_timestamp() + TYPING_TIMEOUT_MS));
+        } else {
+            clients.remove(&id);
+        }
+    }
+
+    /// Name and color of everyone whose typing signal has not run out
+    pub fn get_typing_clients() -> Vec<(String, i32)> {
+        let now = get_now_timestamp();
+        let mut clients = TYPING_CLIENTS.write().unwrap();
+        clients.retain(|_, (_, _, expires_at)| *expires_at > now);
+        let mut typing = clients
+            .values()
+            .map(|(name, color, _)| (name.clone(), *color))
+            .collect::<Vec<_>>();
+        typing.sort();
+        typing
+    }
+}
+
+pub struct ReceiptManager {}
+
+impl ReceiptManager {
+    /// Acknowledge that a message from someone else reached us
+    pub fn queue_delivered(id: &str) {
+        let sent = SENT_RECEIPTS.read().unwrap();
+        if sent.0.contains(id) || sent.1.contains(id) {
+            return;
+        }
+        let mut pending = PENDING_RECEIPTS.write().unwrap();
+        if !pending.0.iter().any(|pending_id| pending_id == id) {
+            pending.0.push(id.to_string());
+        }
+    }
+
+    /// Acknowledge that messages from someone else were on screen, unless
+    /// read receipts are turned off
+    pub fn queue_read(ids: Vec<String>) {
+        if !get_read_receipts() {
+            return;
+        }
+        let sent = SENT_RECEIPTS.read().unwrap();
+        let mut pending = PENDING_RECEIPTS.write().unwrap();
+        for id in ids {
+            if !sent.1.contains(&id) && !pending.1.contains(&id) {
+                pending.1.push(id);
+            }
+        }
+    }
+
+    /// Send everything queued since the last call, a read receipt also
+    /// counts as delivered
+    pub fn flush() {
+        if !STATE.read().unwrap().logged {
+            return;
+        }
+        // Lock order is NETWORK before the receipt sets: the message thread
+        // queues receipts while holding NETWORK, so never wait for NETWORK
+        // with PENDING_RECEIPTS or SENT_RECEIPTS held
+        let (delivered, read) = std::mem::take(&mut *PENDING_RECEIPTS.write().unwrap());
+        if delivered.is_empty() && read.is_empty() {
+            return;
+        }
+        let delivered = delivered
+            .into_iter()
+            .filter(|id| !read.contains(id))
+            .collect::<Vec<_>>();
+
+        {
+            let mut network = NETWORK.write().unwrap();
+            let Some(network) = network.as_mut() else {
+                return;
+            };
+            for (ids, read) in [(&delivered, false), (&read, true)] {
+                for chunk in ids.chunks(RECEIPT_BATCH) {
+                    network.send_packet(
+                        PacketType::ClientReceipt,
+                        ClientReceipt {
+                            message_ids: chunk.to_vec(),
+                            read,
+                        },
+                    );
+                }
+            }
+        }
+
+        let mut sent = SENT_RECEIPTS.write().unwrap();
+        sent.0.extend(delivered);
+        sent.1.extend(read);
+    }
+}
+
 pub struct Service {}
 
 impl Service {
@@ -105,6 +272,28 @@ impl Service {
         network.send_packet(PacketType::ClientAfk, ClientAfk {});
     }
 
+    /// Tell the others whether we are typing, called whenever the input changes.
+    ///
+    /// While typing the signal is repeated every few seconds at most, commands
+    /// never count as typing.
+    pub fn update_typing(text: &str) {
+        if !STATE.read().unwrap().logged {
+            return;
+        }
+        let typing = !text.trim().is_empty() && !text.starts_with('/');
+        let now = get_now_timestamp();
+        let mut sent = TYPING_SENT.write().unwrap();
+        if typing == sent.0 && (!typing || now.saturating_sub(sent.1) < TYPING_RESEND_MS) {
+            return;
+        }
+        let mut network = NETWORK.write().unwrap();
+        let Some(network) = network.as_mut() else {
+            return;
+        };
+        network.send_packet(PacketType::ClientTyping, ClientTyping { typing });
+        *sent = (typing, now);
+    }
+
     pub fn check_login(app: &App) {
         add_debug_message(MessageLevel::Info, "正在检查登录状态...");
         if STATE.read().unwrap().logged {
@@ -124,7 +313,15 @@ impl Service {
         }
     }
 
-    pub fn handle_message(packet: &ServerBroadcastMessage, is_history: bool) -> Result<()> {
+    pub fn handle_message(
+        packet: &ServerBroadcastMessage,
+        is_history: bool,
+        is_offline: bool,
+    ) -> Result<()> {
+        // history the local cache already showed
+        if is_history && !is_offline && CacheManager::contains(&packet.id) {
+            return Ok(());
+        }
         let key = packet.key.clone();
         if key.is_none() {
             return Err(anyhow!("数据异常"));
@@ -137,8 +334,47 @@ impl Service {
         let data = Encryption::aes_decrypt(&packet.data, &key)?;
 
         let registry = create_message_registry();
-        let context = MessageContext { is_history };
-        registry.process_message(packet, data, context)
+        let context = MessageContext {
+            is_history,
+            is_offline,
+            expires_at: None,
+        };
+        registry.process_message(packet, data.clone(), context)?;
+        // only what we could show is worth keeping
+        CacheManager::store(packet, &data);
+        Ok(())
+    }
+
+    /// Show the cached messages of this server, the history that follows
+    /// skips whatever they already cover
+    pub fn load_cached_messages(server_url: &str) {
+        let messages = match CacheManager::open(server_url) {
+            Ok(messages) => messages,
+            Err(e) => {
+                add_chat_message(format!("读取本地缓存失败: {}，可使用 /wipe-cache 清除", e));
+                return;
+            }
+        };
+        if messages.is_empty() {
+            return;
+        }
+
+        let registry = create_message_registry();
+        // placed by timestamp, the history that follows slots in between
+        for message in &messages {
+            let context = MessageContext {
+                is_history: true,
+                is_offline: false,
+                expires_at: None,
+            };
+            if let Err(e) = registry.process_message(message, message.data.clone(), context) {
+                add_debug_message(MessageLevel::Warning, format!("缓存消息处理失败: {}", e));
+            }
+        }
+        add_debug_message(
+            MessageLevel::Info,
+            format!("已从本地缓存载入 {} 条消息", messages.len()),
+        );
     }
 
     pub fn handle_packet(packet: OrwellPacket, network: &mut Network) -> Result<()> {
@@ -147,15 +383,148 @@ impl Service {
     }
 
     pub fn broadcast_message(message: String) -> Result<()> {
+        Self::broadcast_ephemeral_message(message, 0)
+    }
+
+    /// Send an action message, shown to everyone as "* name action".
+    pub fn broadcast_action(action: String) -> Result<()> {
+        let mut payload = action.as_bytes().to_vec();
+        payload.insert(0, MessageType::Me as u8);
+        Self::broadcast_payload(payload, 0)
+    }
+
+    /// The server drops the message from history `ttl` seconds after sending, 0 keeps it.
+    pub fn broadcast_ephemeral_message(message: String, ttl: u64) -> Result<()> {
+        let mut payload = encode_text(&message);
+        payload.insert(0, MessageType::Text as u8);
+        Self::broadcast_payload(payload, ttl)
+    }
+
+    /// Reply to an earlier message, `excerpt` is shown above the reply.
+    pub fn reply_message(
+        target_id: String,
+        quoted_sender: String,
+        excerpt: String,
+        text: String,
+    ) -> Result<()> {
+        let mut payload = ReplyMessage {
+            target_id,
+            quoted_sender,
+            excerpt,
+            markup: has_markup(&text),
+            text,
+        }
+        .encode_to_vec();
+        payload.insert(0, MessageType::Reply as u8);
+        Self::broadcast_payload(payload, 0)
+    }
+
+    /// React to a message with an emoji, `remove` takes an earlier reaction back.
+    pub fn react_to_message(target_id: String, emoji: String, remove: bool) -> Result<()> {
+        let mut payload = ReactionMessage {
+            target_id: target_id.clone(),
+            emoji,
+            remove,
+        }
+        .encode_to_vec();
+        payload.insert(0, MessageType::Reaction as u8);
+        let (payload, ttl) = Self::wrap_disappearing(payload, 0);
+        Self::send_payload(payload, ttl, MessageAction::React, target_id)
+    }
+
+    /// Send a message payload (type byte first) to every client.
+    ///
+    /// While the conversation has a disappearing timer the payload is wrapped
+    /// in an `Expiring` message and the server drops it from history as well.
+    pub fn broadcast_payload(payload: Vec<u8>, ttl: u64) -> Result<()> {
+        let (payload, ttl) = Self::wrap_disappearing(payload, ttl);
+        Self::send_payload(payload, ttl, MessageAction::Post, String::new())
+    }
+
+    fn wrap_disappearing(payload: Vec<u8>, ttl: u64) -> (Vec<u8>, u64) {
+        let timer = get_disappearing_timer();
+        if timer == 0 {
+            return (payload, ttl);
+        }
+        let mut wrapped = ExpiringMessage {
+            timer,
+            data: payload,
+        }
+        .encode_to_vec();
+        wrapped.insert(0, MessageType::Expiring as u8);
+        (wrapped, if ttl == 0 { timer } else { ttl.min(timer) })
+    }
+
+    /// Replace the text of one of our own messages.
+    pub fn edit_message(target_id: String, text: String) -> Result<()> {
+        let mut edit = MessageEdit {
+            target_id: target_id.clone(),
+            markup: has_markup(&text),
+            text,
+            sign: vec![],
+        };
+        edit.sign = Self::sign_payload(MessageType::Edit, &edit.encode_to_vec())?;
+        let mut payload = edit.encode_to_vec();
+        payload.insert(0, MessageType::Edit as u8);
+        let (payload, ttl) = Self::wrap_disappearing(payload, 0);
+        Self::send_payload(payload, ttl, MessageAction::Edit, target_id)
+    }
+
+    /// Delete one of our own messages, the server drops it from history.
+    pub fn delete_message(target_id: String) -> Result<()> {
+        let mut delete = MessageDelete {
+            target_id: target_id.clone(),
+            sign: vec![],
+        };
+        delete.sign = Self::sign_payload(MessageType::Delete, &delete.encode_to_vec())?;
+        let mut payload = delete.encode_to_vec();
+        payload.insert(0, MessageType::Delete as u8);
+        let (payload, ttl) = Self::wrap_disappearing(payload, 0);
+        Self::send_payload(payload, ttl, MessageAction::Delete, target_id)
+    }
+
+    /// Sign an edit or delete encoded with an empty sign, prefixed with its type byte.
+    fn sign_payload(message_type: MessageType, unsigned: &[u8]) -> Result<Vec<u8>> {
+        let key_manager = KEY_MANAGER.read().unwrap();
+        let profile = key_manager
+            .as_ref()
+            .and_then(|manager| manager.profile.clone())
+            .ok_or_else(|| anyhow!("尚未加载身份"))?;
+        drop(key_manager);
+        let mut data = unsigned.to_vec();
+        data.insert(0, message_type as u8);
+        Encryption::dilithium_sign(&data, &profile.dilithium_sk)
+    }
+
+    /// Change the disappearing timer for everyone in the conversation, 0 turns it off.
+    pub fn set_disappearing_timer(seconds: u64) -> Result<()> {
+        let mut payload = DisappearingTimerSetting { seconds }.encode_to_vec();
+        payload.insert(0, MessageType::DisappearingTimer as u8);
+        // the setting itself must not disappear, late joiners learn it from history
+        Self::send_payload(payload, 0, MessageAction::Post, String::new())?;
+        update_disappearing_timer(seconds, get_now_timestamp());
+        Ok(())
+    }
+
+    fn send_payload(
+        payload: Vec<u8>,
+        ttl: u64,
+        action: MessageAction,
+        target_id: String,
+    ) -> Result<()> {
         let mut network = NETWORK.write().unwrap();
         if network.is_none() {
             return Err(anyhow::anyhow!("未连接到服务器"));
         }
         let network = network.as_mut().unwrap();
-        let mut message = message.as_bytes().to_vec();
-        message.insert(0, MessageType::Text as u8);
-        let (keys, data) = Self::broadcast_data(message)?;
-        let mut packet = ClientMessage { keys: vec![], data };
+        let (keys, data) = Self::broadcast_data(payload)?;
+        let mut packet = ClientMessage {
+            keys: vec![],
+            data,
+            ttl,
+            action: action as i32,
+            target_id,
+        };
         for (id, ciphertext) in keys.iter() {
             packet.keys.push(Key {
                 receiver_id: id.clone(),
@@ -1,13 +1,30 @@
+use std::{fs, str::FromStr, sync::RwLock};
+
+use anyhow::{anyhow, Result};
 use lazy_static::lazy_static;
-use ratatui::style::{Color, Style};
+use ratatui::{
+    buffer::Buffer,
+    style::{Color, Modifier, Style},
+};
+
+use crate::config::get_truecolor;
+
+const THEME_FOLDER: &str = "./themes";
 
+/// Names accepted by `Theme::builtin`
+pub const BUILTIN_THEMES: [&str; 4] = ["catppuccin", "light", "high-contrast", "16color"];
+
+#[derive(Debug, Clone)]
 pub struct Theme {
+    pub name: String,
+
     // Base colors
     pub base: Color,     // Base background
     pub mantle: Color,   // Slightly lighter background
     pub crust: Color,    // Darker background
     pub surface0: Color, // Surface color for input
     pub surface1: Color, // Surface color for messages
+    pub overlay0: Color, // Muted text such as timestamps
 
     // Text colors
     pub text: Color,     // Primary text
@@ -34,12 +51,15 @@ pub struct Theme {
 impl Theme {
     pub fn catppuccin() -> Self {
         Self {
+            name: "catppuccin".to_string(),
+
             // Base colors
             base: Color::Rgb(24, 24, 37),     // #1e1e2e
             mantle: Color::Rgb(36, 36, 35),   // #1e1e2e
             crust: Color::Rgb(17, 17, 27),    // #11111b
             surface0: Color::Rgb(49, 50, 68), // #313244
             surface1: Color::Rgb(69, 71, 90), // #45475a
+            overlay0: Color::Rgb(100, 100, 100),
 
             // Text colors
             text: Color::Rgb(255, 255, 255),     // #cdd6f4
@@ -64,6 +84,180 @@ impl Theme {
         }
     }
 
+    /// Catppuccin Latte, for light terminal backgrounds
+    pub fn light() -> Self {
+        Self {
+            name: "light".to_string(),
+
+            base: Color::Rgb(239, 241, 245),     // #eff1f5
+            mantle: Color::Rgb(230, 233, 239),   // #e6e9ef
+            crust: Color::Rgb(220, 224, 232),    // #dce0e8
+            surface0: Color::Rgb(204, 208, 218), // #ccd0da
+            surface1: Color::Rgb(188, 192, 204), // #bcc0cc
+            overlay0: Color::Rgb(156, 160, 176), // #9ca0b0
+
+            text: Color::Rgb(76, 79, 105),       // #4c4f69
+            subtext0: Color::Rgb(108, 111, 133), // #6c6f85
+            subtext1: Color::Rgb(92, 95, 119),   // #5c5f77
+
+            rosewater: Color::Rgb(220, 138, 120), // #dc8a78
+            flamingo: Color::Rgb(221, 120, 120),  // #dd7878
+            pink: Color::Rgb(234, 118, 203),      // #ea76cb
+            mauve: Color::Rgb(136, 57, 239),      // #8839ef
+            red: Color::Rgb(210, 15, 57),         // #d20f39
+            maroon: Color::Rgb(230, 69, 83),      // #e64553
+            peach: Color::Rgb(254, 100, 11),      // #fe640b
+            yellow: Color::Rgb(223, 142, 29),     // #df8e1d
+            green: Color::Rgb(64, 160, 43),       // #40a02b
+            teal: Color::Rgb(23, 146, 153),       // #179299
+            sky: Color::Rgb(4, 165, 229),         // #04a5e5
+            sapphire: Color::Rgb(32, 159, 181),   // #209fb5
+            blue: Color::Rgb(30, 102, 245),       // #1e66f5
+            lavender: Color::Rgb(114, 135, 253),  // #7287fd
+        }
+    }
+
+    /// Pure black background with saturated accents
+    pub fn high_contrast() -> Self {
+        Self {
+            name: "high-contrast".to_string(),
+
+            base: Color::Rgb(0, 0, 0),
+            mantle: Color::Rgb(0, 0, 0),
+            crust: Color::Rgb(0, 0, 0),
+            surface0: Color::Rgb(48, 48, 48),
+            surface1: Color::Rgb(72, 72, 72),
+            overlay0: Color::Rgb(176, 176, 176),
+
+            text: Color::Rgb(255, 255, 255),
+            subtext0: Color::Rgb(224, 224, 224),
+            subtext1: Color::Rgb(240, 240, 240),
+
+            rosewater: Color::Rgb(255, 200, 200),
+            flamingo: Color::Rgb(255, 170, 170),
+            pink: Color::Rgb(255, 120, 220),
+            mauve: Color::Rgb(210, 150, 255),
+            red: Color::Rgb(255, 80, 80),
+            maroon: Color::Rgb(255, 130, 130),
+            peach: Color::Rgb(255, 170, 0),
+            yellow: Color::Rgb(255, 255, 0),
+            green: Color::Rgb(0, 255, 0),
+            teal: Color::Rgb(0, 255, 200),
+            sky: Color::Rgb(0, 220, 255),
+            sapphire: Color::Rgb(0, 200, 255),
+            blue: Color::Rgb(110, 170, 255),
+            lavender: Color::Rgb(200, 210, 255),
+        }
+    }
+
+    /// Only the 16 ANSI colors, backgrounds left to the terminal
+    pub fn ansi16() -> Self {
+        Self {
+            name: "16color".to_string(),
+
+            base: Color::Reset,
+            mantle: Color::Reset,
+            crust: Color::Reset,
+            surface0: Color::DarkGray,
+            surface1: Color::DarkGray,
+            overlay0: Color::DarkGray,
+
+            text: Color::Reset,
+            subtext0: Color::Gray,
+            subtext1: Color::Gray,
+
+            rosewater: Color::LightRed,
+            flamingo: Color::LightRed,
+            pink: Color::LightMagenta,
+            mauve: Color::Magenta,
+            red: Color::Red,
+            maroon: Color::LightRed,
+            peach: Color::LightYellow,
+            yellow: Color::Yellow,
+            green: Color::Green,
+            teal: Color::Cyan,
+            sky: Color::LightCyan,
+            sapphire: Color::Cyan,
+            blue: Color::Blue,
+            lavender: Color::LightBlue,
+        }
+    }
+
+    pub fn builtin(name: &str) -> Option<Self> {
+        match name {
+            "catppuccin" => Some(Self::catppuccin()),
+            "light" => Some(Self::light()),
+            "high-contrast" => Some(Self::high_contrast()),
+            "16color" => Some(Self::ansi16()),
+            _ => None,
+        }
+    }
+
+    /// Load a built-in theme or `./themes/<name>.toml`
+    pub fn load(name: &str) -> Result<Self> {
+        if let Some(theme) = Self::builtin(name) {
+            return Ok(theme);
+        }
+        let path = format!("{}/{}.toml", THEME_FOLDER, name);
+        let content = fs::read_to_string(&path).map_err(|e| anyhow!("无法读取 {}: {}", path, e))?;
+        Self::from_toml(name, &content)
+    }
+
+    /// Parse a theme file, slots it leaves out come from the theme named by
+    /// `extends` or the default theme
+    pub fn from_toml(name: &str, content: &str) -> Result<Self> {
+        let mut table = toml::from_str::<toml::Table>(content)?;
+        let mut theme = match table.remove("extends") {
+            Some(toml::Value::String(base)) => {
+                Self::builtin(&base).ok_or_else(|| anyhow!("未知的基础主题: {}", base))?
+            }
+            Some(_) => return Err(anyhow!("extends 必须是主题名")),
+            None => Self::catppuccin(),
+        };
+        theme.name = name.to_string();
+
+        for (slot, value) in table {
+            let value = value
+                .as_str()
+                .ok_or_else(|| anyhow!("{} 必须是颜色字符串", slot))?;
+            let color =
+                Color::from_str(value).map_err(|_| anyhow!("{}: 无效的颜色 {}", slot, value))?;
+            *theme
+                .slot_mut(&slot)
+                .ok_or_else(|| anyhow!("未知的颜色槽: {}", slot))? = color;
+        }
+        Ok(theme)
+    }
+
+    fn slot_mut(&mut self, slot: &str) -> Option<&mut Color> {
+        Some(match slot {
+            "base" => &mut self.base,
+            "mantle" => &mut self.mantle,
+            "crust" => &mut self.crust,
+            "surface0" => &mut self.surface0,
+            "surface1" => &mut self.surface1,
+            "overlay0" => &mut self.overlay0,
+            "text" => &mut self.text,
+            "subtext0" => &mut self.subtext0,
+            "subtext1" => &mut self.subtext1,
+            "rosewater" => &mut self.rosewater,
+            "flamingo" => &mut self.flamingo,
+            "pink" => &mut self.pink,
+            "mauve" => &mut self.mauve,
+            "red" => &mut self.red,
+            "maroon" => &mut self.maroon,
+            "peach" => &mut self.peach,
+            "yellow" => &mut self.yellow,
+            "green" => &mut self.green,
+            "teal" => &mut self.teal,
+            "sky" => &mut self.sky,
+            "sapphire" => &mut self.sapphire,
+            "blue" => &mut self.blue,
+            "lavender" => &mut self.lavender,
+            _ => return None,
+        })
+    }
+
     // Title style
     pub fn title_style(&self) -> Style {
         Style::default().fg(self.mauve).bg(self.crust)
@@ -106,7 +300,91 @@ impl Theme {
 
     // Timestamp style
     pub fn timestamp_style(&self) -> Style {
-        Style::default().fg(Color::Rgb(100, 100, 100)) // Dark gray color
+        Style::default().fg(self.overlay0)
+    }
+
+    // Disappearing message countdown style
+    pub fn countdown_style(&self) -> Style {
+        Style::default().fg(self.yellow).bg(self.mantle)
+    }
+
+    // "(edited)" marker style
+    pub fn edited_style(&self) -> Style {
+        Style::default()
+            .fg(self.subtext0)
+            .bg(self.mantle)
+            .add_modifier(Modifier::ITALIC)
+    }
+
+    // Quoted message above a reply
+    pub fn quote_style(&self) -> Style {
+        Style::default().fg(self.subtext1).bg(self.mantle)
+    }
+
+    // Reaction summary under a message
+    pub fn reaction_style(&self) -> Style {
+        Style::default().fg(self.peach).bg(self.surface0)
+    }
+
+    // Delivery marker on our own messages
+    pub fn receipt_style(&self) -> Style {
+        Style::default().fg(self.subtext0).bg(self.mantle)
+    }
+
+    // Marker once everyone has read our message
+    pub fn read_receipt_style(&self) -> Style {
+        Style::default().fg(self.green).bg(self.mantle)
+    }
+
+    // Divider above messages missed while offline
+    pub fn divider_style(&self) -> Style {
+        Style::default()
+            .fg(self.mauve)
+            .bg(self.mantle)
+            .add_modifier(Modifier::BOLD)
+    }
+
+    // Inline code and code blocks
+    pub fn code_style(&self) -> Style {
+        Style::default().fg(self.teal).bg(self.surface0)
+    }
+
+    // Box drawn around code blocks
+    pub fn code_border_style(&self) -> Style {
+        Style::default().fg(self.subtext0)
+    }
+
+    // Links in messages
+    pub fn link_style(&self) -> Style {
+        Style::default()
+            .fg(self.sky)
+            .add_modifier(Modifier::UNDERLINED)
+    }
+
+    // @mention of the local user
+    pub fn mention_style(&self) -> Style {
+        Style::default()
+            .fg(self.crust)
+            .bg(self.yellow)
+            .add_modifier(Modifier::BOLD)
+    }
+
+    // Search matches in the chat
+    pub fn search_style(&self) -> Style {
+        Style::default().fg(self.crust).bg(self.yellow)
+    }
+
+    // Matches in the selected search hit
+    pub fn search_current_style(&self) -> Style {
+        Style::default()
+            .fg(self.crust)
+            .bg(self.peach)
+            .add_modifier(Modifier::BOLD)
+    }
+
+    // Divider above the first unread message, and the unread count in the title
+    pub fn unread_style(&self) -> Style {
+        Style::default().fg(self.red).add_modifier(Modifier::BOLD)
     }
 
     // Error style
@@ -121,5 +399,113 @@ impl Theme {
 }
 
 lazy_static! {
-    pub static ref THEME: Theme = Theme::catppuccin();
+    pub static ref THEME: RwLock<Theme> = RwLock::new(Theme::catppuccin());
+    static ref TRUECOLOR: bool = get_truecolor().unwrap_or_else(|| {
+        std::env::var("COLORTERM").is_ok_and(|value| value == "truecolor" || value == "24bit")
+    });
+}
+
+pub fn set_theme(theme: Theme) {
+    *THEME.write().unwrap() = theme;
+}
+
+/// Built-in themes followed by the ones in the theme folder
+pub fn list_themes() -> Vec<String> {
+    let mut themes = BUILTIN_THEMES.map(String::from).to_vec();
+    if let Ok(entries) = fs::read_dir(THEME_FOLDER) {
+        let mut files = entries
+            .filter_map(|entry| {
+                let path = entry.ok()?.path();
+                if path.extension()? != "toml" {
+                    return None;
+                }
+                Some(path.file_stem()?.to_str()?.to_string())
+            })
+            .filter(|name| !themes.contains(name))
+            .collect::<Vec<_>>();
+        files.sort();
+        themes.extend(files);
+    }
+    themes
+}
+
+/// Swap every RGB color in the drawn frame for the closest of the 256
+/// indexed colors when the terminal lacks truecolor support
+pub fn degrade_buffer(buf: &mut Buffer) {
+    if *TRUECOLOR {
+        return;
+    }
+    for cell in buf.content.iter_mut() {
+        cell.fg = degrade_color(cell.fg);
+        cell.bg = degrade_color(cell.bg);
+    }
+}
+
+fn degrade_color(color: Color) -> Color {
+    match color {
+        Color::Rgb(r, g, b) => Color::Indexed(rgb_to_ansi256(r, g, b)),
+        color => color,
+    }
+}
+
+/// Nearest color in the xterm 6x6x6 cube or grayscale ramp
+fn rgb_to_ansi256(r: u8, g: u8, b: u8) -> u8 {
+    const LEVELS: [u8; 6] = [0, 95, 135, 175, 215, 255];
+    let cube_index = |v: u8| match v {
+        0..48 => 0,
+        48..115 => 1,
+        v => (v - 35) / 40,
+    };
+    let distance = |(r2, g2, b2): (u8, u8, u8)| {
+        let d = |a: u8, b: u8| (a as i32 - b as i32).pow(2);
+        d(r, r2) + d(g, g2) + d(b, b2)
+    };
+
+    let (ri, gi, bi) = (cube_index(r), cube_index(g), cube_index(b));
+    let cube = (
+        LEVELS[ri as usize],
+        LEVELS[gi as usize],
+        LEVELS[bi as usize],
+    );
+    let average = (r as i32 + g as i32 + b as i32) / 3;
+    let gray_index = ((average - 3) / 10).clamp(0, 23) as u8;
+    let gray = 8 + gray_index * 10;
+
+    if distance((gray, gray, gray)) < distance(cube) {
+        232 + gray_index
+    } else {
+        16 + 36 * ri + 6 * gi + bi
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn theme_files_override_slots() {
+        let theme = Theme::from_toml(
+            "mine",
+            "extends = \"light\"\ntext = \"#102030\"\nred = \"lightred\"\nblue = \"21\"",
+        )
+        .unwrap();
+        assert_eq!(theme.name, "mine");
+        assert_eq!(theme.text, Color::Rgb(16, 32, 48));
+        assert_eq!(theme.red, Color::LightRed);
+        assert_eq!(theme.blue, Color::Indexed(21));
+        assert_eq!(theme.green, Theme::light().green);
+
+        assert!(Theme::from_toml("bad", "txet = \"red\"").is_err());
+        assert!(Theme::from_toml("bad", "text = \"not a color\"").is_err());
+        assert!(Theme::from_toml("bad", "extends = \"nope\"").is_err());
+    }
+
+    #[test]
+    fn rgb_degrades_to_nearest_indexed() {
+        assert_eq!(rgb_to_ansi256(0, 0, 0), 16);
+        assert_eq!(rgb_to_ansi256(255, 255, 255), 231);
+        assert_eq!(rgb_to_ansi256(255, 0, 0), 196);
+        assert_eq!(rgb_to_ansi256(100, 100, 100), 241);
+        assert_eq!(rgb_to_ansi256(95, 135, 175), 67);
+    }
 }
@@ -11,10 +11,24 @@ use unicode_width::UnicodeWidthStr;
 
 use crate::theme::THEME;
 
+/// Sent messages remembered for recall with Up/Down
+const HISTORY_LIMIT: usize = 100;
+/// Killed text kept for Ctrl+Y and Alt+Y
+const KILL_RING_LIMIT: usize = 16;
+
 pub struct MultiInput {
     inputs: HashMap<String, String>,
     focused_id: Option<String>,
+    /// Cursor of the focused input, counted in graphemes
     cursor_position: usize,
+    /// Sent messages of this session, oldest first
+    history: Vec<String>,
+    /// Entry shown while browsing the history and the text typed before browsing
+    history_browse: Option<(usize, String)>,
+    /// Killed text, newest last
+    kill_ring: Vec<String>,
+    /// Start, length and ring index of the last yank, Alt+Y cycles it
+    last_yank: Option<(usize, usize, usize)>,
     cursor_visible: bool,
     style: Style,
     block: Option<Block<'static>>,
@@ -35,6 +49,10 @@ impl MultiInput {
             inputs: HashMap::new(),
             focused_id: None,
             cursor_position: 0,
+            history: Vec::new(),
+            history_browse: None,
+            kill_ring: Vec::new(),
+            last_yank: None,
             cursor_visible: true,
             style: Style::default(),
             block: None,
@@ -62,6 +80,8 @@ impl MultiInput {
     }
 
     pub fn handle_input(&mut self, input: &str) {
+        // pasted text may come with Windows line endings
+        let input = &input.replace("\r\n", "\n").replace('\r', "\n");
         if let Some(id) = &self.focused_id {
             if let Some(text) = self.inputs.get_mut(id) {
                 let graphemes: Vec<&str> = text.graphemes(true).collect();
@@ -146,6 +166,272 @@ impl MultiInput {
         }
     }
 
+    fn focused_graphemes(&self) -> Vec<String> {
+        self.focused_id
+            .as_ref()
+            .and_then(|id| self.inputs.get(id))
+            .map_or(vec![], |text| {
+                text.graphemes(true).map(str::to_string).collect()
+            })
+    }
+
+    fn set_focused_text(&mut self, text: String, cursor_position: usize) {
+        let Some(id) = &self.focused_id else {
+            return;
+        };
+        if let Some(current) = self.inputs.get_mut(id) {
+            self.cursor_position = cursor_position.min(text.graphemes(true).count());
+            *current = text;
+        }
+    }
+
+    /// Empty the focused input after a message went out
+    pub fn clear(&mut self) {
+        self.set_focused_text(String::new(), 0);
+        self.history_browse = None;
+        self.last_yank = None;
+    }
+
+    pub fn insert_newline(&mut self) {
+        self.handle_input("\n");
+    }
+
+    /// Start of the line the cursor is on, in graphemes
+    fn line_start(graphemes: &[String], position: usize) -> usize {
+        graphemes[..position.min(graphemes.len())]
+            .iter()
+            .rposition(|g| g == "\n")
+            .map_or(0, |pos| pos + 1)
+    }
+
+    /// End of the line the cursor is on, before its line break
+    fn line_end(graphemes: &[String], position: usize) -> usize {
+        let position = position.min(graphemes.len());
+        graphemes[position..]
+            .iter()
+            .position(|g| g == "\n")
+            .map_or(graphemes.len(), |pos| position + pos)
+    }
+
+    fn is_word(grapheme: &str) -> bool {
+        grapheme.chars().next().is_some_and(char::is_alphanumeric)
+    }
+
+    /// Start of the word before `position`, skipping separators first
+    fn word_start(graphemes: &[String], position: usize) -> usize {
+        let mut pos = position.min(graphemes.len());
+        while pos > 0 && !Self::is_word(&graphemes[pos - 1]) {
+            pos -= 1;
+        }
+        while pos > 0 && Self::is_word(&graphemes[pos - 1]) {
+            pos -= 1;
+        }
+        pos
+    }
+
+    /// End of the word after `position`, skipping separators first
+    fn word_end(graphemes: &[String], position: usize) -> usize {
+        let mut pos = position.min(graphemes.len());
+        while pos < graphemes.len() && !Self::is_word(&graphemes[pos]) {
+            pos += 1;
+        }
+        while pos < graphemes.len() && Self::is_word(&graphemes[pos]) {
+            pos += 1;
+        }
+        pos
+    }
+
+    pub fn move_cursor_home(&mut self) {
+        self.cursor_position = Self::line_start(&self.focused_graphemes(), self.cursor_position);
+    }
+
+    pub fn move_cursor_end(&mut self) {
+        self.cursor_position = Self::line_end(&self.focused_graphemes(), self.cursor_position);
+    }
+
+    pub fn move_word_left(&mut self) {
+        self.cursor_position = Self::word_start(&self.focused_graphemes(), self.cursor_position);
+    }
+
+    pub fn move_word_right(&mut self) {
+        self.cursor_position = Self::word_end(&self.focused_graphemes(), self.cursor_position);
+    }
+
+    /// Move to the line above, false when the cursor already is on the first line
+    pub fn move_cursor_up(&mut self) -> bool {
+        let graphemes = self.focused_graphemes();
+        let start = Self::line_start(&graphemes, self.cursor_position);
+        if start == 0 {
+            return false;
+        }
+        let column = self.cursor_position - start;
+        let above = Self::line_start(&graphemes, start - 1);
+        self.cursor_position = (above + column).min(start - 1);
+        true
+    }
+
+    /// Move to the line below, false when the cursor already is on the last line
+    pub fn move_cursor_down(&mut self) -> bool {
+        let graphemes = self.focused_graphemes();
+        let end = Self::line_end(&graphemes, self.cursor_position);
+        if end == graphemes.len() {
+            return false;
+        }
+        let column = self.cursor_position - Self::line_start(&graphemes, self.cursor_position);
+        self.cursor_position = (end + 1 + column).min(Self::line_end(&graphemes, end + 1));
+        true
+    }
+
+    /// Remember a sent message, repeats of the newest entry are skipped
+    pub fn push_history(&mut self, text: String) {
+        self.history_browse = None;
+        if text.trim().is_empty() || self.history.last() == Some(&text) {
+            return;
+        }
+        self.history.push(text);
+        if self.history.len() > HISTORY_LIMIT {
+            self.history.remove(0);
+        }
+    }
+
+    /// Show the previous history entry, the typed text comes back at the end
+    pub fn history_prev(&mut self) {
+        let index = match &self.history_browse {
+            Some((0, _)) => return,
+            Some((index, _)) => index - 1,
+            None if self.history.is_empty() => return,
+            None => self.history.len() - 1,
+        };
+        let draft = match self.history_browse.take() {
+            Some((_, draft)) => draft,
+            None => self.focused_graphemes().concat(),
+        };
+        let text = self.history[index].clone();
+        self.set_focused_text(text, usize::MAX);
+        self.history_browse = Some((index, draft));
+    }
+
+    pub fn history_next(&mut self) {
+        let Some((index, draft)) = self.history_browse.take() else {
+            return;
+        };
+        if index + 1 < self.history.len() {
+            let text = self.history[index + 1].clone();
+            self.set_focused_text(text, usize::MAX);
+            self.history_browse = Some((index + 1, draft));
+        } else {
+            self.set_focused_text(draft, usize::MAX);
+        }
+    }
+
+    /// Cut graphemes `start..end` into the kill ring
+    fn kill(&mut self, start: usize, end: usize) {
+        let mut graphemes = self.focused_graphemes();
+        let end = end.min(graphemes.len());
+        if start >= end {
+            return;
+        }
+        let killed = graphemes.drain(start..end).collect::<String>();
+        self.kill_ring.push(killed);
+        if self.kill_ring.len() > KILL_RING_LIMIT {
+            self.kill_ring.remove(0);
+        }
+        self.set_focused_text(graphemes.concat(), start);
+        self.last_yank = None;
+    }
+
+    /// Ctrl+W, kills back to the previous whitespace
+    pub fn kill_word_before_cursor(&mut self) {
+        let graphemes = self.focused_graphemes();
+        let mut start = self.cursor_position.min(graphemes.len());
+        while start > 0 && graphemes[start - 1].trim().is_empty() {
+            start -= 1;
+        }
+        while start > 0 && !graphemes[start - 1].trim().is_empty() {
+            start -= 1;
+        }
+        self.kill(start, self.cursor_position);
+    }
+
+    /// Alt+D, kills to the end of the next word
+    pub fn kill_word_after_cursor(&mut self) {
+        let end = Self::word_end(&self.focused_graphemes(), self.cursor_position);
+        self.kill(self.cursor_position, end);
+    }
+
+    /// Ctrl+U, kills to the start of the line
+    pub fn kill_to_line_start(&mut self) {
+        let start = Self::line_start(&self.focused_graphemes(), self.cursor_position);
+        self.kill(start, self.cursor_position);
+    }
+
+    /// Ctrl+K, kills to the end of the line
+    pub fn kill_to_line_end(&mut self) {
+        let end = Self::line_end(&self.focused_graphemes(), self.cursor_position);
+        self.kill(self.cursor_position, end);
+    }
+
+    /// Ctrl+Y, inserts the newest kill
+    pub fn yank(&mut self) {
+        let Some(text) = self.kill_ring.last().cloned() else {
+            return;
+        };
+        let start = self.cursor_position;
+        self.handle_input(&text);
+        self.last_yank = Some((
+            start,
+            text.graphemes(true).count(),
+            self.kill_ring.len() - 1,
+        ));
+    }
+
+    /// Alt+Y right after a yank, swaps the yanked text for the kill before it
+    pub fn yank_pop(&mut self) {
+        let Some((start, len, index)) = self.last_yank else {
+            return;
+        };
+        if self.cursor_position != start + len {
+            self.last_yank = None;
+            return;
+        }
+        let index = index.checked_sub(1).unwrap_or(self.kill_ring.len() - 1);
+        let text = self.kill_ring[index].clone();
+        let mut graphemes = self.focused_graphemes();
+        graphemes.splice(start..start + len, [text.clone()]);
+        let len = text.graphemes(true).count();
+        self.set_focused_text(graphemes.concat(), start + len);
+        self.last_yank = Some((start, len, index));
+    }
+
+    /// Text of the focused input up to the cursor
+    pub fn text_before_cursor(&self) -> Option<String> {
+        let text = self.inputs.get(self.focused_id.as_ref()?)?;
+        Some(text.graphemes(true).take(self.cursor_position).collect())
+    }
+
+    /// The whitespace separated word ending at the cursor of the focused input
+    pub fn word_before_cursor(&self) -> Option<String> {
+        let text = self.inputs.get(self.focused_id.as_ref()?)?;
+        let graphemes: Vec<&str> = text.graphemes(true).collect();
+        let end = self.cursor_position.min(graphemes.len());
+        let start = graphemes[..end]
+            .iter()
+            .rposition(|g| g.trim().is_empty())
+            .map_or(0, |pos| pos + 1);
+        Some(graphemes[start..end].concat())
+    }
+
+    /// Replace the word ending at the cursor, used by tab completion
+    pub fn replace_word_before_cursor(&mut self, replacement: &str) {
+        let Some(word) = self.word_before_cursor() else {
+            return;
+        };
+        for _ in 0..word.graphemes(true).count() {
+            self.handle_backspace();
+        }
+        self.handle_input(replacement);
+    }
+
     pub fn get_text(&self, id: &str) -> Option<String> {
         self.inputs.get(id).map(|text| {
             // Remove any padding spaces and cursor character
@@ -208,6 +494,12 @@ impl MultiInput {
                 break;
             }
 
+            if *grapheme == "\n" {
+                y += 1;
+                x = 0;
+                current_width = 0;
+                continue;
+            }
             let width = UnicodeWidthStr::width(*grapheme);
             if current_width + width > self.width as usize {
                 y += 1;
@@ -227,7 +519,15 @@ impl MultiInput {
         let mut current_line = String::new();
         let mut current_width = 0;
 
+        let mut after_break = false;
+
         for grapheme in text.graphemes(true) {
+            after_break = grapheme == "\n";
+            if after_break {
+                lines.push(std::mem::take(&mut current_line));
+                current_width = 0;
+                continue;
+            }
             let width = UnicodeWidthStr::width(grapheme);
             if current_width + width > self.width as usize {
                 lines.push(current_line);
@@ -239,7 +539,8 @@ impl MultiInput {
             }
         }
 
-        if !current_line.is_empty() {
+        // a trailing line break leaves the cursor on a new empty line
+        if !current_line.is_empty() || after_break {
             lines.push(current_line);
         }
 
@@ -264,7 +565,7 @@ impl Widget for &mut MultiInput {
         let block = self.block.clone().unwrap_or_else(|| {
             Block::default()
                 .borders(Borders::ALL)
-                .border_style(THEME.border_style())
+                .border_style(THEME.read().unwrap().border_style())
         });
         let inner_area = block.inner(area);
         block.render(area, buf);
@@ -277,9 +578,9 @@ impl Widget for &mut MultiInput {
         for (id, text) in &self.inputs {
             let is_focused = self.focused_id.as_ref() == Some(id);
             let style = if is_focused {
-                THEME.input_focused_style()
+                THEME.read().unwrap().input_focused_style()
             } else {
-                THEME.input_style()
+                THEME.read().unwrap().input_style()
             };
 
             // Wrap text into lines
@@ -349,7 +650,7 @@ impl Widget for &mut MultiInput {
                             let cursor_pos = prefix_width;
                             if cursor_pos < inner_area.width as usize {
                                 buf.get_mut(inner_area.x + cursor_pos as u16, current_y)
-                                    .set_style(THEME.cursor_style());
+                                    .set_style(THEME.read().unwrap().cursor_style());
                             }
                         }
                     }
@@ -370,7 +671,7 @@ impl Widget for &mut MultiInput {
                         let cursor_pos = prefix_width + cursor_x as usize;
                         if cursor_pos < inner_area.width as usize {
                             buf.get_mut(inner_area.x + cursor_pos as u16, current_y)
-                                .set_style(THEME.cursor_style());
+                                .set_style(THEME.read().unwrap().cursor_style());
                         }
                     }
                 }
@@ -393,7 +694,10 @@ impl Widget for &mut MultiInput {
             // Render character count in the border
             let count_y = area.y + area.height - 1;
             let count_x = area.x + area.width - count_width as u16 - 1; // -1 to leave space for border
-            let count_line = Line::from(vec![Span::styled(count_text, THEME.counter_style())]);
+            let count_line = Line::from(vec![Span::styled(
+                count_text,
+                THEME.read().unwrap().counter_style(),
+            )]);
             buf.set_line(count_x, count_y, &count_line, count_width as u16);
 
             self.current_height = total_lines;
@@ -401,3 +705,138 @@ impl Widget for &mut MultiInput {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn input(text: &str) -> MultiInput {
+        let mut input = MultiInput::new();
+        input.add_input("chat".to_string(), String::new());
+        input.handle_input(text);
+        input
+    }
+
+    fn text(input: &MultiInput) -> &str {
+        input.get_text_ref("chat").unwrap()
+    }
+
+    #[test]
+    fn cursor_moves_over_whole_graphemes() {
+        // family emoji, e + combining accent and a CJK character are one step each
+        let mut input = input("a👨‍👩‍👧e\u{301}中");
+        assert_eq!(input.cursor_position, 4);
+        input.move_cursor_left();
+        input.move_cursor_left();
+        assert_eq!(input.cursor_position, 2);
+        input.handle_backspace();
+        assert_eq!(text(&input), "ae\u{301}中");
+        assert_eq!(input.cursor_position, 1);
+        input.move_cursor_left();
+        input.move_cursor_left();
+        assert_eq!(input.cursor_position, 0);
+        input.move_cursor_right();
+        input.handle_delete();
+        assert_eq!(text(&input), "a中");
+        input.move_cursor_right();
+        input.move_cursor_right();
+        assert_eq!(input.cursor_position, 2);
+    }
+
+    #[test]
+    fn word_movement_skips_separators() {
+        let mut input = input("hello,  wörld 你好");
+        input.move_word_left();
+        assert_eq!(input.cursor_position, 14);
+        input.move_word_left();
+        assert_eq!(input.cursor_position, 8);
+        input.move_word_left();
+        assert_eq!(input.cursor_position, 0);
+        input.move_word_left();
+        assert_eq!(input.cursor_position, 0);
+        input.move_word_right();
+        assert_eq!(input.cursor_position, 5);
+        input.move_word_right();
+        assert_eq!(input.cursor_position, 13);
+        input.move_word_right();
+        input.move_word_right();
+        assert_eq!(input.cursor_position, 16);
+    }
+
+    #[test]
+    fn home_end_and_vertical_movement_follow_lines() {
+        let mut input = input("first");
+        input.insert_newline();
+        input.handle_input("second line");
+        input.insert_newline();
+        input.handle_input("ab");
+        assert_eq!(input.cursor_position, 20);
+
+        input.move_cursor_home();
+        assert_eq!(input.cursor_position, 18);
+        assert!(input.move_cursor_up());
+        assert_eq!(input.cursor_position, 6);
+        input.move_cursor_end();
+        assert_eq!(input.cursor_position, 17);
+        // the first line is shorter, the column is clamped to its end
+        assert!(input.move_cursor_up());
+        assert_eq!(input.cursor_position, 5);
+        assert!(!input.move_cursor_up());
+        assert!(input.move_cursor_down());
+        assert!(input.move_cursor_down());
+        assert_eq!(input.cursor_position, 20);
+        assert!(!input.move_cursor_down());
+    }
+
+    #[test]
+    fn kills_go_to_the_ring_and_yank_back() {
+        let mut input = input("one two three");
+        input.kill_word_before_cursor();
+        assert_eq!(text(&input), "one two ");
+        input.kill_word_before_cursor();
+        assert_eq!(text(&input), "one ");
+        input.move_cursor_home();
+        input.kill_to_line_end();
+        assert_eq!(text(&input), "");
+
+        input.yank();
+        assert_eq!(text(&input), "one ");
+        input.yank_pop();
+        assert_eq!(text(&input), "two ");
+        input.yank_pop();
+        assert_eq!(text(&input), "three");
+        assert_eq!(input.cursor_position, 5);
+
+        input.move_cursor_left();
+        input.kill_to_line_start();
+        assert_eq!(text(&input), "e");
+        input.kill_word_after_cursor();
+        assert_eq!(text(&input), "");
+    }
+
+    #[test]
+    fn history_recalls_and_restores_the_draft() {
+        let mut input = input("");
+        for message in ["first", "second", "second"] {
+            input.push_history(message.to_string());
+        }
+        input.handle_input("draft");
+
+        input.history_prev();
+        assert_eq!(text(&input), "second");
+        input.history_prev();
+        assert_eq!(text(&input), "first");
+        input.history_prev();
+        assert_eq!(text(&input), "first");
+        assert_eq!(input.cursor_position, 5);
+        input.history_next();
+        input.history_next();
+        assert_eq!(text(&input), "draft");
+        input.history_next();
+        assert_eq!(text(&input), "draft");
+
+        input.clear();
+        assert_eq!(text(&input), "");
+        assert_eq!(input.cursor_position, 0);
+    }
+}
@@ -11,6 +11,69 @@ pub struct ClientInfo {
     pub kyber_pk: ::prost::alloc::vec::Vec<u8>,
     #[prost(enumeration = "ClientStatus", tag = "5")]
     pub status: i32,
+    #[prost(bytes = "vec", tag = "6")]
+    pub dilithium_pk: ::prost::alloc::vec::Vec<u8>,
+}
+/// Payload of MessageType::Expiring, wraps another payload (type byte included)
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct ExpiringMessage {
+    /// seconds after the message timestamp until clients drop it
+    #[prost(uint64, tag = "1")]
+    pub timer: u64,
+    #[prost(bytes = "vec", tag = "2")]
+    pub data: ::prost::alloc::vec::Vec<u8>,
+}
+/// Payload of MessageType::DisappearingTimer, 0 turns the timer off
+#[derive(Clone, Copy, PartialEq, ::prost::Message)]
+pub struct DisappearingTimerSetting {
+    #[prost(uint64, tag = "1")]
+    pub seconds: u64,
+}
+/// Payload of MessageType::Edit, sign covers the message encoded with an empty sign
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct MessageEdit {
+    #[prost(string, tag = "1")]
+    pub target_id: ::prost::alloc::string::String,
+    #[prost(string, tag = "2")]
+    pub text: ::prost::alloc::string::String,
+    #[prost(bytes = "vec", tag = "3")]
+    pub sign: ::prost::alloc::vec::Vec<u8>,
+    /// text is written in the lightweight markup
+    #[prost(bool, tag = "4")]
+    pub markup: bool,
+}
+/// Payload of MessageType::Delete, sign covers the message encoded with an empty sign
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct MessageDelete {
+    #[prost(string, tag = "1")]
+    pub target_id: ::prost::alloc::string::String,
+    #[prost(bytes = "vec", tag = "2")]
+    pub sign: ::prost::alloc::vec::Vec<u8>,
+}
+/// Payload of MessageType::Reply, the excerpt is a short quote of the target
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct ReplyMessage {
+    #[prost(string, tag = "1")]
+    pub target_id: ::prost::alloc::string::String,
+    #[prost(string, tag = "2")]
+    pub quoted_sender: ::prost::alloc::string::String,
+    #[prost(string, tag = "3")]
+    pub excerpt: ::prost::alloc::string::String,
+    #[prost(string, tag = "4")]
+    pub text: ::prost::alloc::string::String,
+    /// text is written in the lightweight markup
+    #[prost(bool, tag = "5")]
+    pub markup: bool,
+}
+/// Payload of MessageType::Reaction, remove takes back an earlier reaction
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct ReactionMessage {
+    #[prost(string, tag = "1")]
+    pub target_id: ::prost::alloc::string::String,
+    #[prost(string, tag = "2")]
+    pub emoji: ::prost::alloc::string::String,
+    #[prost(bool, tag = "3")]
+    pub remove: bool,
 }
 #[derive(Clone, PartialEq, ::prost::Message)]
 pub struct Profile {
@@ -25,6 +88,15 @@ pub struct Profile {
     #[prost(bytes = "vec", tag = "5")]
     pub dilithium_sk: ::prost::alloc::vec::Vec<u8>,
 }
+/// Local message cache of one server, stored encrypted next to the profile.
+/// The messages keep their decrypted payload in data and have no key.
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct MessageCache {
+    #[prost(string, tag = "1")]
+    pub server_url: ::prost::alloc::string::String,
+    #[prost(message, repeated, tag = "2")]
+    pub messages: ::prost::alloc::vec::Vec<ServerBroadcastMessage>,
+}
 #[derive(Clone, PartialEq, ::prost::Message)]
 pub struct OrwellRatchetPacket {
     #[prost(bytes = "vec", tag = "1")]
@@ -110,6 +182,15 @@ pub struct ClientMessage {
     pub keys: ::prost::alloc::vec::Vec<Key>,
     #[prost(bytes = "vec", tag = "2")]
     pub data: ::prost::alloc::vec::Vec<u8>,
+    /// seconds until the server drops the message from history, 0 for never
+    #[prost(uint64, tag = "4")]
+    pub ttl: u64,
+    #[prost(enumeration = "MessageAction", tag = "5")]
+    pub action: i32,
+    /// message edited or deleted by this one, must be sent by the same client,
+    /// or reacted to by anyone
+    #[prost(string, tag = "6")]
+    pub target_id: ::prost::alloc::string::String,
 }
 #[derive(Clone, Copy, PartialEq, ::prost::Message)]
 pub struct ClientChangeColor {
@@ -118,6 +199,20 @@ pub struct ClientChangeColor {
 }
 #[derive(Clone, Copy, PartialEq, ::prost::Message)]
 pub struct ClientAfk {}
+/// Relayed to the other online clients as ServerBroadcastTyping, never stored
+#[derive(Clone, Copy, PartialEq, ::prost::Message)]
+pub struct ClientTyping {
+    #[prost(bool, tag = "1")]
+    pub typing: bool,
+}
+/// Acknowledge messages as delivered, or as read when read is set
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct ClientReceipt {
+    #[prost(string, repeated, tag = "1")]
+    pub message_ids: ::prost::alloc::vec::Vec<::prost::alloc::string::String>,
+    #[prost(bool, tag = "2")]
+    pub read: bool,
+}
 #[derive(Clone, Copy, PartialEq, ::prost::Message)]
 pub struct ServerHeartbeat {}
 #[derive(Clone, PartialEq, ::prost::Message)]
@@ -182,6 +277,11 @@ pub struct ServerBroadcastMessage {
     pub data: ::prost::alloc::vec::Vec<u8>,
     #[prost(uint64, tag = "6")]
     pub timestamp: u64,
+    #[prost(string, tag = "7")]
+    pub id: ::prost::alloc::string::String,
+    /// Only set on the receiver's own messages in history
+    #[prost(message, optional, tag = "8")]
+    pub receipt: ::core::option::Option<ServerReceipt>,
 }
 #[derive(Clone, PartialEq, ::prost::Message)]
 pub struct ServerBroadcastClientLogin {
@@ -205,6 +305,12 @@ pub struct ServerBroadcastClientLogout {
 pub struct ServerHistoryMessage {
     #[prost(message, repeated, tag = "1")]
     pub data: ::prost::alloc::vec::Vec<ServerBroadcastMessage>,
+    /// Messages missed while offline, oldest first, sent after the history
+    #[prost(bool, tag = "2")]
+    pub offline: bool,
+    /// Size of the whole offline backlog, only set on its first batch
+    #[prost(uint32, tag = "3")]
+    pub missed: u32,
 }
 #[derive(Clone, PartialEq, ::prost::Message)]
 pub struct ServerChangeColorResponse {
@@ -215,6 +321,29 @@ pub struct ServerChangeColorResponse {
     #[prost(string, tag = "3")]
     pub message: ::prost::alloc::string::String,
 }
+/// Delivery state of one of the receiver's own messages
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct ServerReceipt {
+    #[prost(string, tag = "1")]
+    pub message_id: ::prost::alloc::string::String,
+    #[prost(uint32, tag = "2")]
+    pub recipients: u32,
+    #[prost(uint32, tag = "3")]
+    pub delivered: u32,
+    #[prost(uint32, tag = "4")]
+    pub read: u32,
+}
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct ServerBroadcastTyping {
+    #[prost(string, tag = "1")]
+    pub sender_id: ::prost::alloc::string::String,
+    #[prost(string, tag = "2")]
+    pub sender_name: ::prost::alloc::string::String,
+    #[prost(int32, tag = "3")]
+    pub color: i32,
+    #[prost(bool, tag = "4")]
+    pub typing: bool,
+}
 #[derive(Clone, PartialEq, ::prost::Message)]
 pub struct ServerBroadcastChangeColor {
     #[prost(string, tag = "1")]
@@ -244,6 +373,8 @@ pub enum PacketType {
     ClientMessage = 7,
     ClientChangeColor = 8,
     ClientAfk = 9,
+    ClientTyping = 10,
+    ClientReceipt = 11,
     ServerHeartbeat = 10000,
     ServerError = 10001,
     ServerInformation = 10002,
@@ -256,6 +387,8 @@ pub enum PacketType {
     ServerHistoryMessage = 10009,
     ServerChangeColorResponse = 10010,
     ServerOrwellRatchetStep = 10011,
+    ServerBroadcastTyping = 10012,
+    ServerReceipt = 10013,
 }
 impl PacketType {
     /// String value of the enum field names used in the ProtoBuf definition.
@@ -274,6 +407,8 @@ impl PacketType {
             Self::ClientMessage => "Client_Message",
             Self::ClientChangeColor => "Client_ChangeColor",
             Self::ClientAfk => "Client_Afk",
+            Self::ClientTyping => "Client_Typing",
+            Self::ClientReceipt => "Client_Receipt",
             Self::ServerHeartbeat => "Server_Heartbeat",
             Self::ServerError => "Server_Error",
             Self::ServerInformation => "Server_Information",
@@ -286,6 +421,8 @@ impl PacketType {
             Self::ServerHistoryMessage => "Server_HistoryMessage",
             Self::ServerChangeColorResponse => "Server_ChangeColorResponse",
             Self::ServerOrwellRatchetStep => "Server_OrwellRatchetStep",
+            Self::ServerBroadcastTyping => "Server_BroadcastTyping",
+            Self::ServerReceipt => "Server_Receipt",
         }
     }
     /// Creates an enum from field names used in the ProtoBuf definition.
@@ -301,6 +438,8 @@ impl PacketType {
             "Client_Message" => Some(Self::ClientMessage),
             "Client_ChangeColor" => Some(Self::ClientChangeColor),
             "Client_Afk" => Some(Self::ClientAfk),
+            "Client_Typing" => Some(Self::ClientTyping),
+            "Client_Receipt" => Some(Self::ClientReceipt),
             "Server_Heartbeat" => Some(Self::ServerHeartbeat),
             "Server_Error" => Some(Self::ServerError),
             "Server_Information" => Some(Self::ServerInformation),
@@ -313,6 +452,8 @@ impl PacketType {
             "Server_HistoryMessage" => Some(Self::ServerHistoryMessage),
             "Server_ChangeColorResponse" => Some(Self::ServerChangeColorResponse),
             "Server_OrwellRatchetStep" => Some(Self::ServerOrwellRatchetStep),
+            "Server_BroadcastTyping" => Some(Self::ServerBroadcastTyping),
+            "Server_Receipt" => Some(Self::ServerReceipt),
             _ => None,
         }
     }
@@ -349,6 +490,7 @@ impl ClientStatus {
 #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, ::prost::Enumeration)]
 #[repr(i32)]
 pub enum MessageType {
+    /// UTF-8 text, a leading 0xFF byte marks text written in the lightweight markup
     Text = 0,
     Login = 1,
     Logout = 2,
@@ -357,6 +499,12 @@ pub enum MessageType {
     EnterAfk = 5,
     LeftAfk = 6,
     Image = 7,
+    Expiring = 8,
+    DisappearingTimer = 9,
+    Edit = 10,
+    Delete = 11,
+    Reply = 12,
+    Reaction = 13,
 }
 impl MessageType {
     /// String value of the enum field names used in the ProtoBuf definition.
@@ -373,6 +521,12 @@ impl MessageType {
             Self::EnterAfk => "EnterAfk",
             Self::LeftAfk => "LeftAfk",
             Self::Image => "Image",
+            Self::Expiring => "Expiring",
+            Self::DisappearingTimer => "DisappearingTimer",
+            Self::Edit => "Edit",
+            Self::Delete => "Delete",
+            Self::Reply => "Reply",
+            Self::Reaction => "Reaction",
         }
     }
     /// Creates an enum from field names used in the ProtoBuf definition.
@@ -386,6 +540,44 @@ impl MessageType {
             "EnterAfk" => Some(Self::EnterAfk),
             "LeftAfk" => Some(Self::LeftAfk),
             "Image" => Some(Self::Image),
+            "Expiring" => Some(Self::Expiring),
+            "DisappearingTimer" => Some(Self::DisappearingTimer),
+            "Edit" => Some(Self::Edit),
+            "Delete" => Some(Self::Delete),
+            "Reply" => Some(Self::Reply),
+            "Reaction" => Some(Self::Reaction),
+            _ => None,
+        }
+    }
+}
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, ::prost::Enumeration)]
+#[repr(i32)]
+pub enum MessageAction {
+    Post = 0,
+    Edit = 1,
+    Delete = 2,
+    React = 3,
+}
+impl MessageAction {
+    /// String value of the enum field names used in the ProtoBuf definition.
+    ///
+    /// The values are not transformed in any way and thus are considered stable
+    /// (if the ProtoBuf definition does not change) and safe for programmatic use.
+    pub fn as_str_name(&self) -> &'static str {
+        match self {
+            Self::Post => "MessageAction_Post",
+            Self::Edit => "MessageAction_Edit",
+            Self::Delete => "MessageAction_Delete",
+            Self::React => "MessageAction_React",
+        }
+    }
+    /// Creates an enum from field names used in the ProtoBuf definition.
+    pub fn from_str_name(value: &str) -> ::core::option::Option<Self> {
+        match value {
+            "MessageAction_Post" => Some(Self::Post),
+            "MessageAction_Edit" => Some(Self::Edit),
+            "MessageAction_Delete" => Some(Self::Delete),
+            "MessageAction_React" => Some(Self::React),
             _ => None,
         }
     }
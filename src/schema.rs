@@ -17,6 +17,16 @@ diesel::table! {
         msg_id_ -> Text,
         receiver_id_ -> Text,
         data_ -> Binary,
+        pending_ -> Bool,
+    }
+}
+
+diesel::table! {
+    message_receipts_ (msg_id_, receiver_id_) {
+        msg_id_ -> Text,
+        receiver_id_ -> Text,
+        delivered_at_ -> BigInt,
+        read_at_ -> Nullable<BigInt>,
     }
 }
 
@@ -26,7 +36,15 @@ diesel::table! {
         sender_id_ -> Text,
         data_ -> Binary,
         timestamp_ -> BigInt,
+        expires_at_ -> Nullable<BigInt>,
+        action_ -> Integer,
+        target_id_ -> Nullable<Text>,
     }
 }
 
-diesel::allow_tables_to_appear_in_same_query!(clients_, message_keys_, messages_,);
+diesel::allow_tables_to_appear_in_same_query!(
+    clients_,
+    message_keys_,
+    message_receipts_,
+    messages_,
+);
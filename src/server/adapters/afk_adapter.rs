@@ -27,11 +27,11 @@ impl PacketAdapter for AfkAdapter {
         context: PacketContext,
     ) -> Result<()> {
         let _packet = decode_packet!(packet, ClientAfk);
-        let client = context.client_info.as_ref().unwrap().client.clone();
-        let status = ClientManager::get_status(context.conn_id).await;
+        let client = context.client()?.clone();
+        let status = ClientManager::get_status(context.conn_id).await?;
 
         if status == ClientStatus::Afk {
-            ClientManager::update_status(context.conn_id, ClientStatus::Online).await;
+            ClientManager::update_status(context.conn_id, ClientStatus::Online).await?;
             broadcast_message_from_server(
                 MessageType::LeftAfk,
                 &[],
@@ -42,7 +42,7 @@ impl PacketAdapter for AfkAdapter {
             )
             .await?;
         } else {
-            ClientManager::update_status(context.conn_id, ClientStatus::Afk).await;
+            ClientManager::update_status(context.conn_id, ClientStatus::Afk).await?;
             broadcast_message_from_server(
                 MessageType::EnterAfk,
                 &[],
@@ -29,8 +29,8 @@ impl PacketAdapter for ColorAdapter {
         context: PacketContext,
     ) -> Result<()> {
         let packet = decode_packet!(packet, ClientChangeColor);
-        let client = context.client_info.as_ref().unwrap().client.clone();
-        let clients = ClientManager::get_all_clients().await;
+        let client = context.client()?.clone();
+        let clients = ClientManager::get_all_clients().await?;
 
         if clients
             .iter()
@@ -63,7 +63,7 @@ impl PacketAdapter for ColorAdapter {
             )
             .await?;
 
-            ClientManager::update_color(&client.id_, packet.color).await;
+            ClientManager::update_color(&client.id_, packet.color).await?;
 
             send_packet(
                 context.conn_id,
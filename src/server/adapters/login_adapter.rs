@@ -27,17 +27,17 @@ impl PacketAdapter for LoginAdapter {
     ) -> Result<()> {
         let packet = decode_packet!(packet, ClientLogin);
         let token = TokenManager::validate_token(context.conn_id, &packet.token_sign).await;
-        let mut login_client = None;
+        let login_client = match token {
+            Some(token) => crate::client::ClientManager::find_client(&token.1).await?,
+            None => None,
+        };
 
-        let response = if token.is_none() {
+        let response = if login_client.is_none() {
             ServerLoginResponse {
                 success: false,
                 message: "身份校验失败".to_string(),
             }
         } else {
-            let pk = token.clone().unwrap().1;
-            let client = crate::client::ClientManager::find_client(&pk).unwrap();
-            login_client.replace(client);
             ServerLoginResponse {
                 success: true,
                 message: "登录成功".to_string(),
@@ -1,17 +1,19 @@
 use crate::{
     client::ClientManager,
+    error::ServerError,
     message::MessageManager,
     packet_adapter::{PacketAdapter, PacketContext},
     send_packet,
 };
-use anyhow::Result;
+use anyhow::{anyhow, Result};
 use async_trait::async_trait;
 use orwell::{
     decode_packet,
-    pb::orwell::{ClientMessage, PacketType, ServerBroadcastMessage},
+    pb::orwell::{ClientMessage, MessageAction, PacketType, ServerBroadcastMessage},
     shared::helper::get_now_timestamp,
 };
 use prost::Message;
+use tracing::warn;
 
 pub struct MessageAdapter;
 
@@ -27,11 +29,24 @@ impl PacketAdapter for MessageAdapter {
         context: PacketContext,
     ) -> Result<()> {
         let packet = decode_packet!(packet, ClientMessage);
-        let sender = context.client_info.as_ref().unwrap().client.clone();
-        let data = packet.data;
+        let sender = context.client()?.clone();
+        let action = packet.action();
+        // anyone may react, even to a message that already left the history
+        if matches!(action, MessageAction::Edit | MessageAction::Delete) {
+            // payloads are end-to-end encrypted, authorship is checked on the stored row
+            match MessageManager::get_message(&packet.target_id).await? {
+                Some(target) if target.sender_id_ == sender.id_ => {}
+                Some(_) => {
+                    return Err(ServerError::InvalidPacket(anyhow!("只能修改自己的消息")).into())
+                }
+                None => return Err(ServerError::InvalidPacket(anyhow!("消息不存在")).into()),
+            }
+        }
+        let msg_id = MessageManager::new_message_id();
+        let mut delivered = vec![];
 
         for key in &packet.keys {
-            let client = ClientManager::get_client_by_id(&key.receiver_id).await;
+            let client = ClientManager::get_client_by_id(&key.receiver_id).await?;
             if client.is_none() {
                 continue;
             }
@@ -39,23 +54,34 @@ impl PacketAdapter for MessageAdapter {
             if let Some(conn_id) =
                 ClientManager::get_client_connection_by_id(&client.id_.clone()).await
             {
-                send_packet(
+                if let Err(e) = send_packet(
                     conn_id,
                     PacketType::ServerBroadcastMessage,
                     ServerBroadcastMessage {
                         sender_id: sender.id_.clone(),
                         sender_name: sender.name_.clone(),
                         key: Some(key.clone()),
-                        data: data.clone(),
+                        data: packet.data.clone(),
                         color: sender.color_,
                         timestamp: get_now_timestamp(),
+                        id: msg_id.clone(),
+                        receipt: None,
                     },
                 )
-                .await?;
+                .await
+                {
+                    warn!("Failed to deliver message to {}: {:?}", conn_id, e);
+                } else {
+                    delivered.push(client.id_.clone());
+                }
             }
         }
 
-        MessageManager::add_message(sender.id_.clone(), data.clone(), packet.keys).await;
+        let target_id = packet.target_id.clone();
+        MessageManager::add_message(msg_id, sender.id_.clone(), packet, &delivered).await?;
+        if action == MessageAction::Delete {
+            MessageManager::delete_message(&target_id).await?;
+        }
         Ok(())
     }
 }
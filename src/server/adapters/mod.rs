@@ -4,13 +4,16 @@ pub mod heartbeat_adapter;
 pub mod login_adapter;
 pub mod message_adapter;
 pub mod pre_login_adapter;
+pub mod receipt_adapter;
 pub mod register_adapter;
+pub mod typing_adapter;
 
 use crate::adapters::heartbeat_adapter::HeartbeatAdapter;
 use crate::adapters::{
     afk_adapter::AfkAdapter, color_adapter::ColorAdapter, login_adapter::LoginAdapter,
     message_adapter::MessageAdapter, pre_login_adapter::PreLoginAdapter,
-    register_adapter::RegisterAdapter,
+    receipt_adapter::ReceiptAdapter, register_adapter::RegisterAdapter,
+    typing_adapter::TypingAdapter,
 };
 use crate::packet_adapter::PacketAdapterRegistry;
 
@@ -25,6 +28,8 @@ pub fn create_registry() -> PacketAdapterRegistry {
     registry.register(Box::new(ColorAdapter));
     registry.register(Box::new(AfkAdapter));
     registry.register(Box::new(HeartbeatAdapter));
+    registry.register(Box::new(TypingAdapter));
+    registry.register(Box::new(ReceiptAdapter));
 
     registry
 }
@@ -27,7 +27,7 @@ impl PacketAdapter for PreLoginAdapter {
         context: PacketContext,
     ) -> Result<()> {
         let packet = decode_packet!(packet, ClientPreLogin);
-        let client = ClientManager::find_client(&packet.dilithium_pk);
+        let client = ClientManager::find_client(&packet.dilithium_pk).await?;
 
         if orwell::shared::helper::get_version() != packet.version {
             let response = ServerPreLogin {
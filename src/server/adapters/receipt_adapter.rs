@@ -0,0 +1,58 @@
+use crate::{
+    client::ClientManager,
+    error::ServerError,
+    message::MessageManager,
+    packet_adapter::{PacketAdapter, PacketContext},
+    send_packet,
+};
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use orwell::{
+    decode_packet,
+    pb::orwell::{ClientReceipt, PacketType},
+};
+use prost::Message;
+use tracing::warn;
+
+/// Upper bound of message ids acknowledged by one packet.
+const MAX_RECEIPT_BATCH: usize = 200;
+
+/// Stores delivery and read acknowledgements and forwards them to the sender.
+pub struct ReceiptAdapter;
+
+#[async_trait]
+impl PacketAdapter for ReceiptAdapter {
+    fn packet_type(&self) -> PacketType {
+        PacketType::ClientReceipt
+    }
+
+    async fn process(
+        &self,
+        packet: orwell::pb::orwell::OrwellPacket,
+        context: PacketContext,
+    ) -> Result<()> {
+        let packet = decode_packet!(packet, ClientReceipt);
+        if packet.message_ids.len() > MAX_RECEIPT_BATCH {
+            return Err(ServerError::InvalidPacket(anyhow!("回执数量过多")).into());
+        }
+        let client = context.client()?.clone();
+
+        for msg_id in &packet.message_ids {
+            let Some((sender_id, counts)) =
+                MessageManager::record_receipt(msg_id, &client.id_, packet.read).await?
+            else {
+                continue;
+            };
+            let Some(conn_id) = ClientManager::get_client_connection_by_id(&sender_id).await else {
+                continue;
+            };
+            if let Err(e) =
+                send_packet(conn_id, PacketType::ServerReceipt, counts.to_pb_receipt()).await
+            {
+                warn!("Failed to forward receipt to {}: {:?}", conn_id, e);
+            }
+        }
+
+        Ok(())
+    }
+}
@@ -6,10 +6,12 @@ use crate::{
 };
 use anyhow::Result;
 use async_trait::async_trait;
+use crystals_dilithium::dilithium5;
 use orwell::{
     decode_packet,
     pb::orwell::{ClientRegister, PacketType, ServerRegisterResponse},
 };
+use pqcrypto_kyber::kyber1024;
 use prost::Message;
 use rand::Rng;
 
@@ -27,16 +29,25 @@ impl PacketAdapter for RegisterAdapter {
         context: PacketContext,
     ) -> Result<()> {
         let packet = decode_packet!(packet, ClientRegister);
-        let client = ClientManager::find_client(&packet.dilithium_pk);
+        let client = ClientManager::find_client(&packet.dilithium_pk).await?;
         let mut registered_client = None;
 
-        let response = if client.is_some() {
+        // a malformed key would break every broadcast that has to encrypt for it
+        let response = if packet.kyber_pk.len() != kyber1024::public_key_bytes()
+            || packet.dilithium_pk.len() != dilithium5::PUBLICKEYBYTES
+        {
+            ServerRegisterResponse {
+                success: false,
+                color: 0,
+                message: "非法密钥".to_string(),
+            }
+        } else if client.is_some() {
             ServerRegisterResponse {
                 success: false,
                 color: 0,
                 message: "您已经注册过了".to_string(),
             }
-        } else if ClientManager::is_name_taken(&packet.name) {
+        } else if ClientManager::is_name_taken(&packet.name).await? {
             ServerRegisterResponse {
                 success: false,
                 color: 0,
@@ -49,7 +60,8 @@ impl PacketAdapter for RegisterAdapter {
                 &packet.kyber_pk,
                 &packet.dilithium_pk,
                 color,
-            );
+            )
+            .await?;
             registered_client.replace(client);
             ServerRegisterResponse {
                 success: true,
@@ -0,0 +1,60 @@
+use crate::{
+    client::ClientManager,
+    packet_adapter::{PacketAdapter, PacketContext},
+    send_packet,
+};
+use anyhow::Result;
+use async_trait::async_trait;
+use orwell::{
+    decode_packet,
+    pb::orwell::{ClientTyping, PacketType, ServerBroadcastTyping},
+};
+use prost::Message;
+use tracing::warn;
+
+/// Relays typing signals to the other online clients, nothing is stored.
+pub struct TypingAdapter;
+
+#[async_trait]
+impl PacketAdapter for TypingAdapter {
+    fn packet_type(&self) -> PacketType {
+        PacketType::ClientTyping
+    }
+
+    async fn process(
+        &self,
+        packet: orwell::pb::orwell::OrwellPacket,
+        context: PacketContext,
+    ) -> Result<()> {
+        let packet = decode_packet!(packet, ClientTyping);
+        let client = context.client()?.clone();
+        let broadcast = ServerBroadcastTyping {
+            sender_id: client.id_.clone(),
+            sender_name: client.name_.clone(),
+            color: client.color_,
+            typing: packet.typing,
+        };
+
+        for online in ClientManager::get_all_online_clients().await {
+            if online.client.id_ == client.id_ {
+                continue;
+            }
+            let Some(conn_id) =
+                ClientManager::get_client_connection_by_id(&online.client.id_).await
+            else {
+                continue;
+            };
+            if let Err(e) = send_packet(
+                conn_id,
+                PacketType::ServerBroadcastTyping,
+                broadcast.clone(),
+            )
+            .await
+            {
+                warn!("Failed to relay typing to {}: {:?}", conn_id, e);
+            }
+        }
+
+        Ok(())
+    }
+}
@@ -4,17 +4,17 @@ use diesel::prelude::*;
 use lazy_static::lazy_static;
 use orwell::{
     pb::orwell::{ClientInfo as PbClientInfo, ClientStatus},
-    schema::clients_::{self, dsl::*},
+    schema::clients_,
 };
 use tokio::sync::RwLock;
 use tracing::info;
 use uuid::Uuid;
 
-use crate::get_db_connection;
+use crate::{error::ServerError, storage::storage};
 
 #[derive(Queryable, Selectable, Insertable, Clone, Debug)]
 #[diesel(table_name = clients_)]
-#[diesel(check_for_backend(diesel::sqlite::Sqlite))]
+#[diesel(check_for_backend(diesel::sqlite::Sqlite, diesel::pg::Pg))]
 #[diesel(primary_key(id_))]
 pub struct Client {
     pub id_: String,
@@ -58,6 +58,7 @@ impl ClientInfo {
             color: self.client.color_ as u32,
             kyber_pk: self.client.kyber_pk_.clone(),
             status: self.status as i32,
+            dilithium_pk: self.client.dilithium_pk_.clone(),
         }
     }
 }
@@ -86,17 +87,16 @@ impl ClientManager {
         }
     }
 
-    pub fn is_name_taken(name: &str) -> bool {
-        let mut conn: SqliteConnection = get_db_connection();
-        clients_
-            .filter(name_.eq(name))
-            .first::<Client>(&mut conn)
-            .optional()
-            .unwrap()
-            .is_some()
+    pub async fn is_name_taken(name: &str) -> Result<bool, ServerError> {
+        storage()?.is_name_taken(name.to_string()).await
     }
 
-    pub fn register_client(name: &str, kyber_pk: &[u8], dilithium_pk: &[u8], color: i32) -> Client {
+    pub async fn register_client(
+        name: &str,
+        kyber_pk: &[u8],
+        dilithium_pk: &[u8],
+        color: i32,
+    ) -> Result<Client, ServerError> {
         let id = Uuid::now_v7().to_string();
         let client = Client {
             id_: id,
@@ -106,21 +106,14 @@ impl ClientManager {
             color_: color,
             online_time_: 0,
         };
-        let mut conn = get_db_connection();
-        diesel::insert_into(clients_)
-            .values(client.clone())
-            .execute(&mut conn)
-            .unwrap();
-        client
+        storage()?.insert_client(client.clone()).await?;
+        Ok(client)
     }
 
-    pub fn find_client(dilithium_pk: &[u8]) -> Option<Client> {
-        let mut conn: SqliteConnection = get_db_connection();
-        clients_
-            .filter(dilithium_pk_.eq(dilithium_pk))
-            .first::<Client>(&mut conn)
-            .optional()
-            .unwrap()
+    pub async fn find_client(dilithium_pk: &[u8]) -> Result<Option<Client>, ServerError> {
+        storage()?
+            .find_client_by_dilithium_pk(dilithium_pk.to_vec())
+            .await
     }
 
     pub async fn login_client(conn_id: u32, client: Client) -> ClientInfo {
@@ -133,13 +126,8 @@ impl ClientManager {
         info
     }
 
-    pub async fn get_client_by_id(id: &str) -> Option<Client> {
-        let mut conn: SqliteConnection = get_db_connection();
-        clients_
-            .filter(id_.eq(id))
-            .first::<Client>(&mut conn)
-            .optional()
-            .unwrap()
+    pub async fn get_client_by_id(id: &str) -> Result<Option<Client>, ServerError> {
+        storage()?.find_client_by_id(id.to_string()).await
     }
 
     pub async fn get_online_client_by_connection(conn_id: u32) -> Option<ClientInfo> {
@@ -177,13 +165,11 @@ impl ClientManager {
         client_manager.clients.values().cloned().collect()
     }
 
-    pub async fn get_all_clients() -> Vec<ClientInfo> {
-        let client_manager = CLIENT_MANAGER.read().await;
-        let online_clients = client_manager.clients.values().cloned().collect::<Vec<_>>();
+    pub async fn get_all_clients() -> Result<Vec<ClientInfo>, ServerError> {
+        let online_clients = Self::get_all_online_clients().await;
 
         // get offline clients
-        let mut conn: SqliteConnection = get_db_connection();
-        let all_clients = clients_.load::<Client>(&mut conn).unwrap();
+        let all_clients = storage()?.load_clients().await?;
         let offline_clients = all_clients
             .iter()
             .filter(|client| !online_clients.iter().any(|c| c.client.id_ == client.id_))
@@ -193,7 +179,7 @@ impl ClientManager {
             })
             .collect::<Vec<_>>();
         info!("online_clients: {:?}", offline_clients.len());
-        online_clients.into_iter().chain(offline_clients).collect()
+        Ok(online_clients.into_iter().chain(offline_clients).collect())
     }
 
     pub async fn get_all_connections() -> Vec<u32> {
@@ -201,32 +187,36 @@ impl ClientManager {
         client_manager.clients.keys().cloned().collect()
     }
 
-    pub async fn update_color(id: &str, color: i32) {
-        let mut conn: SqliteConnection = get_db_connection();
-        diesel::update(clients_)
-            .filter(id_.eq(id))
-            .set(color_.eq(color))
-            .execute(&mut conn)
-            .unwrap();
+    pub async fn update_color(id: &str, color: i32) -> Result<(), ServerError> {
+        storage()?
+            .update_client_color(id.to_string(), color)
+            .await?;
 
         if let Some(client) = Self::get_client_connection_by_id(id).await {
             let mut client_manager = CLIENT_MANAGER.write().await;
-            client_manager
-                .clients
-                .get_mut(&client)
-                .unwrap()
-                .client
-                .color_ = color;
+            if let Some(info) = client_manager.clients.get_mut(&client) {
+                info.client.color_ = color;
+            }
         }
+        Ok(())
     }
 
-    pub async fn get_status(conn_id: u32) -> ClientStatus {
+    pub async fn get_status(conn_id: u32) -> Result<ClientStatus, ServerError> {
         let client_manager = CLIENT_MANAGER.read().await;
-        client_manager.clients.get(&conn_id).unwrap().status
+        client_manager
+            .clients
+            .get(&conn_id)
+            .map(|info| info.status)
+            .ok_or(ServerError::NotLoggedIn)
     }
 
-    pub async fn update_status(conn_id: u32, status: ClientStatus) {
+    pub async fn update_status(conn_id: u32, status: ClientStatus) -> Result<(), ServerError> {
         let mut client_manager = CLIENT_MANAGER.write().await;
-        client_manager.clients.get_mut(&conn_id).unwrap().status = status;
+        let info = client_manager
+            .clients
+            .get_mut(&conn_id)
+            .ok_or(ServerError::NotLoggedIn)?;
+        info.status = status;
+        Ok(())
     }
 }
@@ -3,11 +3,49 @@ use orwell::shared::config::{Config, ConfigError};
 use serde::{Deserialize, Serialize};
 use std::sync::RwLock;
 
+/// Which storage backend the server keeps clients and messages in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DatabaseBackend {
+    Sqlite,
+    Postgres,
+}
+
+/// Limits on stored history, unset or 0 means unlimited.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RetentionPolicy {
+    /// Seconds a message is kept.
+    pub max_age: Option<u64>,
+    /// Newest messages kept for every recipient.
+    pub max_count_per_recipient: Option<u64>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct RetentionConfig {
+    /// Seconds between two cleanup runs.
+    pub interval: Option<u64>,
+    #[serde(flatten)]
+    pub policy: RetentionPolicy,
+}
+
+impl RetentionConfig {
+    pub fn interval_or_default(&self) -> u64 {
+        self.interval.unwrap_or(300).max(1)
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ServerConfig {
     pub port: Option<u16>,
     pub cert_key_path: Option<String>,
     pub cert_fullchain_path: Option<String>,
+    pub database_backend: Option<DatabaseBackend>,
+    /// SQLite database file, used by the `sqlite` backend.
+    pub database_path: Option<String>,
+    /// Connection url, used by the `postgres` backend.
+    pub database_url: Option<String>,
+    pub database_pool_size: Option<u32>,
+    pub retention: Option<RetentionConfig>,
 }
 
 impl Config for ServerConfig {
@@ -20,6 +58,20 @@ impl ServerConfig {
     pub fn port_or_default(&self) -> u16 {
         self.port.unwrap_or(1337)
     }
+
+    pub fn database_backend_or_default(&self) -> DatabaseBackend {
+        self.database_backend.unwrap_or(DatabaseBackend::Sqlite)
+    }
+
+    pub fn database_path_or_default(&self) -> String {
+        self.database_path
+            .clone()
+            .unwrap_or_else(|| "server.db".to_string())
+    }
+
+    pub fn database_pool_size_or_default(&self) -> u32 {
+        self.database_pool_size.unwrap_or(8)
+    }
 }
 
 impl Default for ServerConfig {
@@ -28,6 +80,11 @@ impl Default for ServerConfig {
             port: Some(1337),
             cert_key_path: Some(String::new()),
             cert_fullchain_path: Some(String::new()),
+            database_backend: Some(DatabaseBackend::Sqlite),
+            database_path: Some("server.db".to_string()),
+            database_url: Some(String::new()),
+            database_pool_size: Some(8),
+            retention: Some(RetentionConfig::default()),
         }
     }
 }
@@ -53,6 +110,29 @@ pub fn get_cert_fullchain_path() -> Option<String> {
     get_config().cert_fullchain_path.clone()
 }
 
+pub fn get_database_backend() -> DatabaseBackend {
+    get_config().database_backend_or_default()
+}
+
+pub fn get_database_path() -> String {
+    get_config().database_path_or_default()
+}
+
+pub fn get_database_url() -> Option<String> {
+    get_config()
+        .database_url
+        .clone()
+        .filter(|url| !url.is_empty())
+}
+
+pub fn get_database_pool_size() -> u32 {
+    get_config().database_pool_size_or_default()
+}
+
+pub fn get_retention_config() -> RetentionConfig {
+    get_config().retention.unwrap_or_default()
+}
+
 /// Reload configuration from file
 pub fn reload_config() -> Result<(), ConfigError> {
     let new_config = ServerConfig::load()?;
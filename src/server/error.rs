@@ -0,0 +1,123 @@
+use std::fmt;
+
+use tokio_tungstenite::tungstenite;
+
+/// Errors raised while serving a single connection.
+#[derive(Debug)]
+pub enum ServerError {
+    /// The peer sent bytes that are not a valid protobuf message.
+    Decode(prost::DecodeError),
+    /// The Kyber handshake could not be completed.
+    Handshake(anyhow::Error),
+    /// A ratchet packet could not be decrypted.
+    Ratchet(anyhow::Error),
+    /// A decrypted packet was rejected (signature, timestamp, salt, payload).
+    InvalidPacket(anyhow::Error),
+    /// The packet requires a logged in client.
+    NotLoggedIn,
+    /// No ratchet or sender is registered for the connection.
+    ConnectionNotFound(u32),
+    WebSocket(Box<tungstenite::Error>),
+    DatabasePool(diesel::r2d2::PoolError),
+    Database(diesel::result::Error),
+    /// Embedded migrations could not be applied.
+    Migration(anyhow::Error),
+    Internal(anyhow::Error),
+}
+
+impl ServerError {
+    /// Whether the connection has to be dropped after this error.
+    ///
+    /// Once the ratchet is out of sync or the transport is broken there is no
+    /// way to keep talking to the peer, everything else is reported and the
+    /// connection stays open.
+    pub fn is_fatal(&self) -> bool {
+        matches!(
+            self,
+            ServerError::Decode(_)
+                | ServerError::Handshake(_)
+                | ServerError::Ratchet(_)
+                | ServerError::ConnectionNotFound(_)
+                | ServerError::WebSocket(_)
+        )
+    }
+
+    /// Message sent to the peer in a `ServerError` packet.
+    ///
+    /// Internal details (database, io) stay in the server log.
+    pub fn client_message(&self) -> String {
+        match self {
+            ServerError::Decode(_) => "非法数据".to_string(),
+            ServerError::Handshake(_) => "握手失败".to_string(),
+            ServerError::Ratchet(_) => "棘轮解密失败".to_string(),
+            ServerError::InvalidPacket(e) => format!("数据包被拒绝: {}", e),
+            ServerError::NotLoggedIn => "尚未登录".to_string(),
+            ServerError::ConnectionNotFound(_)
+            | ServerError::WebSocket(_)
+            | ServerError::DatabasePool(_)
+            | ServerError::Database(_)
+            | ServerError::Migration(_)
+            | ServerError::Internal(_) => "服务器内部错误".to_string(),
+        }
+    }
+}
+
+impl fmt::Display for ServerError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ServerError::Decode(e) => write!(f, "decode error: {}", e),
+            ServerError::Handshake(e) => write!(f, "handshake failed: {}", e),
+            ServerError::Ratchet(e) => write!(f, "ratchet error: {}", e),
+            ServerError::InvalidPacket(e) => write!(f, "invalid packet: {}", e),
+            ServerError::NotLoggedIn => write!(f, "client is not logged in"),
+            ServerError::ConnectionNotFound(conn_id) => {
+                write!(f, "connection {} not found", conn_id)
+            }
+            ServerError::WebSocket(e) => write!(f, "websocket error: {}", e),
+            ServerError::DatabasePool(e) => write!(f, "database pool error: {}", e),
+            ServerError::Database(e) => write!(f, "database error: {}", e),
+            ServerError::Migration(e) => write!(f, "migration error: {}", e),
+            ServerError::Internal(e) => write!(f, "internal error: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for ServerError {}
+
+impl From<prost::DecodeError> for ServerError {
+    fn from(err: prost::DecodeError) -> Self {
+        ServerError::Decode(err)
+    }
+}
+
+impl From<tungstenite::Error> for ServerError {
+    fn from(err: tungstenite::Error) -> Self {
+        ServerError::WebSocket(Box::new(err))
+    }
+}
+
+impl From<diesel::r2d2::PoolError> for ServerError {
+    fn from(err: diesel::r2d2::PoolError) -> Self {
+        ServerError::DatabasePool(err)
+    }
+}
+
+impl From<diesel::result::Error> for ServerError {
+    fn from(err: diesel::result::Error) -> Self {
+        ServerError::Database(err)
+    }
+}
+
+impl From<anyhow::Error> for ServerError {
+    /// Adapters return `anyhow::Result`, recover the typed error if there is one.
+    fn from(err: anyhow::Error) -> Self {
+        match err.downcast::<ServerError>() {
+            Ok(e) => e,
+            // payload decode failures happen inside an intact ratchet packet
+            Err(err) if err.downcast_ref::<prost::DecodeError>().is_some() => {
+                ServerError::InvalidPacket(err)
+            }
+            Err(err) => ServerError::Internal(err),
+        }
+    }
+}
@@ -1,34 +1,72 @@
-use diesel::{insert_into, prelude::*};
+use diesel::prelude::*;
 use orwell::{
-    pb::orwell::Key as PbKey,
-    schema::message_keys_::{self, dsl::*},
-    schema::messages_::{self, dsl::*},
+    pb::orwell::{ClientMessage, MessageAction, ServerReceipt},
+    schema::{message_keys_, message_receipts_, messages_},
     shared::helper::get_now_timestamp,
 };
 use uuid::Uuid;
 
-use crate::get_db_connection;
+use crate::{error::ServerError, storage::storage};
 
 #[derive(Queryable, Selectable, Insertable)]
 #[diesel(table_name = messages_)]
-#[diesel(check_for_backend(diesel::sqlite::Sqlite))]
+#[diesel(check_for_backend(diesel::sqlite::Sqlite, diesel::pg::Pg))]
 #[diesel(primary_key(unique_id_))]
 pub struct Message {
     pub id_: String,
     pub sender_id_: String,
     pub data_: Vec<u8>,
     pub timestamp_: i64,
+    /// Set from the sender's ttl, the message leaves history after this time.
+    pub expires_at_: Option<i64>,
+    /// `MessageAction` of the message, edits, deletes and reactions name their
+    /// target so deleting it can take them along.
+    pub action_: i32,
+    pub target_id_: Option<String>,
 }
 
 #[derive(Queryable, Selectable, Insertable)]
 #[diesel(table_name = message_keys_)]
-#[diesel(check_for_backend(diesel::sqlite::Sqlite))]
+#[diesel(check_for_backend(diesel::sqlite::Sqlite, diesel::pg::Pg))]
 #[diesel(primary_key(unique_id_))]
 pub struct MessageKey {
     pub id_: String,
     pub msg_id_: String,
     pub receiver_id_: String,
     pub data_: Vec<u8>,
+    /// Waiting in the receiver's offline queue, not delivered yet.
+    pub pending_: bool,
+}
+
+/// Set once a recipient acknowledged the message, `read_at_` once they saw it.
+#[derive(Queryable, Selectable, Insertable)]
+#[diesel(table_name = message_receipts_)]
+#[diesel(check_for_backend(diesel::sqlite::Sqlite, diesel::pg::Pg))]
+pub struct Receipt {
+    pub msg_id_: String,
+    pub receiver_id_: String,
+    pub delivered_at_: i64,
+    pub read_at_: Option<i64>,
+}
+
+/// How many recipients of a message got and read it, the sender not counted.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReceiptCounts {
+    pub msg_id: String,
+    pub recipients: i64,
+    pub delivered: i64,
+    pub read: i64,
+}
+
+impl ReceiptCounts {
+    pub fn to_pb_receipt(&self) -> ServerReceipt {
+        ServerReceipt {
+            message_id: self.msg_id.clone(),
+            recipients: self.recipients as u32,
+            delivered: self.delivered as u32,
+            read: self.read as u32,
+        }
+    }
 }
 
 pub struct MessageManager {}
@@ -38,45 +76,114 @@ impl MessageManager {
         Self {}
     }
 
-    pub async fn add_message(sender_id: String, data: Vec<u8>, keys: Vec<PbKey>) {
-        let msg_id = Uuid::now_v7().to_string();
-        let mut conn: SqliteConnection = get_db_connection();
+    pub fn new_message_id() -> String {
+        Uuid::now_v7().to_string()
+    }
+
+    /// Store a message, receivers missing from `delivered` get it queued
+    /// for their next login.
+    pub async fn add_message(
+        msg_id: String,
+        sender_id: String,
+        message: ClientMessage,
+        delivered: &[String],
+    ) -> Result<(), ServerError> {
+        let timestamp = get_now_timestamp() as i64;
+        let action = message.action();
+        let ttl = message.ttl;
+        let keys = message.keys;
         let message = Message {
             id_: msg_id.clone(),
             sender_id_: sender_id,
-            data_: data,
-            timestamp_: get_now_timestamp() as i64,
+            data_: message.data,
+            timestamp_: timestamp,
+            expires_at_: (ttl > 0).then(|| {
+                timestamp
+                    .saturating_add(i64::try_from(ttl.saturating_mul(1000)).unwrap_or(i64::MAX))
+            }),
+            action_: action as i32,
+            target_id_: (action != MessageAction::Post).then_some(message.target_id),
         };
-        insert_into(messages_)
-            .values(message)
-            .execute(&mut conn)
-            .unwrap();
-        for key in keys {
-            let id = Uuid::now_v7().to_string();
-            let key = MessageKey {
-                id_: id,
+        let keys = keys
+            .into_iter()
+            .map(|key| MessageKey {
+                id_: Uuid::now_v7().to_string(),
                 msg_id_: msg_id.clone(),
+                pending_: !delivered.contains(&key.receiver_id),
                 receiver_id_: key.receiver_id,
                 data_: key.ciphertext,
-            };
-            insert_into(message_keys_)
-                .values(key)
-                .execute(&mut conn)
-                .unwrap();
+            })
+            .collect::<Vec<_>>();
+        // a message without its keys is unreadable, the storage keeps both or nothing
+        storage()?.insert_message(message, keys).await
+    }
+
+    pub async fn get_message(id: &str) -> Result<Option<Message>, ServerError> {
+        storage()?.find_message(id.to_string()).await
+    }
+
+    /// Drop a message along with the edits and reactions that target it.
+    pub async fn delete_message(id: &str) -> Result<(), ServerError> {
+        storage()?.delete_message(id.to_string()).await?;
+        Ok(())
+    }
+
+    /// Record that `receiver_id` got (or read) a message, returns the sender
+    /// and new counts when anything changed.
+    pub async fn record_receipt(
+        msg_id: &str,
+        receiver_id: &str,
+        read: bool,
+    ) -> Result<Option<(String, ReceiptCounts)>, ServerError> {
+        storage()?
+            .record_receipt(
+                msg_id.to_string(),
+                receiver_id.to_string(),
+                read,
+                get_now_timestamp() as i64,
+            )
+            .await
+    }
+
+    pub async fn get_receipt_counts(
+        msg_ids: Vec<String>,
+    ) -> Result<Vec<ReceiptCounts>, ServerError> {
+        if msg_ids.is_empty() {
+            return Ok(vec![]);
         }
+        storage()?.load_receipt_counts(msg_ids).await
+    }
+
+    /// Oldest `amount` messages `receiver_id` missed while offline.
+    pub async fn get_pending_messages(
+        receiver_id: String,
+        amount: i32,
+    ) -> Result<Vec<(Message, MessageKey)>, ServerError> {
+        storage()?
+            .load_pending(receiver_id, amount as i64, get_now_timestamp() as i64)
+            .await
+    }
+
+    pub async fn count_pending_messages(receiver_id: String) -> Result<i64, ServerError> {
+        storage()?
+            .count_pending(receiver_id, get_now_timestamp() as i64)
+            .await
+    }
+
+    pub async fn mark_delivered(
+        receiver_id: String,
+        msg_ids: Vec<String>,
+    ) -> Result<(), ServerError> {
+        storage()?.clear_pending(receiver_id, msg_ids).await?;
+        Ok(())
     }
 
     pub async fn get_history_messages(
         receiver_id: String,
         amount: i32,
-    ) -> Vec<(Message, MessageKey)> {
-        let mut conn: SqliteConnection = get_db_connection();
-        messages_::table
-            .inner_join(message_keys_::table.on(message_keys_::msg_id_.eq(messages_::id_)))
-            .filter(message_keys_::receiver_id_.eq(receiver_id))
-            .order(messages_::timestamp_.desc())
-            .limit(amount as i64)
-            .load::<(Message, MessageKey)>(&mut conn)
-            .unwrap()
+    ) -> Result<Vec<(Message, MessageKey)>, ServerError> {
+        storage()?
+            .load_history(receiver_id, amount as i64, get_now_timestamp() as i64)
+            .await
     }
 }
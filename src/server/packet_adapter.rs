@@ -2,7 +2,11 @@ use anyhow::Result;
 use async_trait::async_trait;
 use orwell::pb::orwell::{OrwellPacket, PacketType};
 
-use crate::{client::ClientInfo, WsSender};
+use crate::{
+    client::{Client, ClientInfo},
+    error::ServerError,
+    WsSender,
+};
 
 /// Context for packet processing
 pub struct PacketContext {
@@ -11,6 +15,16 @@ pub struct PacketContext {
     pub client_info: Option<ClientInfo>,
 }
 
+impl PacketContext {
+    /// The logged in client that sent the packet.
+    pub fn client(&self) -> Result<&Client, ServerError> {
+        self.client_info
+            .as_ref()
+            .map(|info| &info.client)
+            .ok_or(ServerError::NotLoggedIn)
+    }
+}
+
 /// Trait for packet adapters
 #[async_trait]
 pub trait PacketAdapter: Send + Sync {
@@ -0,0 +1,106 @@
+use std::time::Duration;
+
+use orwell::shared::helper::get_now_timestamp;
+use tracing::{info, warn};
+
+use crate::{
+    config::{get_retention_config, RetentionConfig},
+    error::ServerError,
+    storage::{storage, Purged, Storage},
+};
+
+/// Periodically drop history that is past its ttl or the configured limits.
+pub async fn run_retention_task() {
+    loop {
+        let config = get_retention_config();
+        let now = get_now_timestamp() as i64;
+        let result = async { enforce_retention(storage()?, &config, now).await }.await;
+        match result {
+            Ok(purged) if purged != Purged::default() => info!(
+                "已清理过期消息: {} 条消息, {} 个密钥",
+                purged.messages, purged.keys
+            ),
+            Ok(_) => {}
+            Err(e) => warn!("Retention pass failed: {}", e),
+        }
+        tokio::time::sleep(Duration::from_secs(config.interval_or_default())).await;
+    }
+}
+
+/// One cleanup pass at `now`.
+pub async fn enforce_retention(
+    storage: &dyn Storage,
+    config: &RetentionConfig,
+    now: i64,
+) -> Result<Purged, ServerError> {
+    let mut purged = storage.delete_expired_messages(now).await?;
+
+    if let Some(max_age) = config.policy.max_age.filter(|age| *age > 0) {
+        let max_age = i64::try_from(max_age.saturating_mul(1000)).unwrap_or(i64::MAX);
+        purged += storage
+            .delete_messages_before(now.saturating_sub(max_age))
+            .await?;
+    }
+    if let Some(max_count) = config
+        .policy
+        .max_count_per_recipient
+        .filter(|count| *count > 0)
+    {
+        purged += storage
+            .trim_history(i64::try_from(max_count).unwrap_or(i64::MAX))
+            .await?;
+    }
+    Ok(purged)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        config::RetentionPolicy,
+        storage::tests::{history_timestamps, remove_sqlite, shared_message, temp_sqlite},
+    };
+
+    #[tokio::test]
+    async fn policy_limits_age_count_and_ttl() {
+        let (storage, path) = temp_sqlite().await;
+        for (timestamp, receivers) in [
+            (50_000, &["reader"][..]),
+            (95_000, &["reader", "other"][..]),
+            (97_000, &["reader"][..]),
+        ] {
+            let (message, keys) = shared_message(timestamp, receivers);
+            storage.insert_message(message, keys).await.unwrap();
+        }
+        let (mut message, keys) = shared_message(96_000, &["reader"]);
+        message.expires_at_ = Some(99_000);
+        storage.insert_message(message, keys).await.unwrap();
+
+        let config = RetentionConfig {
+            interval: None,
+            policy: RetentionPolicy {
+                max_age: Some(10),
+                max_count_per_recipient: Some(1),
+            },
+        };
+        // 95_000 loses the reader's key but other can still read it
+        let purged = enforce_retention(&storage, &config, 100_000).await.unwrap();
+        assert_eq!(
+            purged,
+            Purged {
+                messages: 2,
+                keys: 3
+            }
+        );
+        assert_eq!(
+            history_timestamps(&storage, "reader", 100_000).await,
+            vec![97_000]
+        );
+        assert_eq!(
+            history_timestamps(&storage, "other", 100_000).await,
+            vec![95_000]
+        );
+
+        remove_sqlite(storage, path);
+    }
+}
@@ -1,12 +1,12 @@
 use anyhow::Result;
 use crystals_dilithium::dilithium5;
-use diesel::{Connection, SqliteConnection};
-use futures_util::{stream::SplitSink, SinkExt, StreamExt};
+use futures_util::{stream::SplitSink, FutureExt, SinkExt, StreamExt};
 use lazy_static::lazy_static;
 use orwell::{
     pb::orwell::{
-        ClientHello, ClientHello2, Key, MessageType, OrwellRatchetPacket, OrwellRatchetStep,
-        OrwellSignedPacket, PacketType, ServerBroadcastMessage, ServerHeartbeat, ServerHello,
+        ClientHello, ClientHello2, ClientMessage, Key, MessageType, OrwellRatchetPacket,
+        OrwellRatchetStep, OrwellSignedPacket, PacketType, ServerBroadcastMessage,
+        ServerError as PbServerError, ServerHeartbeat, ServerHello,
     },
     shared::{
         encryption::{Encryption, KyberDoubleRatchet, RatchetState},
@@ -21,7 +21,11 @@ use std::{
     collections::HashMap,
     fs,
     io::BufReader,
-    sync::Arc,
+    panic::AssertUnwindSafe,
+    sync::{
+        atomic::{AtomicU32, Ordering},
+        Arc,
+    },
     time::{Duration, Instant},
 };
 use tokio::{
@@ -37,6 +41,7 @@ use crate::{
     adapters::create_registry,
     client::ClientManager,
     config::{get_cert_fullchain_path, get_cert_key_path, get_port},
+    error::ServerError,
     message::MessageManager,
     packet_adapter::PacketContext,
     service::Service,
@@ -47,13 +52,19 @@ use packet_adapter::PacketAdapterRegistry;
 mod adapters;
 mod client;
 mod config;
+mod error;
 mod message;
 mod packet_adapter;
+mod retention;
 mod service;
+mod storage;
 mod token;
 
 pub type WsSender = SplitSink<WebSocketStream<TlsStream<TcpStream>>, Message>;
 
+/// Connection ids are never reused, unlike the peer port they used to be derived from.
+static NEXT_CONN_ID: AtomicU32 = AtomicU32::new(1);
+
 pub struct State {
     dilithium_sk: dilithium5::SecretKey,
     dilithium_pk: dilithium5::PublicKey,
@@ -100,6 +111,7 @@ async fn broadcast_message_from_server(
     let mut msg_data = msg_data.to_vec();
     msg_data.insert(0, message_type as u8);
     let encrypted_data = Encryption::aes_encrypt(&msg_data, &key);
+    let msg_id = MessageManager::new_message_id();
 
     let packet = ServerBroadcastMessage {
         key: None,
@@ -108,29 +120,53 @@ async fn broadcast_message_from_server(
         color,
         data: encrypted_data.clone(),
         timestamp: get_now_timestamp(),
+        id: msg_id.clone(),
+        receipt: None,
     };
 
     let mut keys = vec![];
+    let mut delivered = vec![];
 
-    for client_info in ClientManager::get_all_clients().await {
+    for client_info in ClientManager::get_all_clients().await? {
         let client = client_info.client;
         let mut p = packet.clone();
+        let ciphertext = match Encryption::kyber_encrypt(&key, &client.kyber_pk_) {
+            Ok(ciphertext) => ciphertext,
+            Err(e) => {
+                warn!("Skipping {} in broadcast: {:?}", client.id_, e);
+                continue;
+            }
+        };
         let k = Key {
             receiver_id: client.id_.clone(),
-            ciphertext: Encryption::kyber_encrypt(&key, &client.kyber_pk_)?,
+            ciphertext,
         };
         p.key = Some(k.clone());
         keys.push(k);
 
         if except_sender && client.id_ == sender_id {
+            // the notice is about them, nothing to catch up on later
+            delivered.push(client.id_);
             continue;
         }
         if let Some(conn_id) = ClientManager::get_client_connection_by_id(&client.id_).await {
-            send_packet(conn_id, PacketType::ServerBroadcastMessage, p.clone()).await?;
+            // one broken receiver must not stop the broadcast for everyone else
+            if let Err(e) =
+                send_packet(conn_id, PacketType::ServerBroadcastMessage, p.clone()).await
+            {
+                warn!("Failed to broadcast to {}: {:?}", conn_id, e);
+            } else {
+                delivered.push(client.id_);
+            }
         }
     }
 
-    MessageManager::add_message(sender_id.clone(), encrypted_data.clone(), keys).await;
+    let message = ClientMessage {
+        keys,
+        data: encrypted_data.clone(),
+        ..Default::default()
+    };
+    MessageManager::add_message(msg_id, sender_id.clone(), message, &delivered).await?;
 
     Ok(())
 }
@@ -152,12 +188,14 @@ where
     let end_time: Instant = Instant::now();
     info!("加密用时 {}ms", (end_time - start_time).as_millis());
 
-    let target_sender = {
+    let sender = {
         let senders = SENDERS.read().await;
-        senders.get(&conn_id).cloned()
+        senders
+            .get(&conn_id)
+            .cloned()
+            .ok_or(ServerError::ConnectionNotFound(conn_id))?
     };
 
-    let sender = target_sender.unwrap();
     let mut sender = sender.lock().await;
     sender.send(Message::Binary(encrypted.into())).await?;
 
@@ -171,7 +209,7 @@ where
     let mut connections = CONNECTIONS.write().await;
     let ratchet = connections
         .get_mut(&conn_id)
-        .ok_or_else(|| anyhow::anyhow!("Connection not found"))?;
+        .ok_or(ServerError::ConnectionNotFound(conn_id))?;
     let ratchet_state = ratchet.ratchet_state.clone();
     send_packet_internal(conn_id, packet_type, packet, ratchet).await?;
     drop(connections);
@@ -180,7 +218,9 @@ where
     if rand && ratchet_state == RatchetState::HandshakeFinished {
         info!("ratchet step");
         let mut connections = CONNECTIONS.write().await;
-        let ratchet = connections.get_mut(&conn_id).unwrap();
+        let ratchet = connections
+            .get_mut(&conn_id)
+            .ok_or(ServerError::ConnectionNotFound(conn_id))?;
         let mut old_ratchet = ratchet.clone();
         let ct = ratchet.step_send_chain()?;
         let packet = OrwellRatchetStep {
@@ -214,20 +254,22 @@ async fn handle_packet(
     packet: OrwellSignedPacket,
     ws_sender: Arc<Mutex<WsSender>>,
     conn_id: u32,
-) -> Result<()> {
+) -> Result<(), ServerError> {
     let client = ClientManager::get_client_by_connection(conn_id).await;
 
     let validated_packet = match &client {
-        None => Encryption::validate(packet.clone(), None)?,
+        None => Encryption::validate(packet.clone(), None),
         Some(client_info) => Encryption::validate(
             packet.clone(),
             Some(&dilithium5::PublicKey::from_bytes(
                 &client_info.client.dilithium_pk_,
             )),
-        )?,
-    };
+        ),
+    }
+    .map_err(ServerError::InvalidPacket)?;
 
-    let packet_type = PacketType::try_from(validated_packet.packet_type)?;
+    let packet_type = PacketType::try_from(validated_packet.packet_type)
+        .map_err(|e| ServerError::InvalidPacket(e.into()))?;
 
     let registry = get_adapter_registry().await;
     if let Some(adapter) = registry.get(packet_type) {
@@ -245,23 +287,154 @@ async fn handle_packet(
     Ok(())
 }
 
+/// Answer a `ClientHello`, returning the new ratchet and the `ServerHello` for the peer.
+fn accept_client_hello(
+    data: &[u8],
+    dilithium_pk: &dilithium5::PublicKey,
+) -> Result<(KyberDoubleRatchet, ServerHello), ServerError> {
+    let packet = ClientHello::decode(data)?;
+    let mut ratchet = KyberDoubleRatchet::new();
+    ratchet.ratchet_state = RatchetState::HandshakePhase2;
+    let response = ratchet
+        .initialize_session(&packet.pk)
+        .map_err(ServerError::Handshake)?;
+    let packet = ServerHello {
+        ciphertext: response,
+        pk: ratchet.kyber_pk.as_bytes().to_vec(),
+        dilithium_pk: dilithium_pk.to_bytes().to_vec(),
+    };
+    Ok((ratchet, packet))
+}
+
+/// Finish the handshake with the `ClientHello2` of the peer.
+fn accept_client_hello2(data: &[u8], ratchet: &mut KyberDoubleRatchet) -> Result<(), ServerError> {
+    let packet = ClientHello2::decode(data)?;
+    ratchet
+        .finalize_session(&packet.ciphertext)
+        .map_err(ServerError::Handshake)?;
+    ratchet.ratchet_state = RatchetState::HandshakeFinished;
+    Ok(())
+}
+
+/// Decrypt a packet received after the handshake.
+fn open_ratchet_packet(
+    data: &[u8],
+    ratchet: &mut KyberDoubleRatchet,
+) -> Result<OrwellSignedPacket, ServerError> {
+    let packet = OrwellRatchetPacket::decode(data)?;
+    ratchet.decrypt(packet).map_err(ServerError::Ratchet)
+}
+
+async fn get_ratchet_state(conn_id: u32) -> RatchetState {
+    let connections = CONNECTIONS.read().await;
+    match connections.get(&conn_id) {
+        Some(ratchet) => ratchet.ratchet_state.clone(),
+        None => RatchetState::HandshakePhase1,
+    }
+}
+
+async fn handle_binary(
+    data: &[u8],
+    ws_sender: Arc<Mutex<WsSender>>,
+    conn_id: u32,
+) -> Result<(), ServerError> {
+    match get_ratchet_state(conn_id).await {
+        RatchetState::HandshakePhase1 => {
+            info!("客户端已连接");
+            let state = STATE.read().await;
+            let (ratchet, packet) = accept_client_hello(data, &state.dilithium_pk)?;
+            drop(state);
+            let mut connections = CONNECTIONS.write().await;
+            connections.insert(conn_id, ratchet);
+            drop(connections);
+
+            let response = packet.encode_to_vec();
+            let mut sender = ws_sender.lock().await;
+            sender.send(Message::Binary(response.into())).await?;
+            drop(sender);
+            info!("已回应客户端");
+        }
+        RatchetState::HandshakePhase2 => {
+            let mut connections = CONNECTIONS.write().await;
+            let ratchet = connections
+                .get_mut(&conn_id)
+                .ok_or(ServerError::ConnectionNotFound(conn_id))?;
+            accept_client_hello2(data, ratchet)?;
+            drop(connections);
+
+            let mut random_data = vec![];
+            let mut rng = rand::rngs::OsRng;
+            for _ in 0..rng.gen_range(1024..4096) {
+                random_data.push(rng.gen_range(0..=255));
+            }
+            let mut sender = ws_sender.lock().await;
+            sender
+                .send(Message::Binary(random_data.to_vec().into()))
+                .await?;
+            drop(sender);
+        }
+        RatchetState::HandshakeFinished => {
+            let mut connections = CONNECTIONS.write().await;
+            let ratchet = connections
+                .get_mut(&conn_id)
+                .ok_or(ServerError::ConnectionNotFound(conn_id))?;
+            let data = open_ratchet_packet(data, ratchet)?;
+            drop(connections);
+
+            handle_packet(data, ws_sender.clone(), conn_id).await?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Tell the peer about a rejected packet, if there is a secure channel to do so.
+async fn report_error(conn_id: u32, error: &ServerError) {
+    if get_ratchet_state(conn_id).await != RatchetState::HandshakeFinished {
+        return;
+    }
+    let packet = PbServerError {
+        error: error.client_message(),
+    };
+    if let Err(e) = send_packet(conn_id, PacketType::ServerError, packet).await {
+        warn!("Failed to report error to {}: {:?}", conn_id, e);
+    }
+}
+
+/// Drop everything the server keeps about a connection.
+async fn cleanup_connection(conn_id: u32) {
+    CONNECTIONS.write().await.remove(&conn_id);
+    SENDERS.write().await.remove(&conn_id);
+    if let Err(e) = Service::logout_client(conn_id).await {
+        warn!("Failed to logout connection {}: {:?}", conn_id, e);
+    }
+}
+
 async fn handle_connection_with_error(
     stream: WebSocketStream<TlsStream<TcpStream>>,
     addr: std::net::SocketAddr,
-) -> Result<()> {
-    handle_connection(stream, addr)
+) {
+    let conn_id = NEXT_CONN_ID.fetch_add(1, Ordering::Relaxed);
+    // a panic must not leave a half registered connection behind
+    match AssertUnwindSafe(handle_connection(stream, addr, conn_id))
+        .catch_unwind()
         .await
-        .inspect_err(|e| warn!("Error when handling connection: {:?}", e))
+    {
+        Ok(Ok(())) => {}
+        Ok(Err(e)) => warn!("Error when handling connection {}: {}", conn_id, e),
+        Err(_) => warn!("Connection {} panicked", conn_id),
+    }
+    cleanup_connection(conn_id).await;
 }
 
 async fn handle_connection(
     stream: WebSocketStream<TlsStream<TcpStream>>,
     addr: std::net::SocketAddr,
-) -> Result<()> {
+    conn_id: u32,
+) -> Result<(), ServerError> {
     let (ws_sender_raw, mut ws_receiver) = stream.split();
     let ws_sender = Arc::new(Mutex::new(ws_sender_raw));
-    let conn_id = addr.port() as u32;
-    info!("New connection: {}", conn_id);
+    info!("New connection: {} ({})", conn_id, addr);
     // Store sender for global access
     let mut senders = SENDERS.write().await;
     senders.insert(conn_id, ws_sender.clone());
@@ -269,104 +442,24 @@ async fn handle_connection(
     info!("Sender stored: {}", conn_id);
 
     while let Some(msg) = ws_receiver.next().await {
-        match msg {
-            Ok(Message::Binary(data)) => {
-                let connections = CONNECTIONS.read().await;
-                let ratchet: Option<&KyberDoubleRatchet> = connections.get(&conn_id);
-                let state = match ratchet {
-                    Some(ratchet) => ratchet.ratchet_state.clone(),
-                    None => RatchetState::HandshakePhase1,
-                };
-                drop(connections);
-                match state {
-                    RatchetState::HandshakePhase1 => {
-                        info!("客户端已连接");
-                        let state = STATE.read().await;
-                        let packet = ClientHello::decode(data).unwrap();
-                        let mut ratchet = KyberDoubleRatchet::new();
-                        ratchet.ratchet_state = RatchetState::HandshakePhase2;
-                        let response = ratchet.initialize_session(&packet.pk)?;
-                        let packet = ServerHello {
-                            ciphertext: response,
-                            pk: ratchet.kyber_pk.as_bytes().to_vec(),
-                            dilithium_pk: state.dilithium_pk.to_bytes().to_vec(),
-                        };
-                        drop(state);
-                        let mut connections = CONNECTIONS.write().await;
-                        connections.insert(conn_id, ratchet);
-                        drop(connections);
-
-                        let response = packet.encode_to_vec();
-                        let mut sender = ws_sender.lock().await;
-                        sender.send(Message::Binary(response.into())).await.unwrap();
-                        drop(sender);
-                        info!("已回应客户端");
-                    }
-                    RatchetState::HandshakePhase2 => {
-                        let packet = ClientHello2::decode(data).unwrap();
-                        let mut connections = CONNECTIONS.write().await;
-                        let ratchet: Option<&mut KyberDoubleRatchet> =
-                            connections.get_mut(&conn_id);
-                        if ratchet.is_none() {
-                            return Err(anyhow::anyhow!("Connection not found"));
-                        }
-                        let ratchet = ratchet.unwrap();
-                        ratchet.finalize_session(&packet.ciphertext)?;
-                        ratchet.ratchet_state = RatchetState::HandshakeFinished;
-                        drop(connections);
-
-                        let mut random_data = vec![];
-                        let mut rng = rand::rngs::OsRng;
-                        for _ in 0..rng.gen_range(1024..4096) {
-                            random_data.push(rng.gen_range(0..=255));
-                        }
-                        let mut sender = ws_sender.lock().await;
-                        sender
-                            .send(Message::Binary(random_data.to_vec().into()))
-                            .await
-                            .unwrap();
-                        drop(sender);
-                    }
-                    RatchetState::HandshakeFinished => {
-                        let mut connections: tokio::sync::RwLockWriteGuard<
-                            '_,
-                            HashMap<u32, KyberDoubleRatchet>,
-                        > = CONNECTIONS.write().await;
-                        let ratchet: Option<&mut KyberDoubleRatchet> =
-                            connections.get_mut(&conn_id);
-                        if ratchet.is_none() {
-                            return Err(anyhow::anyhow!("Connection not found"));
-                        }
-                        let ratchet = ratchet.unwrap();
-                        let data = OrwellRatchetPacket::decode(data)?;
-                        let data = ratchet.decrypt(data)?;
-                        drop(connections);
-
-                        handle_packet(data, ws_sender.clone(), conn_id).await?;
+        match msg? {
+            Message::Binary(data) => {
+                if let Err(e) = handle_binary(&data, ws_sender.clone(), conn_id).await {
+                    warn!("Rejected packet from {}: {}", conn_id, e);
+                    report_error(conn_id, &e).await;
+                    if e.is_fatal() {
+                        return Err(e);
                     }
                 }
             }
-            Ok(Message::Text(_)) => {
+            Message::Text(_) => {
                 let mut sender = ws_sender.lock().await;
                 sender
                     .send(Message::Text("Invalid packet".to_string().into()))
-                    .await
-                    .unwrap();
+                    .await?;
                 drop(sender);
             }
-            Ok(Message::Close(_)) => {
-                CONNECTIONS.write().await.remove(&conn_id);
-                SENDERS.write().await.remove(&conn_id);
-                Service::logout_client(conn_id).await?;
-                break;
-            }
-            Err(e) => {
-                warn!("WebSocket 错误: {:?}", e);
-                CONNECTIONS.write().await.remove(&conn_id);
-                SENDERS.write().await.remove(&conn_id);
-                Service::logout_client(conn_id).await?;
-                break;
-            }
+            Message::Close(_) => break,
             _ => {}
         }
     }
@@ -374,12 +467,22 @@ async fn handle_connection(
     Ok(())
 }
 
-pub fn get_db_connection() -> SqliteConnection {
-    SqliteConnection::establish("server.db").unwrap()
-}
-
 #[tokio::main]
 async fn main() -> Result<()> {
+    tracing_subscriber::fmt::init();
+
+    storage::init_storage()?;
+    let applied = storage::storage()?.run_migrations().await?;
+    if applied.is_empty() {
+        info!("数据库结构已是最新");
+    } else {
+        info!("已应用数据库迁移: {}", applied.join(", "));
+    }
+    if std::env::args().any(|arg| arg == "--migrate-only") {
+        return Ok(());
+    }
+    tokio::spawn(retention::run_retention_task());
+
     let addr = format!("0.0.0.0:{}", get_port());
     let listener = TcpListener::bind(addr.clone()).await?;
     println!("Listening on: {}", addr);
@@ -408,8 +511,6 @@ async fn main() -> Result<()> {
 
     let acceptor = tokio_rustls::TlsAcceptor::from(Arc::new(config));
 
-    tracing_subscriber::fmt::init();
-
     // heartbeat
     tokio::spawn(async move {
         loop {
@@ -464,3 +565,126 @@ async fn main() -> Result<()> {
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use orwell::pb::orwell::OrwellPacket;
+
+    fn garbage() -> Vec<Vec<u8>> {
+        let mut random = vec![0u8; 4096];
+        rand::thread_rng().fill(random.as_mut_slice());
+        vec![
+            vec![],
+            vec![0xff; 64],
+            random,
+            ClientHello { pk: vec![1, 2, 3] }.encode_to_vec(),
+        ]
+    }
+
+    /// Runs the handshake the way the client does and returns both ends.
+    fn handshake() -> (KyberDoubleRatchet, KyberDoubleRatchet) {
+        let dilithium = dilithium5::Keypair::generate(None);
+        let mut client = KyberDoubleRatchet::new();
+        let hello = ClientHello {
+            pk: client.kyber_pk.as_bytes().to_vec(),
+        };
+        let (mut server, server_hello) =
+            accept_client_hello(&hello.encode_to_vec(), &dilithium.public).unwrap();
+        let ct = client
+            .establish_session(&server_hello.ciphertext, &server_hello.pk)
+            .unwrap();
+        let hello2 = ClientHello2 {
+            ciphertext: ct.as_bytes().to_vec(),
+        };
+        accept_client_hello2(&hello2.encode_to_vec(), &mut server).unwrap();
+        (client, server)
+    }
+
+    #[test]
+    fn garbage_during_handshake_phase1_is_rejected() {
+        let dilithium = dilithium5::Keypair::generate(None);
+        for data in garbage() {
+            assert!(accept_client_hello(&data, &dilithium.public).is_err());
+        }
+    }
+
+    #[test]
+    fn garbage_during_handshake_phase2_is_rejected() {
+        let dilithium = dilithium5::Keypair::generate(None);
+        let client = KyberDoubleRatchet::new();
+        let hello = ClientHello {
+            pk: client.kyber_pk.as_bytes().to_vec(),
+        };
+        let (mut server, _) =
+            accept_client_hello(&hello.encode_to_vec(), &dilithium.public).unwrap();
+        for data in garbage() {
+            let result = accept_client_hello2(&data, &mut server);
+            assert!(result.unwrap_err().is_fatal());
+            assert!(server.ratchet_state == RatchetState::HandshakePhase2);
+        }
+    }
+
+    #[test]
+    fn garbage_after_handshake_is_rejected() {
+        let mut garbage = garbage();
+        garbage.push(
+            OrwellRatchetPacket {
+                kyber_pk: vec![0; 1568],
+                send_counter: 0,
+                recv_counter: 0,
+                data: vec![0; 128],
+            }
+            .encode_to_vec(),
+        );
+        for data in garbage {
+            let (_, mut server) = handshake();
+            assert!(open_ratchet_packet(&data, &mut server).is_err());
+        }
+    }
+
+    #[test]
+    fn huge_send_counter_is_rejected() {
+        let (mut client, mut server) = handshake();
+        let mut packet = client
+            .encrypt(OrwellSignedPacket {
+                data: None,
+                sign: vec![],
+            })
+            .unwrap();
+        packet.send_counter = u64::MAX;
+        let result = open_ratchet_packet(&packet.encode_to_vec(), &mut server);
+        assert!(matches!(result, Err(ServerError::Ratchet(_))));
+    }
+
+    #[test]
+    fn valid_ratchet_packet_with_garbage_payload_is_not_fatal() {
+        let (mut client, mut server) = handshake();
+        let packet = client
+            .encrypt(OrwellSignedPacket {
+                data: Some(OrwellPacket {
+                    timestamp: 0,
+                    salt: vec![0; 3],
+                    packet_type: i32::MAX,
+                    data: vec![0xff; 16],
+                }),
+                sign: vec![0; 16],
+            })
+            .unwrap();
+        let packet = open_ratchet_packet(&packet.encode_to_vec(), &mut server).unwrap();
+        let error = Encryption::validate(packet, None)
+            .map_err(ServerError::InvalidPacket)
+            .unwrap_err();
+        assert!(!error.is_fatal());
+    }
+
+    #[test]
+    fn payload_decode_error_is_not_fatal() {
+        let error = ServerError::from(
+            anyhow::Error::new(ClientHello::decode([0xffu8; 8].as_slice()).unwrap_err())
+                .context("非法数据"),
+        );
+        assert!(matches!(error, ServerError::InvalidPacket(_)));
+        assert!(!error.is_fatal());
+    }
+}
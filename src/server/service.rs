@@ -1,15 +1,22 @@
+use std::collections::HashMap;
+
 use anyhow::{anyhow, Result};
 use orwell::pb::orwell::{
     Key, MessageType, PacketType, ServerBroadcastMessage, ServerClientInfo, ServerHistoryMessage,
+    ServerReceipt,
 };
+use tracing::warn;
 
 use crate::{
     broadcast_message_from_server,
     client::{Client, ClientManager},
-    message::MessageManager,
+    message::{Message, MessageKey, MessageManager},
     send_packet,
 };
 
+/// Messages missed while offline go out this many per packet.
+const OFFLINE_BATCH: i32 = 100;
+
 pub struct Service {}
 
 impl Service {
@@ -30,52 +37,98 @@ impl Service {
         )
         .await?;
 
-        let mut packet = ServerHistoryMessage { data: vec![] };
-        for (message, key) in MessageManager::get_history_messages(client.id_.clone(), 50).await {
-            let client = ClientManager::get_client_by_id(&message.sender_id_).await;
-            let client = client.unwrap_or_default();
-            let sender_id = client.id_.clone();
-            let sender_name = client.name_.clone();
-            let color = client.color_;
-            packet.data.push(ServerBroadcastMessage {
-                sender_id,
-                sender_name,
-                color,
-                data: message.data_,
-                key: Some(Key {
-                    receiver_id: key.receiver_id_,
-                    ciphertext: key.data_,
-                }),
-                timestamp: message.timestamp_ as u64,
-            });
+        let missed = MessageManager::count_pending_messages(client.id_.clone()).await?;
+        let history = MessageManager::get_history_messages(client.id_.clone(), 50).await?;
+        // the client shows delivery state next to its own messages
+        let own_ids = history
+            .iter()
+            .filter(|(message, _)| message.sender_id_ == client.id_)
+            .map(|(message, _)| message.id_.clone())
+            .collect::<Vec<_>>();
+        let mut receipts = MessageManager::get_receipt_counts(own_ids)
+            .await?
+            .into_iter()
+            .map(|counts| (counts.msg_id.clone(), counts))
+            .collect::<HashMap<_, _>>();
+
+        let mut packet = ServerHistoryMessage {
+            data: vec![],
+            offline: false,
+            missed: 0,
+        };
+        for (message, key) in history {
+            let receipt = receipts
+                .remove(&message.id_)
+                .map(|counts| counts.to_pb_receipt());
+            packet
+                .data
+                .push(Self::to_broadcast_message(message, key, receipt).await?);
         }
 
         send_packet(conn_id, PacketType::ServerHistoryMessage, packet)
             .await
             .map_err(|e| anyhow!("{} 发送历史消息失败: {:?}", client.name_.clone(), e))?;
 
-        let infos = ClientManager::get_all_clients()
-            .await
-            .into_iter()
-            .map(|info: crate::client::ClientInfo| info.to_pb_client_info())
-            .collect::<Vec<_>>();
+        Self::deliver_pending(conn_id, &client, missed).await?;
 
-        for online_client_info in ClientManager::get_all_online_clients().await {
-            let conn_id =
-                ClientManager::get_client_connection_by_id(&online_client_info.client.id_)
-                    .await
-                    .unwrap();
-            send_packet(
-                conn_id,
-                PacketType::ServerClientInfo,
-                ServerClientInfo {
-                    data: infos.clone(),
-                },
-            )
-            .await?;
+        Self::broadcast_resync_client().await
+    }
+
+    /// Replay everything `client` missed while offline, oldest first and in
+    /// batches. A batch leaves the queue only once it was sent.
+    async fn deliver_pending(conn_id: u32, client: &Client, missed: i64) -> Result<()> {
+        let mut first = true;
+        loop {
+            let pending =
+                MessageManager::get_pending_messages(client.id_.clone(), OFFLINE_BATCH).await?;
+            if pending.is_empty() {
+                return Ok(());
+            }
+            let msg_ids = pending
+                .iter()
+                .map(|(message, _)| message.id_.clone())
+                .collect::<Vec<_>>();
+
+            let mut packet = ServerHistoryMessage {
+                data: vec![],
+                offline: true,
+                missed: if first { missed as u32 } else { 0 },
+            };
+            for (message, key) in pending {
+                packet
+                    .data
+                    .push(Self::to_broadcast_message(message, key, None).await?);
+            }
+
+            send_packet(conn_id, PacketType::ServerHistoryMessage, packet)
+                .await
+                .map_err(|e| anyhow!("{} 发送离线消息失败: {:?}", client.name_.clone(), e))?;
+            MessageManager::mark_delivered(client.id_.clone(), msg_ids).await?;
+            first = false;
         }
+    }
 
-        Ok(())
+    async fn to_broadcast_message(
+        message: Message,
+        key: MessageKey,
+        receipt: Option<ServerReceipt>,
+    ) -> Result<ServerBroadcastMessage> {
+        let sender = ClientManager::get_client_by_id(&message.sender_id_)
+            .await?
+            .unwrap_or_default();
+        Ok(ServerBroadcastMessage {
+            sender_id: sender.id_,
+            sender_name: sender.name_,
+            color: sender.color_,
+            data: message.data_,
+            key: Some(Key {
+                receiver_id: key.receiver_id_,
+                ciphertext: key.data_,
+            }),
+            timestamp: message.timestamp_ as u64,
+            receipt,
+            id: message.id_,
+        })
     }
 
     pub async fn logout_client(conn_id: u32) -> Result<()> {
@@ -97,24 +150,28 @@ impl Service {
 
     pub async fn broadcast_resync_client() -> Result<()> {
         let infos = ClientManager::get_all_clients()
-            .await
+            .await?
             .into_iter()
             .map(|info| info.to_pb_client_info())
             .collect::<Vec<_>>();
 
         for online_client_info in ClientManager::get_all_online_clients().await {
-            let conn_id: u32 =
-                ClientManager::get_client_connection_by_id(&online_client_info.client.id_)
-                    .await
-                    .unwrap();
-            send_packet(
+            let Some(conn_id) =
+                ClientManager::get_client_connection_by_id(&online_client_info.client.id_).await
+            else {
+                continue;
+            };
+            if let Err(e) = send_packet(
                 conn_id,
                 PacketType::ServerClientInfo,
                 ServerClientInfo {
                     data: infos.clone(),
                 },
             )
-            .await?;
+            .await
+            {
+                warn!("Failed to resync clients for {}: {:?}", conn_id, e);
+            }
         }
         Ok(())
     }
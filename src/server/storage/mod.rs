@@ -0,0 +1,663 @@
+#[macro_use]
+mod queries;
+mod postgres;
+mod sqlite;
+
+use std::sync::OnceLock;
+
+use async_trait::async_trait;
+use diesel::{
+    backend::Backend,
+    migration::MigrationSource,
+    r2d2::{ConnectionManager, Pool, R2D2Connection},
+};
+use diesel_migrations::{EmbeddedMigrations, MigrationHarness};
+
+pub use postgres::PostgresStorage;
+pub use sqlite::SqliteStorage;
+
+use crate::{
+    client::Client,
+    config::{
+        get_database_backend, get_database_path, get_database_pool_size, get_database_url,
+        DatabaseBackend,
+    },
+    error::ServerError,
+    message::{Message, MessageKey, ReceiptCounts},
+};
+
+static STORAGE: OnceLock<Box<dyn Storage>> = OnceLock::new();
+
+/// Rows removed by a cleanup pass.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Purged {
+    pub messages: usize,
+    pub keys: usize,
+}
+
+impl std::ops::AddAssign for Purged {
+    fn add_assign(&mut self, other: Self) {
+        self.messages += other.messages;
+        self.keys += other.keys;
+    }
+}
+
+/// Persistent state of the server, implemented once per database backend.
+#[async_trait]
+pub trait Storage: Send + Sync {
+    /// Apply pending migrations and return the versions that were run.
+    async fn run_migrations(&self) -> Result<Vec<String>, ServerError>;
+
+    async fn is_name_taken(&self, name: String) -> Result<bool, ServerError>;
+
+    async fn insert_client(&self, client: Client) -> Result<(), ServerError>;
+
+    async fn find_client_by_dilithium_pk(
+        &self,
+        dilithium_pk: Vec<u8>,
+    ) -> Result<Option<Client>, ServerError>;
+
+    async fn find_client_by_id(&self, id: String) -> Result<Option<Client>, ServerError>;
+
+    async fn load_clients(&self) -> Result<Vec<Client>, ServerError>;
+
+    async fn update_client_color(&self, id: String, color: i32) -> Result<(), ServerError>;
+
+    /// Store a message together with its keys, both or nothing.
+    async fn insert_message(
+        &self,
+        message: Message,
+        keys: Vec<MessageKey>,
+    ) -> Result<(), ServerError>;
+
+    async fn find_message(&self, id: String) -> Result<Option<Message>, ServerError>;
+
+    /// Drop a message with the edits and reactions that target it, keys and
+    /// receipts included.
+    async fn delete_message(&self, id: String) -> Result<Purged, ServerError>;
+
+    /// Newest messages delivered to `receiver_id` that have not expired at
+    /// `now`, newest first.
+    async fn load_history(
+        &self,
+        receiver_id: String,
+        amount: i64,
+        now: i64,
+    ) -> Result<Vec<(Message, MessageKey)>, ServerError>;
+
+    /// Messages waiting in the offline queue of `receiver_id` that have not
+    /// expired at `now`, oldest first.
+    async fn load_pending(
+        &self,
+        receiver_id: String,
+        amount: i64,
+        now: i64,
+    ) -> Result<Vec<(Message, MessageKey)>, ServerError>;
+
+    async fn count_pending(&self, receiver_id: String, now: i64) -> Result<i64, ServerError>;
+
+    /// Take delivered messages out of the offline queue of `receiver_id`.
+    async fn clear_pending(
+        &self,
+        receiver_id: String,
+        msg_ids: Vec<String>,
+    ) -> Result<usize, ServerError>;
+
+    /// Drop messages whose ttl ran out at `now`, keys and receipts included.
+    async fn delete_expired_messages(&self, now: i64) -> Result<Purged, ServerError>;
+
+    /// Drop messages sent before `before`, keys and receipts included.
+    async fn delete_messages_before(&self, before: i64) -> Result<Purged, ServerError>;
+
+    /// Keep the newest `keep` keys of every recipient, messages left without
+    /// any key are dropped as well.
+    async fn trim_history(&self, keep: i64) -> Result<Purged, ServerError>;
+
+    /// Mark a message delivered to `receiver_id`, and read if `read` is set.
+    ///
+    /// Only recipients other than the sender count. Returns the sender and
+    /// the new counts, or `None` when nothing changed.
+    async fn record_receipt(
+        &self,
+        msg_id: String,
+        receiver_id: String,
+        read: bool,
+        now: i64,
+    ) -> Result<Option<(String, ReceiptCounts)>, ServerError>;
+
+    /// Receipt counts of the given messages, unknown ids are skipped.
+    async fn load_receipt_counts(
+        &self,
+        msg_ids: Vec<String>,
+    ) -> Result<Vec<ReceiptCounts>, ServerError>;
+}
+
+/// Open the configured backend, must run before the first query.
+pub fn init_storage() -> Result<(), ServerError> {
+    let storage: Box<dyn Storage> = match get_database_backend() {
+        DatabaseBackend::Sqlite => Box::new(SqliteStorage::connect(
+            &get_database_path(),
+            get_database_pool_size(),
+        )?),
+        DatabaseBackend::Postgres => {
+            let url = get_database_url().ok_or_else(|| {
+                ServerError::Internal(anyhow::anyhow!("postgres 后端需要配置 database_url"))
+            })?;
+            Box::new(PostgresStorage::connect(&url, get_database_pool_size())?)
+        }
+    };
+    STORAGE
+        .set(storage)
+        .map_err(|_| ServerError::Internal(anyhow::anyhow!("Storage already initialized")))
+}
+
+pub fn storage() -> Result<&'static dyn Storage, ServerError> {
+    STORAGE
+        .get()
+        .map(|storage| storage.as_ref())
+        .ok_or_else(|| ServerError::Internal(anyhow::anyhow!("Storage not initialized")))
+}
+
+/// Run blocking diesel work on a pooled connection without stalling the runtime.
+async fn with_connection<C, F, T>(pool: &Pool<ConnectionManager<C>>, f: F) -> Result<T, ServerError>
+where
+    C: R2D2Connection + 'static,
+    F: FnOnce(&mut C) -> Result<T, ServerError> + Send + 'static,
+    T: Send + 'static,
+{
+    let pool = pool.clone();
+    tokio::task::spawn_blocking(move || {
+        let mut conn = pool.get()?;
+        f(&mut conn)
+    })
+    .await
+    .map_err(|e| ServerError::Internal(e.into()))?
+}
+
+/// Apply `migrations` on `conn`.
+///
+/// A database that already contains migrations this binary does not know
+/// about was written by a newer server, so it is left untouched.
+fn apply_migrations<DB: Backend>(
+    conn: &mut impl MigrationHarness<DB>,
+    migrations: EmbeddedMigrations,
+) -> Result<Vec<String>, ServerError> {
+    let known = MigrationSource::<DB>::migrations(&migrations)
+        .map_err(|e| ServerError::Migration(anyhow::anyhow!(e)))?
+        .iter()
+        .map(|migration| migration.name().version().as_owned())
+        .collect::<Vec<_>>();
+    let unknown = conn
+        .applied_migrations()
+        .map_err(|e| ServerError::Migration(anyhow::anyhow!(e)))?
+        .into_iter()
+        .filter(|version| !known.contains(version))
+        .map(|version| version.to_string())
+        .collect::<Vec<_>>();
+    if !unknown.is_empty() {
+        return Err(ServerError::Migration(anyhow::anyhow!(
+            "数据库结构比当前服务器更新, 未知迁移: {}",
+            unknown.join(", ")
+        )));
+    }
+
+    let applied = conn
+        .run_pending_migrations(migrations)
+        .map_err(|e| ServerError::Migration(anyhow::anyhow!(e)))?;
+    Ok(applied.iter().map(|version| version.to_string()).collect())
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use std::path::PathBuf;
+
+    use orwell::pb::orwell::MessageAction;
+    use uuid::Uuid;
+
+    use super::*;
+
+    const NOW: i64 = 1_000;
+
+    /// Fresh migrated database in the temp dir, remove it with [`remove_sqlite`].
+    pub(crate) async fn temp_sqlite() -> (SqliteStorage, PathBuf) {
+        let path = std::env::temp_dir().join(format!("orwell-test-{}.db", Uuid::now_v7()));
+        let storage = SqliteStorage::connect(path.to_str().unwrap(), 2).unwrap();
+        storage.run_migrations().await.unwrap();
+        (storage, path)
+    }
+
+    pub(crate) fn remove_sqlite(storage: SqliteStorage, path: PathBuf) {
+        drop(storage);
+        for suffix in ["", "-wal", "-shm"] {
+            let _ = std::fs::remove_file(format!("{}{}", path.display(), suffix));
+        }
+    }
+
+    /// Message with one key for every receiver.
+    pub(crate) fn shared_message(timestamp: i64, receivers: &[&str]) -> (Message, Vec<MessageKey>) {
+        let message = Message {
+            id_: Uuid::now_v7().to_string(),
+            sender_id_: "sender".to_string(),
+            data_: timestamp.to_be_bytes().to_vec(),
+            timestamp_: timestamp,
+            expires_at_: None,
+            action_: 0,
+            target_id_: None,
+        };
+        let keys = receivers
+            .iter()
+            .map(|receiver_id| MessageKey {
+                id_: Uuid::now_v7().to_string(),
+                msg_id_: message.id_.clone(),
+                receiver_id_: receiver_id.to_string(),
+                data_: vec![2; 8],
+                pending_: false,
+            })
+            .collect();
+        (message, keys)
+    }
+
+    pub(crate) async fn history_timestamps(
+        storage: &dyn Storage,
+        receiver_id: &str,
+        now: i64,
+    ) -> Vec<i64> {
+        storage
+            .load_history(receiver_id.to_string(), 100, now)
+            .await
+            .unwrap()
+            .iter()
+            .map(|(message, _)| message.timestamp_)
+            .collect()
+    }
+
+    fn test_client(name: &str) -> Client {
+        Client {
+            id_: Uuid::now_v7().to_string(),
+            name_: name.to_string(),
+            kyber_pk_: vec![1; 8],
+            dilithium_pk_: Uuid::now_v7().as_bytes().to_vec(),
+            online_time_: 0,
+            color_: 0xffffff,
+        }
+    }
+
+    fn test_message(sender_id: &str, receiver_id: &str, timestamp: i64) -> (Message, MessageKey) {
+        let message = Message {
+            id_: Uuid::now_v7().to_string(),
+            sender_id_: sender_id.to_string(),
+            data_: timestamp.to_be_bytes().to_vec(),
+            timestamp_: timestamp,
+            expires_at_: None,
+            action_: 0,
+            target_id_: None,
+        };
+        let key = MessageKey {
+            id_: Uuid::now_v7().to_string(),
+            msg_id_: message.id_.clone(),
+            receiver_id_: receiver_id.to_string(),
+            data_: vec![2; 8],
+            pending_: false,
+        };
+        (message, key)
+    }
+
+    /// Same expectations for every backend, names are unique so a shared
+    /// database can be reused between runs.
+    async fn exercise(storage: &dyn Storage) {
+        storage.run_migrations().await.unwrap();
+        assert!(storage.run_migrations().await.unwrap().is_empty());
+
+        let name = format!("alice-{}", Uuid::now_v7());
+        let alice = test_client(&name);
+        let bob = test_client(&format!("bob-{}", Uuid::now_v7()));
+        assert!(!storage.is_name_taken(name.clone()).await.unwrap());
+        storage.insert_client(alice.clone()).await.unwrap();
+        storage.insert_client(bob.clone()).await.unwrap();
+        assert!(storage.is_name_taken(name).await.unwrap());
+
+        let found = storage
+            .find_client_by_dilithium_pk(alice.dilithium_pk_.clone())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(found.name_, alice.name_);
+        assert_eq!(found.kyber_pk_, alice.kyber_pk_);
+        assert!(storage
+            .find_client_by_dilithium_pk(vec![0; 4])
+            .await
+            .unwrap()
+            .is_none());
+
+        storage
+            .update_client_color(bob.id_.clone(), 0x123456)
+            .await
+            .unwrap();
+        let bob_row = storage
+            .find_client_by_id(bob.id_.clone())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(bob_row.color_, 0x123456);
+        let all = storage.load_clients().await.unwrap();
+        assert!(all.contains(&alice) && all.contains(&bob));
+
+        for timestamp in [100, 300, 200] {
+            let (message, key) = test_message(&alice.id_, &bob.id_, timestamp);
+            storage.insert_message(message, vec![key]).await.unwrap();
+        }
+        let (message, key) = test_message(&bob.id_, &alice.id_, 400);
+        storage.insert_message(message, vec![key]).await.unwrap();
+
+        let history = storage.load_history(bob.id_.clone(), 2, NOW).await.unwrap();
+        let timestamps = history
+            .iter()
+            .map(|(message, _)| message.timestamp_)
+            .collect::<Vec<_>>();
+        assert_eq!(timestamps, vec![300, 200]);
+        assert!(history
+            .iter()
+            .all(|(message, key)| key.msg_id_ == message.id_ && key.receiver_id_ == bob.id_));
+        assert_eq!(history[0].0.data_, 300i64.to_be_bytes().to_vec());
+
+        // duplicate key ids make the second insert fail, the message must not stay behind
+        let (message, key) = test_message(&alice.id_, &bob.id_, 500);
+        let message_id = message.id_.clone();
+        let duplicate = MessageKey {
+            id_: key.id_.clone(),
+            msg_id_: key.msg_id_.clone(),
+            receiver_id_: key.receiver_id_.clone(),
+            data_: key.data_.clone(),
+            pending_: key.pending_,
+        };
+        assert!(storage
+            .insert_message(message, vec![key, duplicate])
+            .await
+            .is_err());
+        let history = storage
+            .load_history(bob.id_.clone(), 10, NOW)
+            .await
+            .unwrap();
+        assert_eq!(history.len(), 3);
+        assert!(history.iter().all(|(message, _)| message.id_ != message_id));
+
+        let target = history[0].0.id_.clone();
+        let found = storage.find_message(target.clone()).await.unwrap().unwrap();
+        assert_eq!(found.sender_id_, alice.id_);
+        assert_eq!(
+            storage.delete_message(target.clone()).await.unwrap(),
+            Purged {
+                messages: 1,
+                keys: 1
+            }
+        );
+        assert!(storage.find_message(target).await.unwrap().is_none());
+        assert_eq!(
+            storage
+                .load_history(bob.id_.clone(), 10, NOW)
+                .await
+                .unwrap()
+                .len(),
+            2
+        );
+    }
+
+    async fn exercise_edit_history(storage: &dyn Storage) {
+        let ivan = Uuid::now_v7().to_string();
+        let row = |data: &str, action: MessageAction, target_id: Option<&str>| {
+            let (mut message, keys) = shared_message(1000, &[&ivan]);
+            message.data_ = data.as_bytes().to_vec();
+            message.action_ = action as i32;
+            message.target_id_ = target_id.map(str::to_string);
+            (message, keys)
+        };
+
+        let (post, keys) = row("old text", MessageAction::Post, None);
+        let target = post.id_.clone();
+        storage.insert_message(post, keys).await.unwrap();
+        for (data, action) in [
+            ("old text, edited", MessageAction::Edit),
+            ("+1", MessageAction::React),
+            ("deleted", MessageAction::Delete),
+        ] {
+            let (message, keys) = row(data, action, Some(&target));
+            storage.insert_message(message, keys).await.unwrap();
+        }
+
+        // the delete tombstone stays so clients loading history drop the line
+        assert_eq!(
+            storage.delete_message(target).await.unwrap(),
+            Purged {
+                messages: 3,
+                keys: 3
+            }
+        );
+        let history = storage.load_history(ivan, 10, NOW).await.unwrap();
+        assert_eq!(history.len(), 1);
+        assert_eq!(history[0].0.data_, b"deleted");
+        assert!(!history
+            .iter()
+            .any(|(message, _)| message.data_.starts_with(b"old text")));
+    }
+
+    async fn exercise_retention(storage: &dyn Storage) {
+        // retention covers the whole table, start from an empty one
+        storage.delete_messages_before(i64::MAX).await.unwrap();
+        let carol = Uuid::now_v7().to_string();
+        let dave = Uuid::now_v7().to_string();
+        for timestamp in [1000, 2000, 3000, 4000] {
+            let (message, keys) = shared_message(timestamp, &[&dave]);
+            storage.insert_message(message, keys).await.unwrap();
+        }
+        let (message, keys) = shared_message(2500, &[&dave, &carol]);
+        storage.insert_message(message, keys).await.unwrap();
+        let (mut message, keys) = shared_message(1500, &[&dave]);
+        message.expires_at_ = Some(1600);
+        storage.insert_message(message, keys).await.unwrap();
+
+        assert!(history_timestamps(storage, &dave, 1550)
+            .await
+            .contains(&1500));
+        assert!(!history_timestamps(storage, &dave, 1600)
+            .await
+            .contains(&1500));
+        let purged = storage.delete_expired_messages(1700).await.unwrap();
+        assert!(purged.messages >= 1 && purged.keys >= 1);
+
+        let purged = storage.delete_messages_before(1500).await.unwrap();
+        assert_eq!(
+            purged,
+            Purged {
+                messages: 1,
+                keys: 1
+            }
+        );
+        assert_eq!(
+            history_timestamps(storage, &dave, NOW).await,
+            vec![4000, 3000, 2500, 2000]
+        );
+
+        // 2500 is still readable by carol, 2000 has no key left
+        let purged = storage.trim_history(2).await.unwrap();
+        assert_eq!(
+            purged,
+            Purged {
+                messages: 1,
+                keys: 2
+            }
+        );
+        assert_eq!(
+            history_timestamps(storage, &dave, NOW).await,
+            vec![4000, 3000]
+        );
+        assert_eq!(history_timestamps(storage, &carol, NOW).await, vec![2500]);
+    }
+
+    async fn exercise_receipts(storage: &dyn Storage) {
+        let erin = Uuid::now_v7().to_string();
+        let frank = Uuid::now_v7().to_string();
+        // the sender keeps a key to its own message but is no recipient
+        let (message, keys) = shared_message(1000, &["sender", &erin, &frank]);
+        let msg_id = message.id_.clone();
+        storage.insert_message(message, keys).await.unwrap();
+        let counts = |recipients, delivered, read| ReceiptCounts {
+            msg_id: msg_id.clone(),
+            recipients,
+            delivered,
+            read,
+        };
+
+        let (sender, delivered) = storage
+            .record_receipt(msg_id.clone(), erin.clone(), false, NOW)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(sender, "sender");
+        assert_eq!(delivered, counts(2, 1, 0));
+        assert!(storage
+            .record_receipt(msg_id.clone(), erin.clone(), false, NOW)
+            .await
+            .unwrap()
+            .is_none());
+        // reading implies delivery
+        let (_, read) = storage
+            .record_receipt(msg_id.clone(), frank.clone(), true, NOW)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(read, counts(2, 2, 1));
+        let (_, read) = storage
+            .record_receipt(msg_id.clone(), erin.clone(), true, NOW)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(read, counts(2, 2, 2));
+
+        for outsider in ["sender".to_string(), Uuid::now_v7().to_string()] {
+            assert!(storage
+                .record_receipt(msg_id.clone(), outsider, true, NOW)
+                .await
+                .unwrap()
+                .is_none());
+        }
+        assert_eq!(
+            storage
+                .load_receipt_counts(vec![msg_id.clone(), "missing".to_string()])
+                .await
+                .unwrap(),
+            vec![counts(2, 2, 2)]
+        );
+
+        // racing receipts for the same recipient must not hit the primary key
+        let (message, keys) = shared_message(1500, &[&erin]);
+        let raced = message.id_.clone();
+        storage.insert_message(message, keys).await.unwrap();
+        let (first, second) = tokio::join!(
+            storage.record_receipt(raced.clone(), erin.clone(), false, NOW),
+            storage.record_receipt(raced.clone(), erin.clone(), false, NOW),
+        );
+        assert_eq!(
+            [first.unwrap(), second.unwrap()]
+                .iter()
+                .filter(|changed| changed.is_some())
+                .count(),
+            1
+        );
+
+        // receipts leave with their message and do not leak into a new one
+        storage.delete_messages_before(2000).await.unwrap();
+        let (mut message, mut keys) = shared_message(3000, &[&erin]);
+        message.id_ = msg_id.clone();
+        keys[0].msg_id_ = msg_id.clone();
+        storage.insert_message(message, keys).await.unwrap();
+        assert_eq!(
+            storage
+                .load_receipt_counts(vec![msg_id.clone()])
+                .await
+                .unwrap(),
+            vec![counts(1, 0, 0)]
+        );
+        storage.delete_message(msg_id.clone()).await.unwrap();
+    }
+
+    async fn exercise_offline_queue(storage: &dyn Storage) {
+        let grace = Uuid::now_v7().to_string();
+        let heidi = Uuid::now_v7().to_string();
+        for timestamp in [3000, 1000, 2000] {
+            let (message, mut keys) = shared_message(timestamp, &[&grace, &heidi]);
+            // heidi was online, grace gets the message on the next login
+            keys[0].pending_ = true;
+            storage.insert_message(message, keys).await.unwrap();
+        }
+        let (mut message, mut keys) = shared_message(1500, &[&grace]);
+        message.expires_at_ = Some(1600);
+        keys[0].pending_ = true;
+        storage.insert_message(message, keys).await.unwrap();
+
+        assert_eq!(storage.count_pending(grace.clone(), NOW).await.unwrap(), 4);
+        assert_eq!(storage.count_pending(grace.clone(), 1700).await.unwrap(), 3);
+        assert_eq!(storage.count_pending(heidi.clone(), NOW).await.unwrap(), 0);
+        assert!(history_timestamps(storage, &grace, NOW).await.is_empty());
+        assert_eq!(
+            history_timestamps(storage, &heidi, NOW).await,
+            vec![3000, 2000, 1000]
+        );
+
+        let first = storage
+            .load_pending(grace.clone(), 2, 1700)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|(message, _)| (message.timestamp_, message.id_))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            first
+                .iter()
+                .map(|(timestamp, _)| *timestamp)
+                .collect::<Vec<_>>(),
+            vec![1000, 2000]
+        );
+        let first = first.into_iter().map(|(_, id)| id).collect::<Vec<_>>();
+        assert_eq!(
+            storage.clear_pending(grace.clone(), first).await.unwrap(),
+            2
+        );
+
+        // delivered messages move to the history, heidi's keys stay untouched
+        assert_eq!(storage.count_pending(grace.clone(), 1700).await.unwrap(), 1);
+        assert_eq!(
+            history_timestamps(storage, &grace, 1700).await,
+            vec![2000, 1000]
+        );
+        assert_eq!(
+            history_timestamps(storage, &heidi, NOW).await,
+            vec![3000, 2000, 1000]
+        );
+    }
+
+    #[tokio::test]
+    async fn sqlite_storage() {
+        let (storage, path) = temp_sqlite().await;
+        exercise(&storage).await;
+        exercise_edit_history(&storage).await;
+        exercise_retention(&storage).await;
+        exercise_receipts(&storage).await;
+        exercise_offline_queue(&storage).await;
+        remove_sqlite(storage, path);
+    }
+
+    /// Needs a local Postgres:
+    /// `ORWELL_TEST_POSTGRES_URL=postgres://localhost/orwell_test cargo test -- --ignored`
+    #[tokio::test]
+    #[ignore]
+    async fn postgres_storage() {
+        let url = std::env::var("ORWELL_TEST_POSTGRES_URL")
+            .expect("ORWELL_TEST_POSTGRES_URL must point to a test database");
+        let storage = PostgresStorage::connect(&url, 2).unwrap();
+        exercise(&storage).await;
+        exercise_edit_history(&storage).await;
+        exercise_retention(&storage).await;
+        exercise_receipts(&storage).await;
+        exercise_offline_queue(&storage).await;
+    }
+}
@@ -0,0 +1,44 @@
+use std::time::Duration;
+
+use diesel::{
+    prelude::*,
+    r2d2::{ConnectionManager, Pool},
+};
+use diesel_migrations::{embed_migrations, EmbeddedMigrations};
+
+use crate::error::ServerError;
+
+/// Keys ranked per recipient, newest first, everything past the limit goes.
+const TRIM_KEYS: &str = "DELETE FROM message_keys_ WHERE id_ IN (
+    SELECT id_ FROM (
+        SELECT message_keys_.id_ AS id_, ROW_NUMBER() OVER (
+            PARTITION BY message_keys_.receiver_id_
+            ORDER BY messages_.timestamp_ DESC, messages_.id_ DESC
+        ) AS rank_
+        FROM message_keys_ INNER JOIN messages_ ON messages_.id_ = message_keys_.msg_id_
+    ) ranked WHERE rank_ > $1
+)";
+
+pub const MIGRATIONS: EmbeddedMigrations = embed_migrations!("migrations/postgres");
+
+pub struct PostgresStorage {
+    pool: Pool<ConnectionManager<PgConnection>>,
+}
+
+impl PostgresStorage {
+    pub fn connect(url: &str, pool_size: u32) -> Result<Self, ServerError> {
+        let pool = Pool::builder()
+            .max_size(pool_size)
+            .connection_timeout(Duration::from_secs(10))
+            .build(ConnectionManager::new(url))?;
+        Ok(Self { pool })
+    }
+}
+
+diesel_storage!(
+    PostgresStorage,
+    PgConnection,
+    transaction,
+    MIGRATIONS,
+    TRIM_KEYS
+);
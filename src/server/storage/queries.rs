@@ -0,0 +1,433 @@
+/// Implements [`Storage`](super::Storage) for a diesel backend. The queries
+/// are the same for every database, only the connection, the kind of
+/// transaction, the migrations and the raw SQL of each backend differ.
+macro_rules! diesel_storage {
+    ($storage:ident, $conn:ty, $transaction:ident, $migrations:ident, $trim_keys:ident) => {
+        mod queries {
+            use std::collections::HashMap;
+
+            use async_trait::async_trait;
+            use diesel::{
+                dsl::{count, count_star},
+                insert_into,
+                prelude::*,
+                sql_types::BigInt,
+            };
+            use orwell::{
+                pb::orwell::MessageAction,
+                schema::{clients_, message_keys_, message_receipts_, messages_},
+            };
+
+            use crate::{
+                client::Client,
+                error::ServerError,
+                message::{Message, MessageKey, Receipt, ReceiptCounts},
+                storage::{apply_migrations, with_connection, Purged, Storage},
+            };
+
+            /// Recipients, deliveries and reads of `msg_ids`, the sender's own key not counted.
+            fn receipt_counts(
+                conn: &mut $conn,
+                msg_ids: &[String],
+            ) -> Result<Vec<ReceiptCounts>, ServerError> {
+                let recipients = message_keys_::table
+                    .inner_join(messages_::table.on(messages_::id_.eq(message_keys_::msg_id_)))
+                    .filter(message_keys_::msg_id_.eq_any(msg_ids))
+                    .filter(message_keys_::receiver_id_.ne(messages_::sender_id_))
+                    .group_by(message_keys_::msg_id_)
+                    .select((message_keys_::msg_id_, count_star()))
+                    .load::<(String, i64)>(conn)?;
+                let receipts = message_receipts_::table
+                    .filter(message_receipts_::msg_id_.eq_any(msg_ids))
+                    .group_by(message_receipts_::msg_id_)
+                    .select((
+                        message_receipts_::msg_id_,
+                        count_star(),
+                        count(message_receipts_::read_at_),
+                    ))
+                    .load::<(String, i64, i64)>(conn)?
+                    .into_iter()
+                    .map(|(msg_id, delivered, read)| (msg_id, (delivered, read)))
+                    .collect::<HashMap<_, _>>();
+                Ok(recipients
+                    .into_iter()
+                    .map(|(msg_id, recipients)| {
+                        let (delivered, read) = receipts.get(&msg_id).copied().unwrap_or_default();
+                        // trimmed keys can leave receipts of former recipients behind
+                        ReceiptCounts {
+                            msg_id,
+                            recipients,
+                            delivered: delivered.min(recipients),
+                            read: read.min(recipients),
+                        }
+                    })
+                    .collect())
+            }
+
+            #[async_trait]
+            impl Storage for super::$storage {
+                async fn run_migrations(&self) -> Result<Vec<String>, ServerError> {
+                    with_connection(&self.pool, |conn| {
+                        apply_migrations(conn, super::$migrations)
+                    })
+                    .await
+                }
+
+                async fn is_name_taken(&self, name: String) -> Result<bool, ServerError> {
+                    with_connection(&self.pool, move |conn| {
+                        Ok(clients_::table
+                            .filter(clients_::name_.eq(name))
+                            .first::<Client>(conn)
+                            .optional()?
+                            .is_some())
+                    })
+                    .await
+                }
+
+                async fn insert_client(&self, client: Client) -> Result<(), ServerError> {
+                    with_connection(&self.pool, move |conn| {
+                        insert_into(clients_::table).values(client).execute(conn)?;
+                        Ok(())
+                    })
+                    .await
+                }
+
+                async fn find_client_by_dilithium_pk(
+                    &self,
+                    dilithium_pk: Vec<u8>,
+                ) -> Result<Option<Client>, ServerError> {
+                    with_connection(&self.pool, move |conn| {
+                        Ok(clients_::table
+                            .filter(clients_::dilithium_pk_.eq(dilithium_pk))
+                            .first::<Client>(conn)
+                            .optional()?)
+                    })
+                    .await
+                }
+
+                async fn find_client_by_id(
+                    &self,
+                    id: String,
+                ) -> Result<Option<Client>, ServerError> {
+                    with_connection(&self.pool, move |conn| {
+                        Ok(clients_::table
+                            .filter(clients_::id_.eq(id))
+                            .first::<Client>(conn)
+                            .optional()?)
+                    })
+                    .await
+                }
+
+                async fn load_clients(&self) -> Result<Vec<Client>, ServerError> {
+                    with_connection(&self.pool, |conn| Ok(clients_::table.load::<Client>(conn)?))
+                        .await
+                }
+
+                async fn update_client_color(
+                    &self,
+                    id: String,
+                    color: i32,
+                ) -> Result<(), ServerError> {
+                    with_connection(&self.pool, move |conn| {
+                        diesel::update(clients_::table)
+                            .filter(clients_::id_.eq(id))
+                            .set(clients_::color_.eq(color))
+                            .execute(conn)?;
+                        Ok(())
+                    })
+                    .await
+                }
+
+                async fn insert_message(
+                    &self,
+                    message: Message,
+                    keys: Vec<MessageKey>,
+                ) -> Result<(), ServerError> {
+                    with_connection(&self.pool, move |conn| {
+                        conn.$transaction(|conn| {
+                            insert_into(messages_::table)
+                                .values(message)
+                                .execute(conn)?;
+                            insert_into(message_keys_::table)
+                                .values(keys)
+                                .execute(conn)?;
+                            Ok(())
+                        })
+                    })
+                    .await
+                }
+
+                async fn find_message(&self, id: String) -> Result<Option<Message>, ServerError> {
+                    with_connection(&self.pool, move |conn| {
+                        Ok(messages_::table
+                            .filter(messages_::id_.eq(id))
+                            .first::<Message>(conn)
+                            .optional()?)
+                    })
+                    .await
+                }
+
+                async fn delete_message(&self, id: String) -> Result<Purged, ServerError> {
+                    with_connection(&self.pool, move |conn| {
+                        conn.$transaction(|conn| {
+                            // edits and reactions would keep the content around
+                            let mut ids = messages_::table
+                                .filter(messages_::target_id_.eq(&id))
+                                .filter(messages_::action_.eq_any([
+                                    MessageAction::Edit as i32,
+                                    MessageAction::React as i32,
+                                ]))
+                                .select(messages_::id_)
+                                .load::<String>(conn)?;
+                            ids.push(id);
+                            diesel::delete(
+                                message_receipts_::table
+                                    .filter(message_receipts_::msg_id_.eq_any(&ids)),
+                            )
+                            .execute(conn)?;
+                            let keys = diesel::delete(
+                                message_keys_::table.filter(message_keys_::msg_id_.eq_any(&ids)),
+                            )
+                            .execute(conn)?;
+                            let messages = diesel::delete(
+                                messages_::table.filter(messages_::id_.eq_any(&ids)),
+                            )
+                            .execute(conn)?;
+                            Ok(Purged { messages, keys })
+                        })
+                    })
+                    .await
+                }
+
+                async fn load_history(
+                    &self,
+                    receiver_id: String,
+                    amount: i64,
+                    now: i64,
+                ) -> Result<Vec<(Message, MessageKey)>, ServerError> {
+                    with_connection(&self.pool, move |conn| {
+                        Ok(messages_::table
+                            .inner_join(
+                                message_keys_::table.on(message_keys_::msg_id_.eq(messages_::id_)),
+                            )
+                            .filter(message_keys_::receiver_id_.eq(receiver_id))
+                            .filter(message_keys_::pending_.eq(false))
+                            .filter(
+                                messages_::expires_at_
+                                    .is_null()
+                                    .or(messages_::expires_at_.gt(now)),
+                            )
+                            .order(messages_::timestamp_.desc())
+                            .limit(amount)
+                            .load::<(Message, MessageKey)>(conn)?)
+                    })
+                    .await
+                }
+
+                async fn load_pending(
+                    &self,
+                    receiver_id: String,
+                    amount: i64,
+                    now: i64,
+                ) -> Result<Vec<(Message, MessageKey)>, ServerError> {
+                    with_connection(&self.pool, move |conn| {
+                        Ok(messages_::table
+                            .inner_join(
+                                message_keys_::table.on(message_keys_::msg_id_.eq(messages_::id_)),
+                            )
+                            .filter(message_keys_::receiver_id_.eq(receiver_id))
+                            .filter(message_keys_::pending_.eq(true))
+                            .filter(
+                                messages_::expires_at_
+                                    .is_null()
+                                    .or(messages_::expires_at_.gt(now)),
+                            )
+                            .order((messages_::timestamp_.asc(), messages_::id_.asc()))
+                            .limit(amount)
+                            .load::<(Message, MessageKey)>(conn)?)
+                    })
+                    .await
+                }
+
+                async fn count_pending(
+                    &self,
+                    receiver_id: String,
+                    now: i64,
+                ) -> Result<i64, ServerError> {
+                    with_connection(&self.pool, move |conn| {
+                        Ok(messages_::table
+                            .inner_join(
+                                message_keys_::table.on(message_keys_::msg_id_.eq(messages_::id_)),
+                            )
+                            .filter(message_keys_::receiver_id_.eq(receiver_id))
+                            .filter(message_keys_::pending_.eq(true))
+                            .filter(
+                                messages_::expires_at_
+                                    .is_null()
+                                    .or(messages_::expires_at_.gt(now)),
+                            )
+                            .count()
+                            .get_result::<i64>(conn)?)
+                    })
+                    .await
+                }
+
+                async fn clear_pending(
+                    &self,
+                    receiver_id: String,
+                    msg_ids: Vec<String>,
+                ) -> Result<usize, ServerError> {
+                    with_connection(&self.pool, move |conn| {
+                        Ok(diesel::update(
+                            message_keys_::table
+                                .filter(message_keys_::receiver_id_.eq(receiver_id))
+                                .filter(message_keys_::msg_id_.eq_any(msg_ids)),
+                        )
+                        .set(message_keys_::pending_.eq(false))
+                        .execute(conn)?)
+                    })
+                    .await
+                }
+
+                async fn delete_expired_messages(&self, now: i64) -> Result<Purged, ServerError> {
+                    with_connection(&self.pool, move |conn| {
+                        conn.$transaction(|conn| {
+                            let expired = messages_::table
+                                .filter(messages_::expires_at_.le(now))
+                                .select(messages_::id_);
+                            diesel::delete(
+                                message_receipts_::table
+                                    .filter(message_receipts_::msg_id_.eq_any(expired)),
+                            )
+                            .execute(conn)?;
+                            let keys = diesel::delete(
+                                message_keys_::table.filter(message_keys_::msg_id_.eq_any(expired)),
+                            )
+                            .execute(conn)?;
+                            let messages = diesel::delete(
+                                messages_::table.filter(messages_::expires_at_.le(now)),
+                            )
+                            .execute(conn)?;
+                            Ok(Purged { messages, keys })
+                        })
+                    })
+                    .await
+                }
+
+                async fn delete_messages_before(&self, before: i64) -> Result<Purged, ServerError> {
+                    with_connection(&self.pool, move |conn| {
+                        conn.$transaction(|conn| {
+                            let old = messages_::table.filter(messages_::timestamp_.lt(before));
+                            diesel::delete(message_receipts_::table.filter(
+                                message_receipts_::msg_id_.eq_any(old.select(messages_::id_)),
+                            ))
+                            .execute(conn)?;
+                            let keys =
+                                diesel::delete(message_keys_::table.filter(
+                                    message_keys_::msg_id_.eq_any(old.select(messages_::id_)),
+                                ))
+                                .execute(conn)?;
+                            let messages = diesel::delete(old).execute(conn)?;
+                            Ok(Purged { messages, keys })
+                        })
+                    })
+                    .await
+                }
+
+                async fn trim_history(&self, keep: i64) -> Result<Purged, ServerError> {
+                    with_connection(&self.pool, move |conn| {
+                        conn.$transaction(|conn| {
+                            let keys = diesel::sql_query(super::$trim_keys)
+                                .bind::<BigInt, _>(keep)
+                                .execute(conn)?;
+                            let messages =
+                                diesel::delete(messages_::table.filter(
+                                    messages_::id_.ne_all(
+                                        message_keys_::table.select(message_keys_::msg_id_),
+                                    ),
+                                ))
+                                .execute(conn)?;
+                            diesel::delete(
+                                message_receipts_::table.filter(
+                                    message_receipts_::msg_id_
+                                        .ne_all(messages_::table.select(messages_::id_)),
+                                ),
+                            )
+                            .execute(conn)?;
+                            Ok(Purged { messages, keys })
+                        })
+                    })
+                    .await
+                }
+
+                async fn record_receipt(
+                    &self,
+                    msg_id: String,
+                    receiver_id: String,
+                    read: bool,
+                    now: i64,
+                ) -> Result<Option<(String, ReceiptCounts)>, ServerError> {
+                    with_connection(&self.pool, move |conn| {
+                        conn.$transaction(|conn| {
+                            let Some(sender_id) = message_keys_::table
+                                .inner_join(
+                                    messages_::table.on(messages_::id_.eq(message_keys_::msg_id_)),
+                                )
+                                .filter(message_keys_::msg_id_.eq(&msg_id))
+                                .filter(message_keys_::receiver_id_.eq(&receiver_id))
+                                .filter(messages_::sender_id_.ne(&receiver_id))
+                                .select(messages_::sender_id_)
+                                .first::<String>(conn)
+                                .optional()?
+                            else {
+                                return Ok(None);
+                            };
+
+                            // one statement, so concurrent receipts cannot both insert
+                            let receipt = Receipt {
+                                msg_id_: msg_id.clone(),
+                                receiver_id_: receiver_id.clone(),
+                                delivered_at_: now,
+                                read_at_: read.then_some(now),
+                            };
+                            let conflict = insert_into(message_receipts_::table)
+                                .values(receipt)
+                                .on_conflict((
+                                    message_receipts_::msg_id_,
+                                    message_receipts_::receiver_id_,
+                                ));
+                            let changed = if read {
+                                let update = conflict
+                                    .do_update()
+                                    .set(message_receipts_::read_at_.eq(now));
+                                // QueryDsl::filter does not apply to an upsert
+                                diesel::query_dsl::methods::FilterDsl::filter(
+                                    update,
+                                    message_receipts_::read_at_.is_null(),
+                                )
+                                .execute(conn)?
+                            } else {
+                                conflict.do_nothing().execute(conn)?
+                            };
+                            if changed == 0 {
+                                return Ok(None);
+                            }
+
+                            Ok(receipt_counts(conn, &[msg_id])?
+                                .pop()
+                                .map(|counts| (sender_id, counts)))
+                        })
+                    })
+                    .await
+                }
+
+                async fn load_receipt_counts(
+                    &self,
+                    msg_ids: Vec<String>,
+                ) -> Result<Vec<ReceiptCounts>, ServerError> {
+                    with_connection(&self.pool, move |conn| receipt_counts(conn, &msg_ids)).await
+                }
+            }
+        }
+    };
+}
@@ -0,0 +1,59 @@
+use std::time::Duration;
+
+use diesel::{
+    connection::SimpleConnection,
+    prelude::*,
+    r2d2::{ConnectionManager, CustomizeConnection, Pool},
+};
+use diesel_migrations::{embed_migrations, EmbeddedMigrations};
+
+use crate::error::ServerError;
+
+/// Keys ranked per recipient, newest first, everything past the limit goes.
+const TRIM_KEYS: &str = "DELETE FROM message_keys_ WHERE id_ IN (
+    SELECT id_ FROM (
+        SELECT message_keys_.id_ AS id_, ROW_NUMBER() OVER (
+            PARTITION BY message_keys_.receiver_id_
+            ORDER BY messages_.timestamp_ DESC, messages_.id_ DESC
+        ) AS rank_
+        FROM message_keys_ INNER JOIN messages_ ON messages_.id_ = message_keys_.msg_id_
+    ) ranked WHERE rank_ > ?
+)";
+
+pub const MIGRATIONS: EmbeddedMigrations = embed_migrations!("migrations/sqlite");
+
+/// Applied to every pooled connection before it is handed out.
+#[derive(Debug)]
+struct SqliteCustomizer;
+
+impl CustomizeConnection<SqliteConnection, diesel::r2d2::Error> for SqliteCustomizer {
+    fn on_acquire(&self, conn: &mut SqliteConnection) -> Result<(), diesel::r2d2::Error> {
+        conn.batch_execute(
+            "PRAGMA busy_timeout = 5000; PRAGMA journal_mode = WAL; PRAGMA synchronous = NORMAL;",
+        )
+        .map_err(diesel::r2d2::Error::QueryError)
+    }
+}
+
+pub struct SqliteStorage {
+    pool: Pool<ConnectionManager<SqliteConnection>>,
+}
+
+impl SqliteStorage {
+    pub fn connect(path: &str, pool_size: u32) -> Result<Self, ServerError> {
+        let pool = Pool::builder()
+            .max_size(pool_size)
+            .connection_timeout(Duration::from_secs(10))
+            .connection_customizer(Box::new(SqliteCustomizer))
+            .build(ConnectionManager::new(path))?;
+        Ok(Self { pool })
+    }
+}
+
+diesel_storage!(
+    SqliteStorage,
+    SqliteConnection,
+    immediate_transaction,
+    MIGRATIONS,
+    TRIM_KEYS
+);
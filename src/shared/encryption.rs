@@ -25,6 +25,8 @@ use std::{
 
 const TIME_LIMIT: u64 = 10000;
 const KYBER1024_CIPHERTEXTBYTES: usize = 1568;
+/// Upper bound of message keys derived for a single out-of-order packet.
+const MAX_SKIP: u64 = 1000;
 
 #[derive(Clone, PartialEq)]
 pub enum RatchetState {
@@ -162,7 +164,10 @@ impl KyberDoubleRatchet {
     }
 
     pub fn step_send_chain(&mut self) -> Result<kyber1024::Ciphertext> {
-        let (shared_secret, ct) = kyber1024_encapsulate(&self.remote_pk.unwrap());
+        let remote_pk = self
+            .remote_pk
+            .ok_or_else(|| anyhow::anyhow!("Session not initialized"))?;
+        let (shared_secret, ct) = kyber1024_encapsulate(&remote_pk);
         let (root_key, send_chain_key) = self.derive_root_key(shared_secret.as_bytes())?;
         self.root_key = root_key;
         self.send_chain_key = send_chain_key;
@@ -201,6 +206,13 @@ impl KyberDoubleRatchet {
     }
 
     pub fn decrypt(&mut self, packet: OrwellRatchetPacket) -> Result<OrwellSignedPacket> {
+        if packet.send_counter.saturating_sub(self.recv_chain_counter) > MAX_SKIP {
+            return Err(anyhow::anyhow!(
+                "Too many skipped messages: {}",
+                packet.send_counter - self.recv_chain_counter
+            ));
+        }
+
         if packet.send_counter > self.recv_chain_counter {
             for i in self.recv_chain_counter..packet.send_counter {
                 let skipped_key = Self::hmac_sha256(
@@ -314,7 +326,7 @@ impl Encryption {
                 "时间戳过期: 当前={}, 数据={}, 差值={}",
                 now_timestamp,
                 data.timestamp,
-                now_timestamp - data.timestamp
+                now_timestamp.abs_diff(data.timestamp)
             ));
         }
 
@@ -2,7 +2,14 @@ use chrono::{DateTime, FixedOffset, Utc};
 use sha2::Sha256;
 use sha3::Digest;
 
-const VERSION: u64 = 1;
+/// Protocol version checked at pre-login, bump it with every change to the
+/// packets, message types or payloads so older peers are turned away
+/// instead of failing on the first message they cannot decode.
+///
+/// 2: disappearing messages, ids with edit and delete, replies, reactions
+///    naming their target, /me, typing, receipts, offline queue and the
+///    markup flag on text
+const VERSION: u64 = 2;
 
 pub fn get_now_timestamp() -> u64 {
     let utc_plus_8 = FixedOffset::east_opt(8 * 3600).unwrap();
@@ -13,11 +20,10 @@ pub fn get_now_timestamp() -> u64 {
 #[macro_export]
 macro_rules! decode_packet {
     ($packet:expr, $packet_type:ty) => {{
-        let decoded = <$packet_type>::decode($packet.data.as_slice());
-        if decoded.is_err() {
-            return Err(anyhow::anyhow!("非法数据"));
+        match <$packet_type>::decode($packet.data.as_slice()) {
+            Ok(decoded) => decoded,
+            Err(e) => return Err(anyhow::Error::new(e).context("非法数据")),
         }
-        decoded.unwrap()
     }};
 }
 